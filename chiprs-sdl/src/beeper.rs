@@ -0,0 +1,130 @@
+use sdl2::AudioSubsystem;
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+
+use chiprs::host::{self, Sample};
+
+const FREQUENCY: f32 = 440.0;
+const VOLUME: f32 = 0.1;
+
+/// A digitized sound being played, resampled to the output rate.
+struct Playback {
+    sample: Sample,
+    pos: f32,
+    step: f32,
+}
+
+impl Playback {
+    fn next(&mut self) -> Option<f32> {
+        let len = self.sample.data.len();
+        if self.pos as usize >= len {
+            if !self.sample.looping || len == 0 {
+                return None;
+            }
+            self.pos %= len as f32;
+        }
+        let value = self.sample.data[self.pos as usize];
+        self.pos += self.step;
+        Some((value as f32 - 128.0) / 128.0 * VOLUME)
+    }
+}
+
+struct Sound {
+    freq: f32,
+    phase_inc: f32,
+    phase: f32,
+    square: bool,
+    playback: Option<Playback>,
+}
+
+impl AudioCallback for Sound {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for x in out.iter_mut() {
+            *x = 0.0;
+            if self.square {
+                *x += if self.phase <= 0.5 { VOLUME } else { -VOLUME };
+                self.phase = (self.phase + self.phase_inc) % 1.0;
+            }
+            if let Some(playback) = self.playback.as_mut() {
+                match playback.next() {
+                    Some(value) => *x += value,
+                    None => self.playback = None,
+                }
+            }
+        }
+    }
+}
+
+/// Plays a square wave while the CHIP-8 sound timer is active, and MegaChip
+/// digitized sounds.
+pub struct Beeper {
+    device: AudioDevice<Sound>,
+    on: bool,
+    // Last sample started.
+    sample: Option<Sample>,
+    paused: bool,
+}
+
+impl Beeper {
+    pub fn new(audio: &AudioSubsystem) -> Result<Beeper, String> {
+        let desired = AudioSpecDesired {
+            freq: Some(44100),
+            channels: Some(1),
+            samples: None,
+        };
+        let device = audio.open_playback(None, &desired, |spec| Sound {
+            freq: spec.freq as f32,
+            phase_inc: FREQUENCY / spec.freq as f32,
+            phase: 0.0,
+            square: false,
+            playback: None,
+        })?;
+        Ok(Beeper { device, on: false, sample: None, paused: false })
+    }
+
+    /// Silences the sound while the emulation is paused, and resumes it
+    /// afterwards.
+    pub fn set_paused(&mut self, paused: bool) {
+        if paused != self.paused {
+            self.paused = paused;
+            self.update();
+        }
+    }
+
+    fn update(&mut self) {
+        if !self.paused && (self.on || self.sample.is_some()) {
+            self.device.resume();
+        } else {
+            self.device.pause();
+        }
+    }
+}
+
+impl host::Beeper for Beeper {
+    fn set(&mut self, on: bool) {
+        if on != self.on {
+            self.device.lock().square = on;
+            self.on = on;
+            self.update();
+        }
+    }
+
+    /// Starts playing a sample, unless it's already playing.
+    fn play(&mut self, sample: Option<&Sample>) {
+        if sample == self.sample.as_ref() {
+            return;
+        }
+        {
+            let mut sound = self.device.lock();
+            let freq = sound.freq;
+            sound.playback = sample.map(|sample| Playback {
+                sample: sample.clone(),
+                pos: 0.0,
+                step: sample.rate as f32 / freq,
+            });
+        }
+        self.sample = sample.cloned();
+        self.update();
+    }
+}
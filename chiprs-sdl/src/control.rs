@@ -0,0 +1,120 @@
+use std::time::{Duration, Instant};
+
+const TIMER_HZ: f64 = 60.0;
+const TURBO_FACTOR: f64 = 4.0;
+const SLOW_FACTOR: f64 = 0.25;
+const SPEED_STEP: f64 = 1.25;
+const MIN_SPEED: u32 = 10;
+const MAX_SPEED: u32 = 1_000_000;
+// How long the overlay stays on screen after a change.
+const OVERLAY_TIME: Duration = Duration::from_secs(2);
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SpeedMode {
+    Normal,
+    Turbo,
+    Slow,
+}
+
+/// Emulation speed and run state, as controlled by the user. Keeps track of
+/// emulated time, so that the emulator can be run in real time, faster or
+/// slower.
+pub struct Control {
+    pub paused: bool,
+    speed: u32,
+    mode: SpeedMode,
+    time: f64,
+    next_tick: f64,
+    next_step: f64,
+    last_change: Option<Instant>,
+}
+
+impl Control {
+    pub fn new(speed: u32, paused: bool) -> Control {
+        Control {
+            paused,
+            speed,
+            mode: SpeedMode::Normal,
+            time: 0.0,
+            next_tick: 0.0,
+            next_step: 0.0,
+            last_change: None,
+        }
+    }
+
+    fn changed(&mut self) {
+        self.last_change = Some(Instant::now());
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        self.changed();
+    }
+
+    pub fn toggle_mode(&mut self, mode: SpeedMode) {
+        self.mode = if self.mode == mode { SpeedMode::Normal } else { mode };
+        self.changed();
+    }
+
+    pub fn faster(&mut self) {
+        self.speed = ((self.speed as f64 * SPEED_STEP) as u32).min(MAX_SPEED);
+        self.changed();
+    }
+
+    pub fn slower(&mut self) {
+        self.speed = ((self.speed as f64 / SPEED_STEP) as u32).max(MIN_SPEED);
+        self.changed();
+    }
+
+    /// Advance emulated time by given number of real seconds, scaled
+    /// according to speed mode.
+    pub fn advance(&mut self, seconds: f64) {
+        self.time += seconds * match self.mode {
+            SpeedMode::Normal => 1.0,
+            SpeedMode::Turbo => TURBO_FACTOR,
+            SpeedMode::Slow => SLOW_FACTOR,
+        };
+    }
+
+    /// Advance emulated time by exactly one frame.
+    pub fn advance_frame(&mut self) {
+        self.time += 1.0 / TIMER_HZ;
+    }
+
+    /// Returns true if a timer tick is due, and schedules the next one.
+    pub fn tick_due(&mut self) -> bool {
+        if self.next_tick <= self.time {
+            self.next_tick += 1.0 / TIMER_HZ;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns true if an instruction is due, and schedules the next one.
+    pub fn step_due(&mut self) -> bool {
+        if self.next_step <= self.time {
+            self.next_step += 1.0 / self.speed as f64;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn show_overlay(&self) -> bool {
+        self.paused || self.mode != SpeedMode::Normal || match self.last_change {
+            Some(t) => t.elapsed() < OVERLAY_TIME,
+            None => false,
+        }
+    }
+
+    pub fn status(&self) -> Vec<String> {
+        let state = match (self.paused, self.mode) {
+            (true, _) => "PAUSED",
+            (false, SpeedMode::Normal) => "RUNNING",
+            (false, SpeedMode::Turbo) => "TURBO X4",
+            (false, SpeedMode::Slow) => "SLOW X1/4",
+        };
+        vec![state.to_string(), format!("{} IPS", self.speed)]
+    }
+}
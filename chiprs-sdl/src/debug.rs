@@ -0,0 +1,185 @@
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::WindowCanvas;
+
+use chiprs::{Chip, V_SIZE};
+
+use crate::text::{draw_text, GLYPH_W, GLYPH_H};
+
+const COLUMN_CHARS: usize = 30;
+const DISASM_BEFORE: usize = 4;
+const DISASM_LINES: usize = 9;
+const MEMORY_ROWS: usize = 8;
+const MEMORY_ROW_BYTES: usize = 8;
+// Number of lines in the taller (right) column.
+const PANEL_LINES: usize = 1 + DISASM_LINES + 1 + 1 + MEMORY_ROWS;
+
+const BACKGROUND: Color = Color { r: 32, g: 32, b: 32, a: 255 };
+const TEXT: Color = Color { r: 200, g: 200, b: 200, a: 255 };
+const HEADER: Color = Color { r: 120, g: 160, b: 255, a: 255 };
+const HIGHLIGHT: Color = Color { r: 255, g: 220, b: 80, a: 255 };
+
+// The keypad, as laid out on the COSMAC VIP.
+const KEYPAD: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF],
+];
+
+/// A panel showing the machine state, drawn next to the game.
+pub struct DebugPanel {
+    pub visible: bool,
+    // Start of the memory view, or None to follow I.
+    memory_start: Option<usize>,
+}
+
+impl DebugPanel {
+    pub fn new() -> DebugPanel {
+        DebugPanel { visible: false, memory_start: None }
+    }
+
+    /// Panel width in pixels, for given text size.
+    pub fn width(size: usize) -> usize {
+        (2 * COLUMN_CHARS + 2) * (GLYPH_W + 1) * size
+    }
+
+    /// Minimum panel height in pixels, for given text size.
+    pub fn height(size: usize) -> usize {
+        (PANEL_LINES * (GLYPH_H + 2) + 4) * size
+    }
+
+    /// Scroll the memory view by given number of rows.
+    pub fn scroll(&mut self, chip: &Chip, rows: isize) {
+        let start = self.memory_start.unwrap_or_else(|| memory_row(chip.i() as usize)) as isize;
+        let max = (chip.memory.bytes.len() - MEMORY_ROWS * MEMORY_ROW_BYTES) as isize;
+        let start = (start + rows * MEMORY_ROW_BYTES as isize).max(0).min(max);
+        self.memory_start = Some(start as usize);
+    }
+
+    /// Make the memory view follow I again.
+    pub fn follow_i(&mut self) {
+        self.memory_start = None;
+    }
+
+    pub fn draw(&self, canvas: &mut WindowCanvas, chip: &Chip,
+                x: usize, height: usize, size: usize) {
+        canvas.set_draw_color(BACKGROUND);
+        canvas.fill_rect(Rect::new(x as i32, 0, DebugPanel::width(size) as u32,
+                                   height as u32)).unwrap();
+
+        let mut left = Column::new(x + char_w(size), size);
+        draw_registers(canvas, &mut left, chip);
+        left.skip();
+        draw_stack(canvas, &mut left, chip);
+        left.skip();
+        draw_keys(canvas, &mut left, chip);
+
+        let mut right = Column::new(x + (COLUMN_CHARS + 1) * char_w(size), size);
+        draw_disassembly(canvas, &mut right, chip);
+        right.skip();
+        let start = self.memory_start.unwrap_or_else(|| memory_row(chip.i() as usize));
+        draw_memory(canvas, &mut right, chip, start);
+    }
+}
+
+struct Column {
+    x: usize,
+    y: usize,
+    size: usize,
+}
+
+impl Column {
+    fn new(x: usize, size: usize) -> Column {
+        Column { x, y: 2 * size, size }
+    }
+
+    fn line(&mut self, canvas: &mut WindowCanvas, color: Color, text: &str) {
+        draw_text(canvas, self.x, self.y, self.size, color, text);
+        self.skip();
+    }
+
+    fn skip(&mut self) {
+        self.y += (GLYPH_H + 2) * self.size;
+    }
+}
+
+fn char_w(size: usize) -> usize {
+    (GLYPH_W + 1) * size
+}
+
+fn memory_row(addr: usize) -> usize {
+    addr - addr % MEMORY_ROW_BYTES
+}
+
+fn draw_registers(canvas: &mut WindowCanvas, col: &mut Column, chip: &Chip) {
+    col.line(canvas, HEADER, "REGISTERS");
+    for row in 0..V_SIZE / 4 {
+        let line: Vec<String> = (row * 4..row * 4 + 4)
+            .map(|i| format!("V{:X} {:02X}", i, chip.v()[i]))
+            .collect();
+        col.line(canvas, TEXT, &line.join("  "));
+    }
+    col.line(canvas, TEXT, &format!("I {:04X}  PC {:04X}  SP {:X}",
+                                    chip.i(), chip.pc(), chip.sp()));
+    col.line(canvas, TEXT, &format!("DT {:02X}    ST {:02X}", chip.dt(), chip.st()));
+}
+
+fn draw_stack(canvas: &mut WindowCanvas, col: &mut Column, chip: &Chip) {
+    col.line(canvas, HEADER, "STACK");
+    let stack = chip.stack();
+    for row in 0..stack.len().div_ceil(4) {
+        let x = col.x;
+        for n in (row * 4..stack.len()).take(4) {
+            let i = n % 4;
+            let color = if n < chip.sp() as usize { HIGHLIGHT } else { TEXT };
+            draw_text(canvas, x + i * 5 * char_w(col.size), col.y, col.size, color,
+                      &format!("{:04X}", stack[n]));
+        }
+        col.skip();
+    }
+}
+
+fn draw_keys(canvas: &mut WindowCanvas, col: &mut Column, chip: &Chip) {
+    col.line(canvas, HEADER, "KEYS");
+    for row in KEYPAD.iter() {
+        for (i, &key) in row.iter().enumerate() {
+            let color = if chip.keys()[key as usize] { HIGHLIGHT } else { TEXT };
+            draw_text(canvas, col.x + i * 2 * char_w(col.size), col.y, col.size, color,
+                      &format!("{:X}", key));
+        }
+        col.skip();
+    }
+}
+
+fn draw_disassembly(canvas: &mut WindowCanvas, col: &mut Column, chip: &Chip) {
+    col.line(canvas, HEADER, "CODE");
+    let pc = chip.pc() as usize;
+    let start = pc.saturating_sub(DISASM_BEFORE * 2);
+    for addr in (start..).step_by(2).take(DISASM_LINES) {
+        if addr + 1 >= chip.memory.bytes.len() {
+            break;
+        }
+        let b = chip.memory.u16_at(addr);
+        let instr = match chip.platform().decode(b) {
+            Some(instr) => instr.to_string(),
+            None => "????".to_string(),
+        };
+        let (color, marker) = if addr == pc { (HIGHLIGHT, '>') } else { (TEXT, ' ') };
+        col.line(canvas, color, &format!("{}{:04X} {:04X} {}", marker, addr, b, instr));
+    }
+}
+
+fn draw_memory(canvas: &mut WindowCanvas, col: &mut Column, chip: &Chip, start: usize) {
+    col.line(canvas, HEADER, "MEMORY");
+    let bytes = &chip.memory.bytes;
+    for row in 0..MEMORY_ROWS {
+        let addr = start + row * MEMORY_ROW_BYTES;
+        if addr >= bytes.len() {
+            break;
+        }
+        let end = (addr + MEMORY_ROW_BYTES).min(bytes.len());
+        let hex: Vec<String> = bytes[addr..end].iter().map(|b| format!("{:02X}", b)).collect();
+        col.line(canvas, TEXT, &format!("{:04X} {}", addr, hex.join(" ")));
+    }
+}
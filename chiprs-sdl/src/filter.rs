@@ -0,0 +1,142 @@
+use std::collections::VecDeque;
+
+use chiprs::display::Display;
+use chiprs::host::FrameSink;
+
+// How much of a pixel's brightness survives each frame after it's cleared.
+const DECAY_FACTOR: f32 = 0.6;
+// Number of frames averaged together in blend mode.
+const BLEND_FRAMES: usize = 3;
+// Number of frames a cleared pixel stays lit in delay mode.
+const CLEAR_DELAY: u8 = 2;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FilterMode {
+    Off,
+    Decay,
+    Blend,
+    DelayClear,
+}
+
+impl FilterMode {
+    pub fn next(self) -> FilterMode {
+        use self::FilterMode::*;
+        match self {
+            Off => Decay,
+            Decay => Blend,
+            Blend => DelayClear,
+            DelayClear => Off,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        use self::FilterMode::*;
+        match self {
+            Off => "off",
+            Decay => "decay",
+            Blend => "blend",
+            DelayClear => "delay-clear",
+        }
+    }
+}
+
+/// Smooths out the flicker caused by games erasing and redrawing sprites.
+/// The filter is fed the display once per frame (as the chip's frame sink),
+/// keeps the last one for drawing, and reports a brightness for each pixel
+/// in range 0.0 - 1.0.
+pub struct RenderFilter {
+    mode: FilterMode,
+    display: Option<Display>,
+    levels: Vec<f32>,
+    history: VecDeque<Vec<u8>>,
+    delays: Vec<u8>,
+}
+
+impl RenderFilter {
+    pub fn new(mode: FilterMode) -> RenderFilter {
+        RenderFilter {
+            mode,
+            display: None,
+            levels: vec![],
+            history: VecDeque::with_capacity(BLEND_FRAMES),
+            delays: vec![],
+        }
+    }
+
+    pub fn mode(&self) -> FilterMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: FilterMode) {
+        let display = self.display.take();
+        *self = RenderFilter::new(mode);
+        self.display = display;
+    }
+
+    /// Last frame received.
+    pub fn display(&self) -> Option<&Display> {
+        self.display.as_ref()
+    }
+
+    fn update(&mut self, display: &Display) {
+        // Start over when the resolution changes.
+        if self.levels.len() != display.pixels.len() {
+            *self = RenderFilter::new(self.mode);
+            self.levels = vec![0.0; display.pixels.len()];
+            self.delays = vec![0; display.pixels.len()];
+        }
+
+        match self.mode {
+            FilterMode::Off => (),
+            FilterMode::Decay => {
+                for (index, level) in self.levels.iter_mut().enumerate() {
+                    if display.pixels[index] != 0 {
+                        *level = 1.0;
+                    } else {
+                        *level *= DECAY_FACTOR;
+                    }
+                }
+            }
+            FilterMode::Blend => {
+                if self.history.len() == BLEND_FRAMES {
+                    self.history.pop_front();
+                }
+                self.history.push_back(display.pixels.to_vec());
+
+                for (index, level) in self.levels.iter_mut().enumerate() {
+                    let lit = self.history.iter().filter(|frame| frame[index] != 0).count();
+                    *level = lit as f32 / self.history.len() as f32;
+                }
+            }
+            FilterMode::DelayClear => {
+                for (index, delay) in self.delays.iter_mut().enumerate() {
+                    if display.pixels[index] != 0 {
+                        *delay = CLEAR_DELAY;
+                    } else if *delay > 0 {
+                        *delay -= 1;
+                    }
+                    self.levels[index] = if *delay > 0 { 1.0 } else { 0.0 };
+                }
+            }
+        }
+    }
+
+    pub fn level(&self, display: &Display, x: usize, y: usize) -> f32 {
+        // Until the next update, the levels can be for a different
+        // resolution.
+        let filtered = || self.levels.get(y * display.width() + x).cloned().unwrap_or(0.0);
+        match self.mode {
+            FilterMode::Blend => filtered(),
+            _ if display.at(x, y) => 1.0,
+            FilterMode::Off => 0.0,
+            _ => filtered(),
+        }
+    }
+}
+
+impl FrameSink for RenderFilter {
+    fn frame(&mut self, display: &Display) {
+        self.update(display);
+        self.display = Some(display.clone());
+    }
+}
@@ -0,0 +1,41 @@
+use chiprs::KEYS_SIZE;
+
+/// Maps CHIP-8 keys to keyboard keys: `keymap[k]` is the (hex) keyboard key
+/// that presses CHIP-8 key `k`.
+///
+/// This is the format of the `.c8k` files in `games/`: 16 hex digits, one
+/// for each CHIP-8 key. For instance, `0123456789ABCDE5` makes key 5 act as
+/// key F.
+pub type Keymap = [u8; KEYS_SIZE];
+
+pub const IDENTITY: Keymap = [
+    0x0, 0x1, 0x2, 0x3, 0x4, 0x5, 0x6, 0x7,
+    0x8, 0x9, 0xA, 0xB, 0xC, 0xD, 0xE, 0xF,
+];
+
+pub fn parse_keymap(s: &str) -> Result<Keymap, String> {
+    let s = s.trim();
+    if s.chars().count() != KEYS_SIZE {
+        return Err(format!("keymap should have {} hex digits, got {:?}", KEYS_SIZE, s));
+    }
+
+    let mut keymap = IDENTITY;
+    for (i, c) in s.chars().enumerate() {
+        match c.to_digit(16) {
+            Some(d) => keymap[i] = d as u8,
+            None => return Err(format!("keymap: {:?} is not a hex digit", c)),
+        }
+    }
+    Ok(keymap)
+}
+
+pub fn load_keymap(filename: &str) -> Result<Keymap, String> {
+    let s = std::fs::read_to_string(filename)
+        .map_err(|e| format!("can't read keymap {}: {}", filename, e))?;
+    parse_keymap(&s).map_err(|e| format!("{}: {}", filename, e))
+}
+
+/// Returns the CHIP-8 keys pressed by given keyboard key.
+pub fn chip_keys(keymap: &Keymap, key: u8) -> impl Iterator<Item=u8> + '_ {
+    (0..KEYS_SIZE as u8).filter(move |&k| keymap[k as usize] == key)
+}
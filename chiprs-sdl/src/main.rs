@@ -2,30 +2,223 @@ extern crate sdl2;
 extern crate chiprs;
 
 mod sdl_interface;
+mod filter;
+mod keymap;
+mod options;
+mod beeper;
+mod control;
+mod text;
+mod debug;
+mod reports;
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::process::exit;
+
+use sdl2::pixels::Color;
 
 use crate::sdl_interface::run_sdl_interface;
+use crate::options::{Options, QuirksOption, parse_args, USAGE};
+use crate::keymap::parse_keymap;
+use crate::reports::{ProfileReport, CoverageReport};
 use chiprs::Chip;
+use chiprs::analysis::Analysis;
+use chiprs::quirks::Quirks;
+use chiprs::hp48;
+use chiprs::octo::{self, Cartridge};
+use chiprs::platform::{self, Platform};
+use chiprs::romdb;
+use chiprs::hook::Hook;
+use chiprs::trace::Tracer;
+use chiprs::vip::Vip;
 
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    match parse_args(&args) {
+        Ok(Some(options)) => {
+            if let Err(e) = run(options) {
+                eprintln!("error: {}", e);
+                exit(1);
+            }
+        }
+        Ok(None) => {
+            println!("{}", USAGE);
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            eprintln!();
+            eprintln!("{}", USAGE);
+            exit(2);
+        }
+    }
+}
+
+fn make_hooks(options: &Options, rom: &[u8]) -> Result<Vec<Box<dyn Hook>>, String> {
+    let mut hooks: Vec<Box<dyn Hook>> = vec![];
 
-    match args.len() {
-        2 => {
-            let filename = &args[1];
-            run(filename);
+    if let Some(ref filename) = options.trace {
+        let out: Box<dyn Write> = if filename == "-" {
+            Box::new(io::stderr())
+        } else {
+            let file = File::create(filename)
+                .map_err(|e| format!("can't create {}: {}", filename, e))?;
+            Box::new(BufWriter::new(file))
+        };
+        let mut tracer = Tracer::new(out, options.trace_filter.clone());
+        if let Some(n) = options.trace_last {
+            tracer = tracer.with_ring_buffer(n);
         }
-        _ => {
-            println!("Usage: {} rom.ch8", args[0]);
+        hooks.push(Box::new(tracer));
+    }
+
+    if let Some(ref filename) = options.profile {
+        hooks.push(Box::new(ProfileReport::new(filename)));
+    }
+
+    if let Some(ref filename) = options.coverage {
+        let memory_size = options.platform().memory_size;
+        hooks.push(Box::new(CoverageReport::new(filename, memory_size, options.addr(), rom)));
+    }
+
+    Ok(hooks)
+}
+
+fn detect_quirks(rom: &[u8], addr: u16) -> Quirks {
+    let recommendation = Analysis::new(rom, addr).recommend_quirks();
+    if recommendation.reasons.is_empty() {
+        eprintln!("quirks: {} (nothing to go by)", recommendation.preset);
+    } else {
+        eprintln!("quirks: {} ({})", recommendation.preset, recommendation.reasons.join(", "));
+    }
+    Quirks::preset(recommendation.preset).unwrap()
+}
+
+/// Fills in options not given on the command line, based on the ROM database.
+fn apply_rom_info(options: &mut Options, rom: &[u8]) -> Result<(), String> {
+    let info = match romdb::lookup(rom) {
+        Some(info) => info,
+        None => return Ok(()),
+    };
+    match info.author {
+        Some(author) => eprintln!("{}, by {}", info.title, author),
+        None => eprintln!("{}", info.title),
+    }
+    if options.platform.is_none() {
+        options.platform = Platform::from_name(info.platform);
+    }
+    if options.speed.is_none() {
+        options.speed = info.speed;
+    }
+    if options.quirks.is_none() {
+        options.quirks = Quirks::preset(info.quirks).map(QuirksOption::Preset);
+    }
+    if options.keymap.is_none() {
+        if let Some(keymap) = info.keymap {
+            options.keymap = Some(parse_keymap(keymap)?);
         }
     }
+    options.rom_info = Some(info);
+    Ok(())
 }
 
-fn run(filename: &str) {
-    let mut chip = Chip::new();
-    chip.memory.load_font();
+/// Fills in options not given on the command line, based on the settings
+/// saved in an Octo cartridge.
+fn apply_cartridge(options: &mut Options, cartridge: &Cartridge) {
+    if options.platform.is_none() {
+        options.platform = Some(cartridge.platform());
+    }
+    if options.speed.is_none() {
+        options.speed = cartridge.speed();
+    }
+    if options.quirks.is_none() {
+        options.quirks = Some(QuirksOption::Preset(cartridge.options.quirks));
+    }
+    options.octo_keyboard = true;
+    if !options.palette {
+        let rgb = |c: u32| Color::RGB((c >> 16) as u8, (c >> 8) as u8, c as u8);
+        if let Some(c) = cartridge.options.background {
+            options.background = rgb(c);
+        }
+        for (color, c) in options.foreground.iter_mut().zip(cartridge.options.foreground.iter()) {
+            if let Some(c) = c {
+                *color = rgb(*c);
+            }
+        }
+    }
+}
+
+fn run(mut options: Options) -> Result<(), String> {
+    let mut rom = fs::read(&options.rom)
+        .map_err(|e| format!("can't load {}: {}", options.rom, e))?;
+    let unwrapped = hp48::unwrap(&rom);
+    let from_hp48 = unwrapped.is_some();
+    if let Some(result) = unwrapped {
+        rom = result.map_err(|e| format!("can't load {}: {}", options.rom, e))?;
+        eprintln!("decoded HP-48 string ({} bytes)", rom.len());
+    }
+    if octo::is_cartridge(&rom) {
+        let cartridge = Cartridge::load(&rom)
+            .map_err(|e| format!("can't load {}: {}", options.rom, e))?;
+        rom = cartridge.rom().map_err(|e| format!("can't load {}: {}", options.rom, e))?;
+        eprintln!("loaded Octo cartridge ({} bytes)", rom.len());
+        apply_cartridge(&mut options, &cartridge);
+    }
+    apply_rom_info(&mut options, &rom)?;
+    if options.platform.is_none() && from_hp48 {
+        options.platform = Some(&platform::CHIP48);
+    }
+    if options.platform.is_none() {
+        options.platform = Platform::detect(&rom);
+        if let Some(platform) = options.platform {
+            eprintln!("detected {}", platform.title);
+        }
+    }
+
+    if options.vip {
+        if options.platform().name != "chip8" {
+            eprintln!("warning: {} is for {}, the VIP only runs CHIP-8",
+                      options.rom, options.platform().title);
+        }
+        options.platform = Some(&platform::CHIP8);
+        return run_vip(&options, &rom);
+    }
+
+    let mut chip = Chip::with_platform(options.platform());
+    if let Some(seed) = options.seed {
+        chip.seed(seed);
+    }
+    chip.memory_policy = options.memory_policy;
+    chip.load_font();
+
+    let addr = options.addr();
+    let loaded = chip.memory.load_rom(&rom, addr)
+        .map_err(|e| format!("can't load {}: {}", options.rom, e))?;
+    for warning in loaded.warnings.iter() {
+        eprintln!("warning: {}: {}", options.rom, warning);
+    }
+    chip.jump(options.entry());
+
+    match options.quirks {
+        Some(QuirksOption::Preset(quirks)) => chip.quirks = quirks,
+        Some(QuirksOption::Auto) => chip.quirks = detect_quirks(&rom, addr as u16),
+        None => (),
+    }
+    let mut hooks = make_hooks(&options, &rom)?;
+    let result = run_sdl_interface(&mut chip, &options, &mut hooks);
+    hooks.finish().map_err(|e| format!("can't write results: {}", e))?;
+    result
+}
 
-    chip.memory.load_program_from_file(0x200, filename).unwrap();
-    chip.jump(0x200);
+/// Runs the ROM on the emulated COSMAC VIP, with the original interpreter.
+fn run_vip(options: &Options, rom: &[u8]) -> Result<(), String> {
+    let read = |filename: &String| fs::read(filename)
+        .map_err(|e| format!("can't load {}: {}", filename, e));
+    let monitor = read(options.monitor.as_ref().unwrap())?;
+    let interpreter = read(options.interpreter.as_ref().unwrap())?;
 
-    run_sdl_interface(&mut chip);
+    let mut vip = Vip::new(&monitor);
+    vip.load(0, &interpreter)?;
+    vip.load(options.addr(), rom).map_err(|e| format!("can't load {}: {}", options.rom, e))?;
+    run_sdl_interface(&mut vip, options, &mut ())
 }
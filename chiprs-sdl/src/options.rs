@@ -0,0 +1,308 @@
+use sdl2::pixels::Color;
+
+use chiprs::memory::{MemoryPolicy, POLICIES};
+use chiprs::platform::{self, Platform};
+use chiprs::quirks::{Quirks, PRESETS};
+use chiprs::romdb::RomInfo;
+use chiprs::trace::TraceFilter;
+
+use crate::keymap::{Keymap, IDENTITY, load_keymap};
+
+pub const USAGE: &str = "\
+Usage: chiprs-sdl [options] rom.ch8
+
+The ROM can also be an HP-48 string, or an Octo cartridge (.gif).
+
+Options:
+  --platform NAME    machine to emulate: chip8 (default), chip48, schip,
+                     xochip, chip8x, chip8e, chip8i, hires (detected for
+                     ROMs starting with 1260) or megachip
+  --addr ADDR        load address (default 0x200, 0x300 for CHIP-8X, 0x600
+                     for ETI-660)
+  --speed HZ         instructions per second (default 5000, 1000 for CHIP-8X,
+                     CHIP-8E, CHIP-8I and hires, 60000 for XO-CHIP and
+                     MegaChip)
+  --quirks PRESET    interpreter quirks: default, vip, chip48, schip, xochip,
+                     or auto (guess based on instructions used by the ROM);
+                     by default, the ones of the platform
+  --memory POLICY    out of bounds memory access: fault (stop with an error,
+                     default), wrap or clamp
+  --keymap FILE      keymap file (.c8k)
+  --palette COLORS   colors as hex RGB: BG,FG or, for XO-CHIP, BG,FG,FG2,BOTH
+                     (default C8C8C8,646464,A06E3C,323232)
+  --scale N          size of CHIP-8 pixel on screen (default 10)
+  --seed N           seed for the random number generator
+  --paused           start paused (press P to resume)
+  --fullscreen       run in fullscreen
+  --mute             disable sound
+  --trace FILE       write instruction trace to FILE (- for stderr)
+  --trace-addr A-B   only trace instructions in address range A-B
+  --trace-instr LIST only trace given instruction kinds, e.g. DRW,CALL
+  --trace-last N     only write out last N instructions, when an error occurs
+  --profile FILE     write execution profile to FILE, and FILE.folded
+  --coverage FILE    write ROM coverage to FILE, and FILE.info (lcov)
+  --vip              run the original interpreter on an emulated COSMAC VIP
+                     instead (CHIP-8 only, no trace, profile or coverage)
+  --monitor FILE     VIP monitor ROM (512 bytes), for --vip
+  --interpreter FILE CHIP-8 interpreter, loaded at 0, for --vip
+  --help             show this message";
+
+pub enum QuirksOption {
+    Preset(Quirks),
+    /// Detect quirks based on instructions used by the ROM.
+    Auto,
+}
+
+/// Options for running a ROM. Platform, speed, quirks and keymap are `None`
+/// if not given on the command line; they're then taken from the ROM
+/// database, if the ROM is known.
+pub struct Options {
+    pub rom: String,
+    pub platform: Option<&'static Platform>,
+    pub addr: Option<usize>,
+    pub speed: Option<u32>,
+    pub quirks: Option<QuirksOption>,
+    pub memory_policy: MemoryPolicy,
+    pub keymap: Option<Keymap>,
+    /// Use Octo's keyboard layout (for Octo cartridges) instead of hex keys.
+    pub octo_keyboard: bool,
+    pub background: Color,
+    /// Colors of pixels lit in the first plane, the second one, and both.
+    pub foreground: [Color; 3],
+    /// Colors were given on the command line.
+    pub palette: bool,
+    pub scale: usize,
+    pub seed: Option<u64>,
+    pub paused: bool,
+    pub fullscreen: bool,
+    pub mute: bool,
+    pub trace: Option<String>,
+    pub trace_filter: TraceFilter,
+    pub trace_last: Option<usize>,
+    pub profile: Option<String>,
+    pub coverage: Option<String>,
+    /// Run on the emulated VIP, with given monitor ROM and interpreter.
+    pub vip: bool,
+    pub monitor: Option<String>,
+    pub interpreter: Option<String>,
+    /// Database entry for the ROM, filled in after loading it.
+    pub rom_info: Option<&'static RomInfo>,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            rom: String::new(),
+            platform: None,
+            addr: None,
+            speed: None,
+            quirks: None,
+            memory_policy: MemoryPolicy::default(),
+            keymap: None,
+            octo_keyboard: false,
+            background: Color::RGB(200, 200, 200),
+            foreground: [
+                Color::RGB(100, 100, 100),
+                Color::RGB(160, 110, 60),
+                Color::RGB(50, 50, 50),
+            ],
+            palette: false,
+            scale: 10,
+            seed: None,
+            paused: false,
+            fullscreen: false,
+            mute: false,
+            trace: None,
+            trace_filter: TraceFilter::default(),
+            trace_last: None,
+            profile: None,
+            coverage: None,
+            vip: false,
+            monitor: None,
+            interpreter: None,
+            rom_info: None,
+        }
+    }
+}
+
+impl Options {
+    pub fn platform(&self) -> &'static Platform {
+        self.platform.unwrap_or(&platform::CHIP8)
+    }
+
+    pub fn addr(&self) -> usize {
+        self.addr.unwrap_or(self.platform().start as usize)
+    }
+
+    /// Where execution starts: the load address if one was given,
+    /// otherwise the platform's entry point.
+    pub fn entry(&self) -> u16 {
+        self.addr.map_or(self.platform().entry(), |addr| addr as u16)
+    }
+
+    pub fn speed(&self) -> u32 {
+        self.speed.unwrap_or(self.platform().speed)
+    }
+
+    pub fn keymap(&self) -> &Keymap {
+        self.keymap.as_ref().unwrap_or(&IDENTITY)
+    }
+}
+
+/// Parses command line arguments (without program name). Returns `Ok(None)`
+/// if the user asked for help.
+pub fn parse_args(args: &[String]) -> Result<Option<Options>, String> {
+    let mut options = Options::default();
+    let mut rom = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next()
+            .ok_or_else(|| format!("option {} requires an argument", arg));
+
+        match arg.as_str() {
+            "--help" | "-h" => return Ok(None),
+            "--platform" => {
+                let name = value()?;
+                options.platform = Some(Platform::from_name(name).ok_or_else(|| format!(
+                    "unknown platform {:?}, expected one of: {}",
+                    name, Platform::names().join(", ")))?);
+            }
+            "--addr" => options.addr = Some(parse_number(value()?)?),
+            "--speed" => {
+                let speed = parse_number(value()?)?;
+                if speed == 0 || speed > 1_000_000 {
+                    return Err(format!("speed should be between 1 and 1000000, got {}", speed));
+                }
+                options.speed = Some(speed as u32);
+            }
+            "--quirks" => {
+                let name = value()?;
+                options.quirks = Some(if name == "auto" {
+                    QuirksOption::Auto
+                } else {
+                    QuirksOption::Preset(Quirks::preset(name).ok_or_else(|| format!(
+                        "unknown quirks preset {:?}, expected one of: {}, auto",
+                        name, PRESETS.join(", ")))?)
+                });
+            }
+            "--memory" => {
+                let name = value()?;
+                options.memory_policy = MemoryPolicy::from_name(name).ok_or_else(|| format!(
+                    "unknown memory policy {:?}, expected one of: {}", name, POLICIES.join(", ")))?;
+            }
+            "--keymap" => options.keymap = Some(load_keymap(value()?)?),
+            "--palette" => {
+                let palette = value()?;
+                let colors = palette.split(',').map(parse_color).collect::<Result<Vec<_>, _>>()?;
+                match colors.len() {
+                    2 => {
+                        options.background = colors[0];
+                        options.foreground[0] = colors[1];
+                    }
+                    4 => {
+                        options.background = colors[0];
+                        options.foreground.copy_from_slice(&colors[1..]);
+                    }
+                    _ => return Err(format!("palette should be BG,FG or BG,FG,FG2,BOTH, got {:?}",
+                                            palette)),
+                }
+                options.palette = true;
+            }
+            "--scale" => {
+                let scale = parse_number(value()?)?;
+                if scale == 0 || scale > 100 {
+                    return Err(format!("scale should be between 1 and 100, got {}", scale));
+                }
+                options.scale = scale;
+            }
+            "--seed" => options.seed = Some(parse_number(value()?)? as u64),
+            "--paused" => options.paused = true,
+            "--fullscreen" => options.fullscreen = true,
+            "--mute" => options.mute = true,
+            "--trace" => options.trace = Some(value()?.clone()),
+            "--trace-addr" => {
+                let range = value()?;
+                let mut parts = range.split('-');
+                match (parts.next(), parts.next(), parts.next()) {
+                    (Some(start), Some(end), None) => {
+                        let start = parse_hex(start)?;
+                        let end = parse_hex(end)?;
+                        if start > end {
+                            return Err(format!("empty address range {:?}", range));
+                        }
+                        options.trace_filter.addr_range = Some((start, end));
+                    }
+                    _ => return Err(format!("address range should be START-END, got {:?}", range)),
+                }
+            }
+            "--trace-instr" => {
+                options.trace_filter.kinds = TraceFilter::parse_kinds(value()?)?;
+            }
+            "--trace-last" => {
+                let n = parse_number(value()?)?;
+                if n == 0 {
+                    return Err("--trace-last should be at least 1".to_string());
+                }
+                options.trace_last = Some(n);
+            }
+            "--profile" => options.profile = Some(value()?.clone()),
+            "--coverage" => options.coverage = Some(value()?.clone()),
+            "--vip" => options.vip = true,
+            "--monitor" => options.monitor = Some(value()?.clone()),
+            "--interpreter" => options.interpreter = Some(value()?.clone()),
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            _ => {
+                if rom.is_some() {
+                    return Err("more than one ROM given".to_string());
+                }
+                rom = Some(arg.clone());
+            }
+        }
+    }
+
+    if options.trace.is_none() &&
+        (options.trace_filter.addr_range.is_some() || !options.trace_filter.kinds.is_empty() ||
+         options.trace_last.is_some()) {
+        return Err("trace options require --trace".to_string());
+    }
+    if options.vip {
+        if options.monitor.is_none() || options.interpreter.is_none() {
+            return Err("--vip requires --monitor and --interpreter".to_string());
+        }
+        if options.platform.is_some_and(|platform| platform.name != "chip8") {
+            return Err("--vip only runs CHIP-8".to_string());
+        }
+        if options.trace.is_some() || options.profile.is_some() || options.coverage.is_some() {
+            return Err("--trace, --profile and --coverage can't be used with --vip".to_string());
+        }
+    } else if options.monitor.is_some() || options.interpreter.is_some() {
+        return Err("--monitor and --interpreter require --vip".to_string());
+    }
+
+    options.rom = rom.ok_or_else(|| "no ROM given".to_string())?;
+    Ok(Some(options))
+}
+
+fn parse_number(s: &str) -> Result<usize, String> {
+    let result = if s.starts_with("0x") || s.starts_with("0X") {
+        usize::from_str_radix(&s[2..], 16)
+    } else {
+        s.parse()
+    };
+    result.map_err(|_| format!("{:?} is not a valid number", s))
+}
+
+fn parse_hex(s: &str) -> Result<u16, String> {
+    let s = s.trim_start_matches("0x").trim_start_matches("0X");
+    u16::from_str_radix(s, 16).map_err(|_| format!("{:?} is not a valid address", s))
+}
+
+fn parse_color(s: &str) -> Result<Color, String> {
+    let s = s.trim_start_matches('#');
+    match u32::from_str_radix(s, 16) {
+        Ok(rgb) if s.len() == 6 =>
+            Ok(Color::RGB((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)),
+        _ => Err(format!("{:?} is not a valid RGB color", s)),
+    }
+}
@@ -0,0 +1,82 @@
+use std::fs::File;
+use std::io::{self, BufWriter};
+
+use chiprs::Chip;
+use chiprs::hook::Hook;
+use chiprs::profile::Profiler;
+use chiprs::coverage::Coverage;
+
+/// Runs the profiler, and writes out the report to `filename` (and the
+/// stacks for flamegraph to `filename.folded`) when done.
+pub struct ProfileReport {
+    profiler: Profiler,
+    filename: String,
+}
+
+impl ProfileReport {
+    pub fn new(filename: &str) -> ProfileReport {
+        ProfileReport {
+            profiler: Profiler::new(),
+            filename: filename.to_string(),
+        }
+    }
+}
+
+impl Hook for ProfileReport {
+    fn before_step(&mut self, chip: &Chip, pc: u16, b: u16) {
+        self.profiler.before_step(chip, pc, b);
+    }
+
+    fn after_step(&mut self, chip: &Chip, result: &Result<(), String>) {
+        self.profiler.after_step(chip, result);
+    }
+
+    fn on_tick(&mut self, chip: &Chip) {
+        self.profiler.on_tick(chip);
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(&self.filename)?);
+        self.profiler.write_report(&mut w)?;
+        let mut w = BufWriter::new(File::create(format!("{}.folded", self.filename))?);
+        self.profiler.write_folded(&mut w)
+    }
+}
+
+/// Tracks ROM coverage, and writes out annotated disassembly to `filename`
+/// (and lcov data to `filename.info`) when done.
+pub struct CoverageReport {
+    coverage: Coverage,
+    filename: String,
+}
+
+impl CoverageReport {
+    pub fn new(filename: &str, memory_size: usize, addr: usize, rom: &[u8]) -> CoverageReport {
+        CoverageReport {
+            coverage: Coverage::new(memory_size, addr, rom),
+            filename: filename.to_string(),
+        }
+    }
+}
+
+impl Hook for CoverageReport {
+    fn before_step(&mut self, chip: &Chip, pc: u16, b: u16) {
+        self.coverage.before_step(chip, pc, b);
+    }
+
+    fn after_step(&mut self, chip: &Chip, result: &Result<(), String>) {
+        self.coverage.after_step(chip, result);
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(&self.filename)?);
+        self.coverage.write_listing(&mut w)?;
+        let mut w = BufWriter::new(File::create(format!("{}.info", self.filename))?);
+        self.coverage.write_lcov(&mut w, &self.filename)?;
+
+        let (executed, read, written, unused) = self.coverage.summary();
+        println!("Coverage: {} bytes executed, {} read, {} written, {} unused",
+                 executed, read, written, unused);
+        Ok(())
+    }
+}
@@ -3,88 +3,353 @@ extern crate chiprs;
 
 use sdl2::pixels::Color;
 use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use sdl2::render::WindowCanvas;
+use sdl2::keyboard::{Keycode, LSHIFTMOD, RSHIFTMOD};
+use sdl2::render::{WindowCanvas, BlendMode};
 use sdl2::rect::Rect;
+use std::cell::RefCell;
+use std::rc::Rc;
 use std::time::{Duration, Instant};
 
-use chiprs::Chip;
-use chiprs::display::{Display, DISPLAY_W, DISPLAY_H};
-
-const PIXEL_W: usize = 10;
-const PIXEL_H: usize = 10;
-
-pub fn run_sdl_interface(chip: &mut Chip) {
-    let sdl_context = sdl2::init().unwrap();
-    let video_subsystem = sdl_context.video().unwrap();
-    let window = video_subsystem.window("chiprs",
-                                        (DISPLAY_W*PIXEL_W) as u32,
-                                        (DISPLAY_H*PIXEL_H) as u32)
-        .position_centered()
-        .opengl()
-        .build()
-        .unwrap();
-
-    let mut canvas = window.into_canvas().build().unwrap();
-    let mut events = sdl_context.event_pump().unwrap();
-    let mut next_tick = Instant::now();
-    let mut next_step = Instant::now();
+use chiprs::display::{Framebuffer, MEGA_W, MEGA_H};
+use chiprs::hook::Hook;
+use chiprs::machine::Machine;
+use chiprs::octo;
+
+use crate::filter::{RenderFilter, FilterMode};
+use crate::keymap::chip_keys;
+use crate::options::Options;
+use crate::beeper::Beeper;
+use crate::control::{Control, SpeedMode};
+use crate::text::{draw_text, text_width, GLYPH_H};
+use crate::debug::DebugPanel;
+
+const HELP_TIME: Duration = Duration::from_secs(5);
+
+/// Runs `machine` (a `Chip`, or the emulated VIP) in a window. The debug
+/// panel is only available for a `Chip`.
+pub fn run_sdl_interface(machine: &mut dyn Machine, options: &Options,
+                         hook: &mut dyn Hook) -> Result<(), String> {
+    let sdl_context = sdl2::init()?;
+    let video_subsystem = sdl_context.video()?;
+    let title = match options.rom_info {
+        Some(info) => format!("chiprs - {}", info.title),
+        None => "chiprs".to_string(),
+    };
+    let (screen_w, screen_h) = screen_size(options);
+    let mut window_builder = video_subsystem.window(&title, screen_w as u32, screen_h as u32);
+    window_builder.position_centered().opengl();
+    if options.fullscreen {
+        window_builder.fullscreen_desktop();
+    }
+    let window = window_builder.build().map_err(|e| e.to_string())?;
+
+    let mut canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+    let mut debug = DebugPanel::new();
+    set_layout(&mut canvas, &debug, options)?;
+    let mut events = sdl_context.event_pump()?;
+
+    let beeper = if options.mute {
+        None
+    } else {
+        match sdl_context.audio().and_then(|audio| Beeper::new(&audio)) {
+            Ok(beeper) => Some(Rc::new(RefCell::new(beeper))),
+            Err(e) => {
+                eprintln!("warning: can't initialize sound: {}", e);
+                None
+            }
+        }
+    };
+    if let Some(ref beeper) = beeper {
+        machine.set_beeper(Box::new(beeper.clone()));
+    }
+
+    let filter = Rc::new(RefCell::new(RenderFilter::new(FilterMode::Off)));
+    machine.set_frame_sink(Box::new(filter.clone()));
+    machine.send_frame();
+    let mut control = Control::new(options.speed(), options.paused);
+    let mut last_update = Instant::now();
+    // Controls are shown for a while after start (unless F1 is pressed
+    // first), and toggled with F1.
+    let mut show_help = true;
+    let mut help_until = Some(last_update + HELP_TIME);
     'running: loop {
         for event in events.poll_iter() {
             match event {
                 Event::Quit {..} => {
                     break 'running;
                 }
-                Event::KeyDown { keycode: Some(key), .. } => {
-                    if key == Keycode::Escape {
-                        break 'running;
+                Event::KeyDown { keycode: Some(key), repeat, keymod, .. } => {
+                    // Keys used by the game (with Octo's layout, some
+                    // letters) aren't hotkeys.
+                    let chip8_key = get_chip8_key(key, options);
+                    match key {
+                        _ if chip8_key.is_some() => (),
+                        Keycode::Escape => break 'running,
+                        Keycode::F1 if !repeat => {
+                            show_help = !show_help;
+                            help_until = None;
+                        }
+                        Keycode::F2 if !repeat => {
+                            let mode = filter.borrow().mode().next();
+                            filter.borrow_mut().set_mode(mode);
+                            eprintln!("Render filter: {}", mode.name());
+                        }
+                        Keycode::F3 if !repeat && machine.as_chip().is_some() => {
+                            debug.visible = !debug.visible;
+                            set_layout(&mut canvas, &debug, options)?;
+                        }
+                        Keycode::PageUp => if let Some(chip) = machine.as_chip() {
+                            debug.scroll(chip, -4);
+                        },
+                        Keycode::PageDown => if let Some(chip) = machine.as_chip() {
+                            debug.scroll(chip, 4);
+                        },
+                        Keycode::Home => debug.follow_i(),
+                        Keycode::P if !repeat => control.toggle_pause(),
+                        Keycode::N => {
+                            control.paused = true;
+                            control.advance_frame();
+                            run_due(machine, &mut control, hook)?;
+                        }
+                        Keycode::I => {
+                            control.paused = true;
+                            machine.step_with(hook)?;
+                            machine.send_frame();
+                        }
+                        Keycode::T if !repeat => control.toggle_mode(SpeedMode::Turbo),
+                        Keycode::S if !repeat => control.toggle_mode(SpeedMode::Slow),
+                        Keycode::Equals | Keycode::KpPlus => control.faster(),
+                        Keycode::Minus | Keycode::KpMinus => control.slower(),
+                        _ => (),
+                    }
+                    if repeat {
+                        continue;
                     }
-                    if let Some(k) = get_chip8_key(key) {
-                        chip.key_down(k);
+                    // With Shift, keys go to the second keypad (CHIP-8X).
+                    let second = options.platform().second_keypad &&
+                        keymod.intersects(LSHIFTMOD | RSHIFTMOD);
+                    if let Some(k) = chip8_key {
+                        for chip_key in chip_keys(options.keymap(), k) {
+                            if second {
+                                machine.key2_down(chip_key);
+                            } else {
+                                machine.key_down(chip_key);
+                            }
+                        }
                     }
                 }
                 Event::KeyUp { keycode: Some(key), .. } => {
-                    if let Some(k) = get_chip8_key(key) {
-                        chip.key_up(k);
+                    if let Some(k) = get_chip8_key(key, options) {
+                        for chip_key in chip_keys(options.keymap(), k) {
+                            machine.key_up(chip_key);
+                            machine.key2_up(chip_key);
+                        }
                     }
                 }
                 _ => {}
             }
         }
 
-        draw_display(&mut canvas, &chip.display);
+        draw_display(&mut canvas, &filter.borrow(), options);
+        if let (true, Some(chip)) = (debug.visible, machine.as_chip()) {
+            let (_, height) = canvas.logical_size();
+            debug.draw(&mut canvas, chip, screen_w, height as usize,
+                       text_size(options));
+        }
+        let mut overlay = vec![];
+        if control.show_overlay() {
+            overlay.extend(control.status());
+        }
+        if help_until.is_some_and(|t| Instant::now() >= t) {
+            show_help = false;
+            help_until = None;
+        }
+        if show_help {
+            overlay.extend(help_lines(options));
+        }
+        if !overlay.is_empty() {
+            draw_overlay(&mut canvas, &overlay, options);
+        }
         canvas.present();
 
         let now = Instant::now();
-        while next_tick < now {
-            chip.tick();
-            next_tick += Duration::new(0, 1_000_000_000u32 / 60);
+        let elapsed = now - last_update;
+        last_update = now;
+        if !control.paused {
+            control.advance(elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9);
+            run_due(machine, &mut control, hook)?;
         }
-        while next_step < now {
-            chip.step().unwrap();
-            next_step += Duration::new(0, 1_000_000_000u32 / 5000);
+        if machine.exited() {
+            break 'running;
+        }
+
+        if let Some(ref beeper) = beeper {
+            beeper.borrow_mut().set_paused(control.paused);
         }
     }
+    Ok(())
 }
 
+/// Runs all timer ticks and instructions that are due, according to
+/// emulated time. Machines that keep their own time run in the ticks.
+fn run_due(machine: &mut dyn Machine, control: &mut Control,
+           hook: &mut dyn Hook) -> Result<(), String> {
+    while control.tick_due() {
+        machine.tick_with(hook);
+    }
+    while control.step_due() {
+        if !machine.keeps_time() {
+            machine.step_with(hook)?;
+        }
+    }
+    Ok(())
+}
+
+fn help_lines(options: &Options) -> Vec<String> {
+    match options.rom_info {
+        Some(info) => {
+            let mut lines = vec![info.title.to_string()];
+            lines.extend(info.help.iter().map(|line| line.to_string()));
+            lines
+        }
+        None => vec![],
+    }
+}
+
+fn text_size(options: &Options) -> usize {
+    (options.scale / 5).max(1)
+}
+
+/// Size of the CHIP-8 screen area in the window, from the platform's
+/// startup resolution.
+fn screen_size(options: &Options) -> (usize, usize) {
+    let (width, height) = options.platform().display_modes[0];
+    (width * options.scale, height * options.scale)
+}
+
+/// Sets up the canvas size, depending on whether the debug panel is shown.
+fn set_layout(canvas: &mut WindowCanvas, debug: &DebugPanel,
+              options: &Options) -> Result<(), String> {
+    let (mut width, mut height) = screen_size(options);
+    if debug.visible {
+        width += DebugPanel::width(text_size(options));
+        height = height.max(DebugPanel::height(text_size(options)));
+    }
+
+    if !options.fullscreen {
+        canvas.window_mut().set_size(width as u32, height as u32)
+            .map_err(|e| e.to_string())?;
+    }
+    canvas.set_logical_size(width as u32, height as u32).map_err(|e| e.to_string())
+}
 
 fn draw_display(canvas: &mut WindowCanvas,
-                display: &Display) {
-    canvas.set_draw_color(Color::RGB(200, 200, 200));
+                filter: &RenderFilter,
+                options: &Options) {
+    // The screen area stays the same, higher resolutions get smaller
+    // pixels.
+    let (screen_w, screen_h) = screen_size(options);
+    let display = match filter.display() {
+        Some(display) => display,
+        None => return,
+    };
+    if let Some(ref framebuffer) = display.framebuffer {
+        draw_framebuffer(canvas, framebuffer, screen_w, screen_h);
+        return;
+    }
+    let (width, height) = (display.width(), display.height());
+    let background = match display.colors {
+        Some(ref colors) => vp590_color(colors.background()),
+        None => options.background,
+    };
+    canvas.set_draw_color(background);
     canvas.clear();
-    canvas.set_draw_color(Color::RGB(100, 100, 100));
-    for i in 0..DISPLAY_W {
-        for j in 0..DISPLAY_H {
-            if display.at(i, j) {
+    for i in 0..width {
+        for j in 0..height {
+            let level = filter.level(display, i, j);
+            if level > 0.05 {
+                let foreground = match display.colors {
+                    Some(ref colors) => vp590_color(colors.at(i, j)),
+                    // Pixels fading out (in filter modes) keep the first
+                    // color.
+                    None => options.foreground[(display.color(i, j) as usize).clamp(1, 3) - 1],
+                };
+                canvas.set_draw_color(mix(background, foreground, level));
+                let (x0, x1) = (i * screen_w / width, (i + 1) * screen_w / width);
+                let (y0, y1) = (j * screen_h / height, (j + 1) * screen_h / height);
                 canvas.fill_rect(Rect::new(
-                    (i * PIXEL_W) as i32, (j * PIXEL_H) as i32,
-                    PIXEL_W as u32, PIXEL_H as u32)).unwrap();
+                    x0 as i32, y0 as i32, (x1 - x0) as u32, (y1 - y0) as u32)).unwrap();
+            }
+        }
+    }
+}
+
+/// Draws the MegaChip screen, over black. Render filters don't apply. The
+/// screen area has the shape of the platform's startup resolution, so the
+/// framebuffer is letterboxed to keep its own shape.
+fn draw_framebuffer(canvas: &mut WindowCanvas, framebuffer: &Framebuffer,
+                    screen_w: usize, screen_h: usize) {
+    canvas.set_draw_color(Color::RGB(0, 0, 0));
+    canvas.clear();
+    let (width, height) = if screen_w * MEGA_H > screen_h * MEGA_W {
+        (screen_h * MEGA_W / MEGA_H, screen_h)
+    } else {
+        (screen_w, screen_w * MEGA_H / MEGA_W)
+    };
+    let (left, top) = ((screen_w - width) / 2, (screen_h - height) / 2);
+    for i in 0..MEGA_W {
+        for j in 0..MEGA_H {
+            let argb = framebuffer.at(i, j);
+            let alpha = (argb >> 24) * framebuffer.alpha as u32 / 0xFF;
+            if argb & 0xFF_FFFF == 0 || alpha == 0 {
+                continue;
             }
+            let channel = |shift: u32| ((argb >> shift & 0xFF) * alpha / 0xFF) as u8;
+            canvas.set_draw_color(Color::RGB(channel(16), channel(8), channel(0)));
+            let (x0, x1) = (i * width / MEGA_W, (i + 1) * width / MEGA_W);
+            let (y0, y1) = (j * height / MEGA_H, (j + 1) * height / MEGA_H);
+            canvas.fill_rect(Rect::new(
+                (left + x0) as i32, (top + y0) as i32,
+                (x1 - x0) as u32, (y1 - y0) as u32)).unwrap();
         }
     }
 }
 
-fn get_chip8_key(key: Keycode) -> Option<u8> {
+fn draw_overlay(canvas: &mut WindowCanvas, lines: &[String], options: &Options) {
+    let size = text_size(options);
+    let margin = 2 * size;
+    let line_h = (GLYPH_H + 2) * size;
+    let width = lines.iter().map(|line| text_width(line, size)).max().unwrap_or(0);
+    let height = lines.len() * line_h;
+
+    canvas.set_blend_mode(BlendMode::Blend);
+    canvas.set_draw_color(Color::RGBA(0, 0, 0, 160));
+    canvas.fill_rect(Rect::new(
+        0, 0, (width + 2 * margin) as u32, (height + 2 * margin) as u32)).unwrap();
+    canvas.set_blend_mode(BlendMode::None);
+
+    for (i, line) in lines.iter().enumerate() {
+        draw_text(canvas, margin, margin + i * line_h, size, Color::RGB(255, 255, 255), line);
+    }
+}
+
+/// CHIP-8X (VP-590) color: bits are red, blue and green.
+fn vp590_color(color: u8) -> Color {
+    let channel = |bit: u8| if color & bit != 0 { 220 } else { 0 };
+    Color::RGB(channel(1), channel(4), channel(2))
+}
+
+fn mix(a: Color, b: Color, level: f32) -> Color {
+    let channel = |x: u8, y: u8| (x as f32 + (y as f32 - x as f32) * level) as u8;
+    Color::RGB(channel(a.r, b.r), channel(a.g, b.g), channel(a.b, b.b))
+}
+
+fn get_chip8_key(key: Keycode, options: &Options) -> Option<u8> {
+    if options.octo_keyboard {
+        let name = key.name().to_lowercase();
+        return octo::KEYBOARD.iter().position(|&c| name == c.to_string()).map(|k| k as u8);
+    }
+
     use sdl2::keyboard::Keycode::*;
     match key {
         Num0 | Kp0 =>
@@ -0,0 +1,95 @@
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::WindowCanvas;
+
+pub const GLYPH_W: usize = 3;
+pub const GLYPH_H: usize = 5;
+
+// A tiny 3x5 font, enough to show some status text on screen.
+const GLYPHS: &[(char, [&str; GLYPH_H])] = &[
+    ('0', ["###", "# #", "# #", "# #", "###"]),
+    ('1', [" # ", "## ", " # ", " # ", "###"]),
+    ('2', ["###", "  #", "###", "#  ", "###"]),
+    ('3', ["###", "  #", "###", "  #", "###"]),
+    ('4', ["# #", "# #", "###", "  #", "  #"]),
+    ('5', ["###", "#  ", "###", "  #", "###"]),
+    ('6', ["###", "#  ", "###", "# #", "###"]),
+    ('7', ["###", "  #", "  #", " # ", " # "]),
+    ('8', ["###", "# #", "###", "# #", "###"]),
+    ('9', ["###", "# #", "###", "  #", "###"]),
+    ('A', [" # ", "# #", "###", "# #", "# #"]),
+    ('B', ["## ", "# #", "## ", "# #", "## "]),
+    ('C', [" ##", "#  ", "#  ", "#  ", " ##"]),
+    ('D', ["## ", "# #", "# #", "# #", "## "]),
+    ('E', ["###", "#  ", "## ", "#  ", "###"]),
+    ('F', ["###", "#  ", "## ", "#  ", "#  "]),
+    ('G', [" ##", "#  ", "# #", "# #", " ##"]),
+    ('H', ["# #", "# #", "###", "# #", "# #"]),
+    ('I', ["###", " # ", " # ", " # ", "###"]),
+    ('J', ["  #", "  #", "  #", "# #", " # "]),
+    ('K', ["# #", "# #", "## ", "# #", "# #"]),
+    ('L', ["#  ", "#  ", "#  ", "#  ", "###"]),
+    ('M', ["# #", "###", "###", "# #", "# #"]),
+    ('N', ["## ", "# #", "# #", "# #", "# #"]),
+    ('O', [" # ", "# #", "# #", "# #", " # "]),
+    ('P', ["## ", "# #", "## ", "#  ", "#  "]),
+    ('Q', [" # ", "# #", "# #", "## ", " ##"]),
+    ('R', ["## ", "# #", "## ", "# #", "# #"]),
+    ('S', [" ##", "#  ", " # ", "  #", "## "]),
+    ('T', ["###", " # ", " # ", " # ", " # "]),
+    ('U', ["# #", "# #", "# #", "# #", "###"]),
+    ('V', ["# #", "# #", "# #", "# #", " # "]),
+    ('W', ["# #", "# #", "###", "###", "# #"]),
+    ('X', ["# #", "# #", " # ", "# #", "# #"]),
+    ('Y', ["# #", "# #", " # ", " # ", " # "]),
+    ('Z', ["###", "  #", " # ", "#  ", "###"]),
+    ('.', ["   ", "   ", "   ", "   ", " # "]),
+    (',', ["   ", "   ", "   ", " # ", "#  "]),
+    (':', ["   ", " # ", "   ", " # ", "   "]),
+    ('-', ["   ", "   ", "###", "   ", "   "]),
+    ('+', ["   ", " # ", "###", " # ", "   "]),
+    ('=', ["   ", "###", "   ", "###", "   "]),
+    ('/', ["  #", "  #", " # ", "#  ", "#  "]),
+    ('%', ["# #", "  #", " # ", "#  ", "# #"]),
+    ('?', ["###", "  #", " # ", "   ", " # "]),
+    ('(', [" # ", "#  ", "#  ", "#  ", " # "]),
+    (')', [" # ", "  #", "  #", "  #", " # "]),
+    ('[', ["## ", "#  ", "#  ", "#  ", "## "]),
+    (']', [" ##", "  #", "  #", "  #", " ##"]),
+    ('<', ["  #", " # ", "#  ", " # ", "  #"]),
+    ('>', ["#  ", " # ", "  #", " # ", "#  "]),
+    ('#', ["# #", "###", "# #", "###", "# #"]),
+];
+
+fn glyph(c: char) -> Option<&'static [&'static str; GLYPH_H]> {
+    let c = c.to_ascii_uppercase();
+    GLYPHS.iter().find(|(g, _)| *g == c).map(|(_, rows)| rows)
+}
+
+/// Width of rendered text in pixels, for given pixel size.
+pub fn text_width(text: &str, size: usize) -> usize {
+    text.chars().count() * (GLYPH_W + 1) * size
+}
+
+/// Draws a line of text, with the top left corner at (x, y). Unknown
+/// characters are rendered as spaces.
+pub fn draw_text(canvas: &mut WindowCanvas, x: usize, y: usize, size: usize,
+                 color: Color, text: &str) {
+    canvas.set_draw_color(color);
+    for (n, c) in text.chars().enumerate() {
+        let rows = match glyph(c) {
+            Some(rows) => rows,
+            None => continue,
+        };
+        let cx = x + n * (GLYPH_W + 1) * size;
+        for (j, row) in rows.iter().enumerate() {
+            for (i, b) in row.bytes().enumerate() {
+                if b == b'#' {
+                    canvas.fill_rect(Rect::new(
+                        (cx + i * size) as i32, (y + j * size) as i32,
+                        size as u32, size as u32)).unwrap();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,88 @@
+extern crate chiprs;
+
+use std::fs;
+use std::io::{self, Write};
+use std::process::exit;
+
+use chiprs::analysis::Analysis;
+use chiprs::memory::MEMORY_SIZE;
+
+const USAGE: &str = "\
+Usage: chiprs-analyze [OPTIONS] ROM
+
+Analyze control flow of a CHIP-8 ROM.
+
+Options:
+  --dot          write the control flow graph in Graphviz format
+                 (default: JSON summary)
+  --addr ADDR    load address (default: 0x200)
+  --help         show this message";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut dot = false;
+    let mut addr = 0x200;
+    let mut rom = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            "--dot" => dot = true,
+            "--addr" => {
+                let value = iter.next().map(String::as_str).unwrap_or("");
+                addr = match parse_addr(value) {
+                    Some(addr) => addr,
+                    None => usage_error(&format!("invalid address: {:?}", value)),
+                };
+            }
+            _ if arg.starts_with("--") => usage_error(&format!("unknown option: {}", arg)),
+            _ if rom.is_none() => rom = Some(arg.clone()),
+            _ => usage_error("too many arguments"),
+        }
+    }
+    let rom = match rom {
+        Some(rom) => rom,
+        None => usage_error("no ROM file given"),
+    };
+
+    let data = match fs::read(&rom) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("error: can't load {}: {}", rom, e);
+            exit(1);
+        }
+    };
+
+    let analysis = Analysis::new(&data, addr);
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let result = if dot {
+        analysis.write_dot(&mut out)
+    } else {
+        analysis.write_json(&mut out)
+    };
+    if let Err(e) = result.and_then(|_| out.flush()) {
+        eprintln!("error: {}", e);
+        exit(1);
+    }
+}
+
+fn parse_addr(s: &str) -> Option<u16> {
+    let addr = if let Some(hex) = s.strip_prefix("0x") {
+        u16::from_str_radix(hex, 16).ok()
+    } else {
+        s.parse().ok()
+    };
+    addr.filter(|&addr| (addr as usize) < MEMORY_SIZE)
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("error: {}", message);
+    eprintln!();
+    eprintln!("{}", USAGE);
+    exit(2);
+}
@@ -0,0 +1,177 @@
+extern crate chiprs;
+
+use std::fs;
+use std::process::exit;
+
+use chiprs::Chip;
+use chiprs::instr::Instr;
+use chiprs::machine::Machine;
+use chiprs::platform::{self, Platform};
+use chiprs::quirks;
+use chiprs::vip::Vip;
+
+const USAGE: &str = "\
+Usage: chiprs-compare --monitor FILE --interpreter FILE [OPTIONS] ROM
+
+Run a CHIP-8 ROM on the emulated COSMAC VIP (using the original interpreter)
+and on chiprs side by side, and report the first instruction after which
+the registers differ, and any difference on the screen at the end.
+
+Differences after RND (different random numbers) and FX29 (the VIP font is
+in the monitor ROM) are expected: chiprs takes the random number from the
+VIP, and I is taken to be the same as long as both point to the same digit.
+
+Options:
+  --monitor FILE      VIP monitor ROM (512 bytes)
+  --interpreter FILE  CHIP-8 interpreter, loaded at 0
+  --steps N           number of instructions to run (default: 10000)
+  --help              show this message";
+
+const ROM_ADDR: usize = 0x200;
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut monitor = None;
+    let mut interpreter = None;
+    let mut steps = 10000;
+    let mut rom = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            "--monitor" => monitor = iter.next().cloned(),
+            "--interpreter" => interpreter = iter.next().cloned(),
+            "--steps" => {
+                let value = iter.next().map(String::as_str).unwrap_or("");
+                steps = match value.parse() {
+                    Ok(steps) => steps,
+                    Err(_) => usage_error(&format!("invalid number of steps: {:?}", value)),
+                };
+            }
+            _ if arg.starts_with("--") => usage_error(&format!("unknown option: {}", arg)),
+            _ if rom.is_none() => rom = Some(arg.clone()),
+            _ => usage_error("too many arguments"),
+        }
+    }
+    let monitor = read(&monitor.unwrap_or_else(|| usage_error("no monitor ROM given")));
+    let interpreter = read(&interpreter.unwrap_or_else(|| usage_error("no interpreter given")));
+    let rom = read(&rom.unwrap_or_else(|| usage_error("no ROM file given")));
+
+    let mut vip = Vip::new(&monitor);
+    let mut chip = Chip::new();
+    chip.quirks = quirks::VIP;
+    chip.load_font();
+    let loaded = vip.load(0, &interpreter)
+        .and_then(|_| vip.load(ROM_ADDR, &rom))
+        .and_then(|_| chip.memory.load_rom(&rom, ROM_ADDR).map_err(|e| e.to_string()));
+    if let Err(e) = loaded {
+        eprintln!("error: {}", e);
+        exit(1);
+    }
+
+    // Run the monitor and the interpreter up to the first instruction.
+    if let Err(e) = vip.step() {
+        eprintln!("error: {}", e);
+        exit(1);
+    }
+    if vip.pc() as usize != ROM_ADDR {
+        eprintln!("error: the interpreter started at {:03X}, not {:03X}", vip.pc(), ROM_ADDR);
+        exit(1);
+    }
+    chip.jump(ROM_ADDR as u16);
+
+    let mut frames = vip.frames();
+    // Values of I (VIP, chiprs) pointing to the same digit, after FX29.
+    let mut font_i = None;
+    for step in 0..steps {
+        let pc = chip.pc();
+        let b = chip.memory.u16_at(pc as usize);
+        let result = Machine::step(&mut vip).and_then(|_| Machine::step(&mut chip));
+        if let Err(e) = result {
+            eprintln!("error: step {}, {:03X}: {}", step, pc, e);
+            exit(1);
+        }
+        // The VIP updates its timers in the video interrupt.
+        while frames < vip.frames() {
+            chip.tick();
+            frames += 1;
+        }
+        match platform::CHIP8.decode(b) {
+            Some(Instr::RND(x, _)) => chip.set_v(x, vip.v()[x as usize]),
+            Some(Instr::LD_F_R(_)) => font_i = Some((vip.i(), chip.i())),
+            _ => (),
+        }
+        if let Some(diff) = compare(&vip, &chip, font_i) {
+            println!("step {}, {:03X}: {}", step, pc, disassemble(b));
+            println!("  {}", diff);
+            dump("vip", &vip);
+            dump("chiprs", &chip);
+            exit(1);
+        }
+    }
+
+    // Let the VIP show the final state of the screen.
+    vip.run_frame();
+    let mut same = true;
+    let width = chip.display.width();
+    for y in 0..chip.display.height() {
+        for x in 0..width {
+            // The VIP repeats each row 4 times.
+            if vip.display.at(x, y * 4) != chip.display.at(x, y) {
+                println!("screen differs at {}, {}", x, y);
+                same = false;
+            }
+        }
+    }
+    if !same {
+        exit(1);
+    }
+    println!("no differences after {} steps", steps);
+}
+
+/// Differences in registers, other than I pointing to the same digit in
+/// each machine's font (`font_i`).
+fn compare(a: &dyn Machine, b: &dyn Machine, font_i: Option<(u32, u32)>) -> Option<String> {
+    if a.pc() != b.pc() {
+        return Some(format!("PC: {:03X} != {:03X}", a.pc(), b.pc()));
+    }
+    if a.i() != b.i() && font_i != Some((a.i(), b.i())) {
+        return Some(format!("I: {:03X} != {:03X}", a.i(), b.i()));
+    }
+    let (va, vb) = (a.v(), b.v());
+    (0..va.len()).find(|&n| va[n] != vb[n])
+        .map(|n| format!("V{:X}: {:02X} != {:02X}", n, va[n], vb[n]))
+}
+
+fn dump(name: &str, machine: &dyn Machine) {
+    let v: Vec<String> = machine.v().iter().map(|v| format!("{:02X}", v)).collect();
+    println!("  {:7} PC={:03X} I={:03X} V={}", name, machine.pc(), machine.i(), v.join(" "));
+}
+
+fn disassemble(b: u16) -> String {
+    match Platform::from_name("chip8").and_then(|platform| platform.decode(b)) {
+        Some(instr) => format!("{:04X} {}", b, instr),
+        None => format!("{:04X} ???", b),
+    }
+}
+
+fn read(path: &str) -> Vec<u8> {
+    match fs::read(path) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("error: can't load {}: {}", path, e);
+            exit(1);
+        }
+    }
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("error: {}", message);
+    eprintln!();
+    eprintln!("{}", USAGE);
+    exit(2);
+}
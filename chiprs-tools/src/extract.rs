@@ -0,0 +1,153 @@
+extern crate chiprs;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+use chiprs::extract::{self, Payload};
+
+const USAGE: &str = "\
+Usage: chiprs-extract [OPTIONS] FILE...
+
+Find ROMs embedded in text files (uuencoded files, HP-48 ASC-> strings and
+hex dumps), and write them out as .ch8 files.
+
+Options:
+  --out DIR      where to write the ROMs (default: current directory)
+  --all          also write ROMs that don't look like CHIP-8 programs
+  --dry-run      only report what was found
+  --help         show this message";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut out = PathBuf::from(".");
+    let mut all = false;
+    let mut dry_run = false;
+    let mut files = vec![];
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            "--out" => match iter.next() {
+                Some(dir) => out = PathBuf::from(dir),
+                None => usage_error("--out needs a directory"),
+            },
+            "--all" => all = true,
+            "--dry-run" => dry_run = true,
+            _ if arg.starts_with("--") => usage_error(&format!("unknown option: {}", arg)),
+            _ => files.push(arg.clone()),
+        }
+    }
+    if files.is_empty() {
+        usage_error("no files given");
+    }
+
+    let mut failed = false;
+    for file in files.iter() {
+        if !extract_file(file, &out, all, dry_run) {
+            failed = true;
+        }
+    }
+    if failed {
+        exit(1);
+    }
+}
+
+/// Writes out the ROMs found in `file`. Returns false if there were errors.
+fn extract_file(file: &str, out: &Path, all: bool, dry_run: bool) -> bool {
+    let data = match fs::read(file) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("error: can't load {}: {}", file, e);
+            return false;
+        }
+    };
+    // Old text files are not always valid UTF-8.
+    let text = String::from_utf8_lossy(&data);
+    let payloads = extract::scan(&text);
+    if payloads.is_empty() {
+        println!("{}: nothing found", file);
+    }
+    let mut ok = true;
+    for (n, payload) in payloads.iter().enumerate() {
+        let name = output_name(file, payload, n, payloads.len());
+        if let Err(e) = extract_one(file, payload, &out.join(name), all, dry_run) {
+            eprintln!("error: {}", e);
+            ok = false;
+        }
+    }
+    ok
+}
+
+fn extract_one(file: &str, payload: &Payload, path: &Path, all: bool, dry_run: bool)
+               -> Result<(), String> {
+    let location = format!("{}:{}", file, payload.line);
+    let rom = match &payload.data {
+        Ok(rom) => rom,
+        Err(e) => {
+            println!("{}: {}, can't decode: {}", location, payload.encoding, e);
+            return Ok(());
+        }
+    };
+    let plausible = extract::is_plausible(rom);
+    print!("{}: {}, {} bytes", location, payload.encoding, rom.len());
+    if !plausible {
+        print!(", doesn't look like CHIP-8");
+    }
+    if dry_run || !(plausible || all) {
+        println!();
+        return Ok(());
+    }
+    println!(" -> {}", path.display());
+    fs::write(path, rom).map_err(|e| format!("can't write {}: {}", path.display(), e))
+}
+
+/// Uses the name of the uuencoded file if there is one, otherwise the name
+/// of the text file, numbered if there's more than one ROM in it.
+fn output_name(file: &str, payload: &Payload, n: usize, count: usize) -> String {
+    let stem = |name: &str| {
+        Path::new(name).file_stem().map_or(String::new(), |s| s.to_string_lossy().into_owned())
+    };
+    match payload.name.as_ref().map(|name| stem(name)).filter(|name| !name.is_empty()) {
+        Some(name) => format!("{}.ch8", name),
+        None if count > 1 => format!("{}-{}.ch8", stem(file), n + 1),
+        None => format!("{}.ch8", stem(file)),
+    }
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("error: {}", message);
+    eprintln!();
+    eprintln!("{}", USAGE);
+    exit(2);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_file() {
+        let out = std::env::temp_dir().join(format!("chiprs-extract-{}", std::process::id()));
+        fs::create_dir_all(&out).unwrap();
+
+        // A post with the same game uuencoded and as a hex dump.
+        assert!(extract_file("testdata/post.txt", &out, false, false));
+        let maze = fs::read("../games/MAZE.ch8").unwrap();
+        assert_eq!(fs::read(out.join("maze.ch8")).unwrap(), maze);
+        assert_eq!(fs::read(out.join("post-2.ch8")).unwrap(), maze);
+
+        // The articles in games/ are only documentation, the files they
+        // mention aren't included.
+        assert!(extract_file("../games/JOUST.txt", &out, false, false));
+        assert!(extract_file("../games/HIDDEN.txt", &out, false, false));
+        assert_eq!(fs::read_dir(&out).unwrap().count(), 2);
+
+        assert!(!extract_file("testdata/missing.txt", &out, false, false));
+        fs::remove_dir_all(&out).unwrap();
+    }
+}
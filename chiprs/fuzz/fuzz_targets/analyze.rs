@@ -0,0 +1,15 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use chiprs::analysis::Analysis;
+use chiprs::memory::MEMORY_SIZE;
+
+// Static analysis (and its output) should work for any ROM.
+fuzz_target!(|data: &[u8]| {
+    let rom = &data[..data.len().min(MEMORY_SIZE - 0x200)];
+    let analysis = Analysis::new(rom, 0x200);
+    analysis.unreachable();
+    analysis.recommend_quirks();
+    analysis.write_dot(&mut std::io::sink()).unwrap();
+    analysis.write_json(&mut std::io::sink()).unwrap();
+});
@@ -0,0 +1,20 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use chiprs::platform::PLATFORMS;
+
+// Every opcode that decodes should encode back to the same opcode, and
+// disassemble without panicking, on every platform.
+fuzz_target!(|data: &[u8]| {
+    for pair in data.chunks_exact(2) {
+        let b = ((pair[0] as u16) << 8) | pair[1] as u16;
+        for platform in PLATFORMS.iter() {
+            if let Some(instr) = platform.decode(b) {
+                assert_eq!(instr.to(), b, "{:?}", instr);
+                assert_eq!(platform.decode(instr.to()), Some(instr));
+                assert!(!instr.to_string().is_empty());
+                assert!(!instr.name().is_empty());
+            }
+        }
+    }
+});
@@ -0,0 +1,54 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use chiprs::Chip;
+use chiprs::memory::MemoryPolicy;
+use chiprs::platform::PLATFORMS;
+use chiprs::quirks::{Quirks, PRESETS};
+
+const HEADER_SIZE: usize = 2;
+const KEY_EVENTS: usize = 16;
+const MAX_STEPS: usize = 10_000;
+// Steps between key events (and timer ticks).
+const EVENT_STEPS: usize = 100;
+
+// Input: policy and platform (1 byte: policy is the value mod 3, platform the
+// rest), quirks preset (1 byte), key events (1 byte each: key
+// number in the high nibble, pressed/released in the lowest bit), and the ROM.
+// The emulator may stop with an error, but should never panic.
+fuzz_target!(|data: &[u8]| {
+    if data.len() < HEADER_SIZE + KEY_EVENTS {
+        return;
+    }
+    let (header, rest) = data.split_at(HEADER_SIZE);
+    let (events, rom) = rest.split_at(KEY_EVENTS);
+    let platform = PLATFORMS[header[0] as usize / 3 % PLATFORMS.len()];
+    let rom = &rom[..rom.len().min(platform.memory_size - 0x200)];
+
+    let mut chip = Chip::with_platform(platform);
+    chip.seed(0);
+    chip.memory_policy = match header[0] % 3 {
+        0 => MemoryPolicy::Fault,
+        1 => MemoryPolicy::Wrap,
+        _ => MemoryPolicy::Clamp,
+    };
+    chip.quirks = Quirks::preset(PRESETS[header[1] as usize % PRESETS.len()]).unwrap();
+    chip.load_font();
+    chip.memory.load_rom(rom, 0x200).unwrap();
+    chip.jump(0x200);
+
+    for step in 0..MAX_STEPS {
+        if step % EVENT_STEPS == 0 {
+            let event = events[(step / EVENT_STEPS) % KEY_EVENTS];
+            if event & 1 != 0 {
+                chip.key_down(event >> 4);
+            } else {
+                chip.key_up(event >> 4);
+            }
+            chip.tick();
+        }
+        if chip.step().is_err() {
+            break;
+        }
+    }
+});
@@ -0,0 +1,607 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+
+use crate::instr::{self, Instr};
+use crate::platform;
+
+/// How control gets from one basic block to another.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, PartialOrd, Ord)]
+pub enum EdgeKind {
+    /// Falling through to the next instruction.
+    Next,
+    /// JP instruction.
+    Jump,
+    /// Skipping the next instruction (SE, SNE, SKP, SKNP).
+    Skip,
+    /// CALL instruction (the block also falls through to the instruction
+    /// after CALL, once the subroutine returns).
+    Call,
+}
+
+impl EdgeKind {
+    fn name(self) -> &'static str {
+        match self {
+            EdgeKind::Next => "next",
+            EdgeKind::Jump => "jump",
+            EdgeKind::Skip => "skip",
+            EdgeKind::Call => "call",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Block {
+    pub start: u16,
+    /// Address after the last instruction.
+    pub end: u16,
+    pub edges: Vec<(u16, EdgeKind)>,
+}
+
+/// Instructions that behave differently depending on the interpreter.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct QuirkUsage {
+    /// SHR/SHL with X != Y (VIP shifts VY, later interpreters shift VX).
+    pub shift_xy: Vec<u16>,
+    /// Fx55/Fx65 (VIP increments I, later interpreters don't).
+    pub load_store: Vec<u16>,
+    /// Bnnn (jumps to nnn + V0, or nnn + VX on CHIP-48 and SUPER-CHIP).
+    pub jump_v0: Vec<u16>,
+}
+
+/// A guess at which interpreter a ROM was written for.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Recommendation {
+    pub preset: &'static str,
+    /// Human-readable explanations.
+    pub reasons: Vec<String>,
+}
+
+/// Static analysis of a ROM: control flow graph built by following all the
+/// branches from the entry point. Instructions are decoded as SUPER-CHIP,
+/// which includes all of CHIP-8.
+///
+/// Computed jumps (`JP V0, nnn`) can't be followed, so code reachable only
+/// through a jump table will show up as unreachable.
+pub struct Analysis {
+    pub start: u16,
+    pub end: u16,
+    /// Decoded instructions reachable from the entry point.
+    pub instrs: BTreeMap<u16, Instr>,
+    pub blocks: Vec<Block>,
+    /// Subroutine entry points, with the blocks that belong to them.
+    pub subroutines: BTreeMap<u16, Vec<u16>>,
+    /// Addresses of computed jumps (`JP V0, nnn`).
+    pub indirect_jumps: Vec<u16>,
+    /// Reachable addresses that don't contain valid instructions.
+    pub invalid: Vec<u16>,
+    /// Jump and call targets outside of the ROM.
+    pub external: Vec<u16>,
+    /// SUPER-CHIP instructions (scrolling, high resolution mode, large
+    /// sprites, flag registers).
+    pub schip: Vec<u16>,
+    /// Sprites, as (address, length), found by looking for `LD I, nnn`
+    /// followed by `DRW`.
+    pub sprites: Vec<(u16, u16)>,
+    pub quirks: QuirkUsage,
+}
+
+impl Analysis {
+    /// Analyze `rom`, loaded at `start` (also the entry point).
+    pub fn new(rom: &[u8], start: u16) -> Analysis {
+        let end = (start as usize + rom.len()).min(platform::SCHIP.memory_size) as u16;
+        let mut analysis = Analysis {
+            start,
+            end,
+            instrs: BTreeMap::new(),
+            blocks: vec![],
+            subroutines: BTreeMap::new(),
+            indirect_jumps: vec![],
+            invalid: vec![],
+            external: vec![],
+            schip: vec![],
+            sprites: vec![],
+            quirks: QuirkUsage::default(),
+        };
+        analysis.find_instrs(rom);
+        analysis.find_blocks();
+        analysis.find_subroutines();
+        analysis.find_sprites();
+        analysis
+    }
+
+    fn in_rom(&self, addr: u16) -> bool {
+        self.start <= addr && addr.checked_add(1).is_some_and(|next| next < self.end)
+    }
+
+    fn find_instrs(&mut self, rom: &[u8]) {
+        let mut queue = vec![self.start];
+        let mut seen = BTreeSet::new();
+        while let Some(addr) = queue.pop() {
+            if !seen.insert(addr) {
+                continue;
+            }
+            if !self.in_rom(addr) {
+                self.external.push(addr);
+                continue;
+            }
+
+            let offset = (addr - self.start) as usize;
+            let b = ((rom[offset] as u16) << 8) | (rom[offset + 1] as u16);
+            let instr = match platform::SCHIP.decode(b) {
+                Some(instr) => instr,
+                None => {
+                    self.invalid.push(addr);
+                    continue;
+                }
+            };
+            self.instrs.insert(addr, instr);
+
+            use crate::instr::Instr::*;
+            // Large sprites (Dxy0) are decoded by the CHIP-8 table.
+            if instr::decode(&[instr::SCHIP], b).is_some() || matches!(instr, DRW(_, _, 0)) {
+                self.schip.push(addr);
+            }
+            match instr {
+                SHR(x, y) | SHL(x, y) if x != y => self.quirks.shift_xy.push(addr),
+                LD_II_R(_) | LD_R_II(_) => self.quirks.load_store.push(addr),
+                JP_V0(_) => {
+                    self.quirks.jump_v0.push(addr);
+                    self.indirect_jumps.push(addr);
+                }
+                _ => (),
+            }
+            for (target, _) in successors(addr, instr) {
+                queue.push(target);
+            }
+        }
+        self.external.sort();
+        self.invalid.sort();
+        self.schip.sort();
+        self.indirect_jumps.sort();
+    }
+
+    fn find_blocks(&mut self) {
+        // Block starts: entry point, and all branch targets.
+        let mut leaders = BTreeSet::new();
+        leaders.insert(self.start);
+        for (&addr, &instr) in self.instrs.iter() {
+            let succ = successors(addr, instr);
+            if ends_block(instr) {
+                for (target, _) in succ {
+                    leaders.insert(target);
+                }
+            }
+        }
+        for &addr in self.invalid.iter() {
+            leaders.insert(addr.wrapping_add(2));
+        }
+
+        for &leader in leaders.iter() {
+            if !self.instrs.contains_key(&leader) {
+                continue;
+            }
+            let mut addr = leader;
+            let edges = loop {
+                let instr = self.instrs[&addr];
+                let next = addr.wrapping_add(2);
+                if ends_block(instr) {
+                    break successors(addr, instr);
+                }
+                if leaders.contains(&next) || !self.instrs.contains_key(&next) {
+                    break successors(addr, instr);
+                }
+                addr = next;
+            };
+            self.blocks.push(Block {
+                start: leader,
+                end: addr.wrapping_add(2),
+                edges,
+            });
+        }
+    }
+
+    fn find_subroutines(&mut self) {
+        let mut entries = BTreeSet::new();
+        for block in self.blocks.iter() {
+            for &(target, kind) in block.edges.iter() {
+                if kind == EdgeKind::Call {
+                    entries.insert(target);
+                }
+            }
+        }
+
+        for entry in entries {
+            // Collect blocks reachable from the entry, not following calls.
+            let mut queue = vec![entry];
+            let mut seen = BTreeSet::new();
+            while let Some(addr) = queue.pop() {
+                if !seen.insert(addr) {
+                    continue;
+                }
+                if let Some(block) = self.block_at(addr) {
+                    for &(target, kind) in block.edges.iter() {
+                        if kind != EdgeKind::Call {
+                            queue.push(target);
+                        }
+                    }
+                }
+            }
+            let blocks = seen.into_iter().filter(|&a| self.block_at(a).is_some()).collect();
+            self.subroutines.insert(entry, blocks);
+        }
+    }
+
+    fn find_sprites(&mut self) {
+        // Value of I at the start of each block: None if not known yet,
+        // Some(None) if it can't be determined. This is propagated along
+        // the edges (including calls, since I is often set up before calling
+        // a drawing subroutine) until nothing changes.
+        let mut entry_i: BTreeMap<u16, Option<Option<u16>>> = BTreeMap::new();
+        entry_i.insert(self.start, Some(None));
+        let mut sprites = BTreeSet::new();
+        let mut queue = vec![self.start];
+        while let Some(start) = queue.pop() {
+            let block = match self.block_at(start) {
+                Some(block) => block,
+                None => continue,
+            };
+            let mut i = entry_i[&start].unwrap_or(None);
+            for addr in (block.start..block.end).step_by(2) {
+                use crate::instr::Instr::*;
+                match self.instrs[&addr] {
+                    LD_I_A(a) => i = Some(a),
+                    DRW(_, _, n) => {
+                        if let Some(a) = i {
+                            // Dxy0 draws a 16x16 sprite.
+                            sprites.insert((a, if n == 0 { 32 } else { n as u16 }));
+                        }
+                    }
+                    ADD_I_R(_) | LD_F_R(_) | LD_HF_R(_) | LD_II_R(_) | LD_R_II(_) => i = None,
+                    _ => (),
+                }
+            }
+            let call = block.edges.iter().any(|&(_, kind)| kind == EdgeKind::Call);
+            for &(target, kind) in block.edges.iter() {
+                // The subroutine might have changed I.
+                let i = if call && kind == EdgeKind::Next { None } else { i };
+                let entry = entry_i.entry(target).or_insert(None);
+                let merged = match *entry {
+                    None => Some(i),
+                    Some(old) if old == i => continue,
+                    Some(_) => Some(None),
+                };
+                if *entry != merged {
+                    *entry = merged;
+                    queue.push(target);
+                }
+            }
+        }
+
+        // Merge sprites with the same address, keeping the longest one.
+        let mut merged: BTreeMap<u16, u16> = BTreeMap::new();
+        for (a, n) in sprites {
+            let len = merged.entry(a).or_insert(0);
+            *len = (*len).max(n);
+        }
+        self.sprites = merged.into_iter().collect();
+    }
+
+    pub fn block_at(&self, addr: u16) -> Option<&Block> {
+        self.blocks.iter().find(|b| b.start == addr)
+    }
+
+    /// Address ranges of the ROM that are neither reachable code nor known
+    /// sprite data.
+    pub fn unreachable(&self) -> Vec<(u16, u16)> {
+        let mut used = vec![false; self.end.saturating_sub(self.start) as usize];
+        let mut mark = |a: u16, n: u16| {
+            for addr in a..a.saturating_add(n) {
+                if self.start <= addr && addr < self.end {
+                    used[(addr - self.start) as usize] = true;
+                }
+            }
+        };
+        for &addr in self.instrs.keys() {
+            mark(addr, 2);
+        }
+        for &(a, n) in self.sprites.iter() {
+            mark(a, n);
+        }
+
+        let mut result = vec![];
+        let mut run_start = None;
+        for (offset, &u) in used.iter().enumerate() {
+            let addr = self.start + offset as u16;
+            match (u, run_start) {
+                (false, None) => run_start = Some(addr),
+                (true, Some(s)) => {
+                    result.push((s, addr));
+                    run_start = None;
+                }
+                _ => (),
+            }
+        }
+        if let Some(s) = run_start {
+            result.push((s, self.end));
+        }
+        result
+    }
+
+    /// Recommends a quirks preset (one of `quirks::PRESETS`), based on
+    /// which instructions the ROM uses and how. This is only a guess: most
+    /// ROMs don't use any of the quirk-sensitive instructions in a way that
+    /// shows which interpreter they were written for.
+    pub fn recommend_quirks(&self) -> Recommendation {
+        let mut reasons = vec![];
+        if let Some(&addr) = self.schip.first() {
+            reasons.push(format!("SUPER-CHIP instruction at {:04X}", addr));
+            return Recommendation { preset: "schip", reasons };
+        }
+
+        let mut vip = 0;
+        let mut chip48 = 0;
+        use crate::instr::Instr::*;
+
+        // Programs written for later interpreters use SHR/SHL with one
+        // register, the second one is usually left as 0 (or equal to X).
+        if let Some(&addr) = self.quirks.shift_xy.first() {
+            vip += 1;
+            reasons.push(format!("shift with two different registers at {:04X}", addr));
+        }
+
+        // Storing or loading registers, and then using I again without
+        // setting it, only makes sense if I stays the same.
+        for &addr in self.quirks.load_store.iter() {
+            let reused = self.rest_of_block(addr).into_iter().map(|(_, instr)| instr).find(|instr| {
+                matches!(instr, LD_I_A(_) | LD_F_R(_) | LD_HF_R(_) | LD_II_R(_) | LD_R_II(_) |
+                         DRW(..) | LD_B_R(_) | ADD_I_R(_))
+            });
+            if let Some(LD_II_R(_)) | Some(LD_R_II(_)) | Some(DRW(..)) | Some(LD_B_R(_)) = reused {
+                chip48 += 1;
+                reasons.push(format!("I reused after load/store at {:04X}", addr));
+                break;
+            }
+        }
+
+        // For Bnnn, look at which register was set just before the jump.
+        for &addr in self.quirks.jump_v0.iter() {
+            let nnn = match self.instrs[&addr] {
+                JP_V0(nnn) => nnn,
+                _ => continue,
+            };
+            let x = (nnn >> 8) as u8;
+            let block = self.blocks.iter().find(|b| b.start <= addr && addr < b.end);
+            let written = block.and_then(|block| {
+                (block.start..addr).step_by(2).rev()
+                    .filter_map(|a| written_register(self.instrs[&a]))
+                    .find(|&r| r == 0 || r == x)
+            });
+            match written {
+                Some(0) => {
+                    vip += 1;
+                    reasons.push(format!("V0 set before jump at {:04X}", addr));
+                }
+                Some(_) => {
+                    chip48 += 1;
+                    reasons.push(format!("V{:X} set before jump at {:04X}", x, addr));
+                }
+                None => (),
+            }
+        }
+
+        let preset = if vip > chip48 {
+            "vip"
+        } else if chip48 > vip {
+            "chip48"
+        } else {
+            "default"
+        };
+        Recommendation { preset, reasons }
+    }
+
+    // Instructions following `addr`, until the end of its block.
+    fn rest_of_block(&self, addr: u16) -> Vec<(u16, Instr)> {
+        match self.blocks.iter().find(|b| b.start <= addr && addr < b.end) {
+            Some(block) => (addr + 2..block.end).step_by(2).map(|a| (a, self.instrs[&a])).collect(),
+            None => vec![],
+        }
+    }
+
+    /// Writes the control flow graph in Graphviz format.
+    pub fn write_dot(&self, w: &mut dyn Write) -> io::Result<()> {
+        writeln!(w, "digraph cfg {{")?;
+        writeln!(w, "    node [shape=box, fontname=monospace];")?;
+        for block in self.blocks.iter() {
+            let mut label = String::new();
+            if self.subroutines.contains_key(&block.start) {
+                label += &format!("sub_{:04X}:\\l", block.start);
+            }
+            for addr in (block.start..block.end).step_by(2) {
+                label += &format!("{:04X}  {}\\l", addr, self.instrs[&addr]);
+            }
+            writeln!(w, "    b{:04X} [label=\"{}\"];", block.start, label)?;
+        }
+        for block in self.blocks.iter() {
+            for &(target, kind) in block.edges.iter() {
+                if self.block_at(target).is_some() {
+                    writeln!(w, "    b{:04X} -> b{:04X} [label=\"{}\"];",
+                             block.start, target, kind.name())?;
+                }
+            }
+            if self.indirect_jumps.contains(&(block.end - 2)) {
+                writeln!(w, "    b{:04X} -> indirect [style=dashed];", block.start)?;
+            }
+        }
+        if !self.indirect_jumps.is_empty() {
+            writeln!(w, "    indirect [label=\"(computed jump)\", shape=none];")?;
+        }
+        writeln!(w, "}}")
+    }
+
+    /// Writes a summary in JSON format.
+    pub fn write_json(&self, w: &mut dyn Write) -> io::Result<()> {
+        let addrs = |addrs: &[u16]| -> String {
+            let items: Vec<String> = addrs.iter().map(|a| format!("\"0x{:03X}\"", a)).collect();
+            format!("[{}]", items.join(", "))
+        };
+        let ranges = |ranges: &[(u16, u16)], key: &str| -> String {
+            let items: Vec<String> = ranges.iter()
+                .map(|&(a, b)| format!("{{\"start\": \"0x{:03X}\", \"{}\": {}}}", a, key, b))
+                .collect();
+            format!("[{}]", items.join(", "))
+        };
+        let unreachable: Vec<(u16, u16)> = self.unreachable().iter()
+            .map(|&(a, b)| (a, b - a)).collect();
+        let subroutines: Vec<u16> = self.subroutines.keys().cloned().collect();
+
+        writeln!(w, "{{")?;
+        writeln!(w, "  \"start\": \"0x{:03X}\",", self.start)?;
+        writeln!(w, "  \"size\": {},", self.end.saturating_sub(self.start))?;
+        writeln!(w, "  \"instructions\": {},", self.instrs.len())?;
+        writeln!(w, "  \"blocks\": {},", self.blocks.len())?;
+        writeln!(w, "  \"subroutines\": {},", addrs(&subroutines))?;
+        writeln!(w, "  \"indirect_jumps\": {},", addrs(&self.indirect_jumps))?;
+        writeln!(w, "  \"invalid\": {},", addrs(&self.invalid))?;
+        writeln!(w, "  \"external\": {},", addrs(&self.external))?;
+        writeln!(w, "  \"schip\": {},", addrs(&self.schip))?;
+        writeln!(w, "  \"sprites\": {},", ranges(&self.sprites, "height"))?;
+        writeln!(w, "  \"unreachable\": {},", ranges(&unreachable, "length"))?;
+        writeln!(w, "  \"quirks\": {{")?;
+        writeln!(w, "    \"shift_xy\": {},", addrs(&self.quirks.shift_xy))?;
+        writeln!(w, "    \"load_store\": {},", addrs(&self.quirks.load_store))?;
+        writeln!(w, "    \"jump_v0\": {}", addrs(&self.quirks.jump_v0))?;
+        writeln!(w, "  }},")?;
+        let recommendation = self.recommend_quirks();
+        let reasons: Vec<String> = recommendation.reasons.iter()
+            .map(|r| format!("\"{}\"", r)).collect();
+        writeln!(w, "  \"recommended_quirks\": {{")?;
+        writeln!(w, "    \"preset\": \"{}\",", recommendation.preset)?;
+        writeln!(w, "    \"reasons\": [{}]", reasons.join(", "))?;
+        writeln!(w, "  }}")?;
+        writeln!(w, "}}")
+    }
+}
+
+/// Possible next instructions after executing `instr` at `addr`.
+fn successors(addr: u16, instr: Instr) -> Vec<(u16, EdgeKind)> {
+    use crate::instr::Instr::*;
+    let next = addr.wrapping_add(2);
+    match instr {
+        JP(a) => vec![(a, EdgeKind::Jump)],
+        CALL(a) => vec![(a, EdgeKind::Call), (next, EdgeKind::Next)],
+        RET | JP_V0(_) | EXIT => vec![],
+        SE(..) | SNE(..) | SE_R(..) | SNE_R(..) | SKP(_) | SKNP(_) =>
+            vec![(next, EdgeKind::Next), (addr.wrapping_add(4), EdgeKind::Skip)],
+        _ => vec![(next, EdgeKind::Next)],
+    }
+}
+
+// Register changed by an instruction, if any.
+fn written_register(instr: Instr) -> Option<u8> {
+    use crate::instr::Instr::*;
+    match instr {
+        LD_R_B(x, _) | ADD_R_B(x, _) | LD_R_R(x, _) | OR(x, _) | AND(x, _) | XOR(x, _) |
+        ADD(x, _) | SUB(x, _) | SHR(x, _) | SUBN(x, _) | SHL(x, _) | RND(x, _) |
+        LD_R_DT(x) | LD_R_K(x) => Some(x),
+        _ => None,
+    }
+}
+
+fn ends_block(instr: Instr) -> bool {
+    use crate::instr::Instr::*;
+    matches!(instr, JP(_) | CALL(_) | RET | JP_V0(_) | EXIT |
+             SE(..) | SNE(..) | SE_R(..) | SNE_R(..) | SKP(_) | SKNP(_))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instr::Instr::*;
+
+    fn assemble(program: &[Instr]) -> Vec<u8> {
+        program.iter().flat_map(|i| {
+            let b = i.to();
+            vec![(b >> 8) as u8, b as u8]
+        }).collect()
+    }
+
+    #[test]
+    fn test_analysis() {
+        let mut rom = assemble(&[
+            // 0x200
+            LD_I_A(0x214),
+            CALL(0x20C),
+            SE(0, 1),
+            JP(0x200),
+            // 0x208
+            JP_V0(0x300),
+            // 0x20A: unreachable
+            CLS,
+            // 0x20C
+            SHR(1, 2),
+            DRW(0, 0, 2),
+            LD_R_II(1),
+            RET,
+        ]);
+        // 0x214: sprite
+        rom.extend(&[0xFF, 0x81]);
+
+        let analysis = Analysis::new(&rom, 0x200);
+        assert_eq!(analysis.instrs.len(), 9);
+        assert_eq!(analysis.indirect_jumps, vec![0x208]);
+        assert_eq!(analysis.block_at(0x200), Some(&Block {
+            start: 0x200,
+            end: 0x204,
+            edges: vec![(0x20C, EdgeKind::Call), (0x204, EdgeKind::Next)],
+        }));
+        assert_eq!(analysis.block_at(0x204), Some(&Block {
+            start: 0x204,
+            end: 0x206,
+            edges: vec![(0x206, EdgeKind::Next), (0x208, EdgeKind::Skip)],
+        }));
+        assert_eq!(analysis.subroutines[&0x20C], vec![0x20C]);
+        assert_eq!(analysis.sprites, vec![(0x214, 2)]);
+        assert_eq!(analysis.unreachable(), vec![(0x20A, 0x20C)]);
+        assert_eq!(analysis.quirks, QuirkUsage {
+            shift_xy: vec![0x20C],
+            load_store: vec![0x210],
+            jump_v0: vec![0x208],
+        });
+    }
+
+    #[test]
+    fn test_recommend_quirks() {
+        let recommend = |program: &[Instr]| Analysis::new(&assemble(program), 0x200)
+            .recommend_quirks().preset;
+
+        assert_eq!(recommend(&[LD_R_B(0, 1), JP(0x202)]), "default");
+        assert_eq!(recommend(&[SHR(1, 2), JP(0x202)]), "vip");
+        assert_eq!(recommend(&[LD_I_A(0x300), LD_II_R(3), LD_R_II(3), JP(0x206)]), "chip48");
+        assert_eq!(recommend(&[LD_R_B(0, 2), JP_V0(0x300)]), "vip");
+        assert_eq!(recommend(&[LD_R_B(3, 2), JP_V0(0x300)]), "chip48");
+
+        // SUPER-CHIP: high resolution mode (00FF), then scroll (00C4).
+        let mut rom = vec![0x00, 0xFF, 0x00, 0xC4, 0xF0, 0x30];
+        rom.extend(assemble(&[JP(0x206)]));
+        let analysis = Analysis::new(&rom, 0x200);
+        assert_eq!(analysis.schip, vec![0x200, 0x202, 0x204]);
+        assert_eq!(analysis.instrs[&0x204], LD_HF_R(0));
+        assert!(analysis.invalid.is_empty());
+        assert!(analysis.instrs.contains_key(&0x206));
+        assert_eq!(analysis.recommend_quirks().preset, "schip");
+    }
+
+    #[test]
+    fn test_outside_memory() {
+        let rom = assemble(&[CLS, JP(0x200)]);
+        for &start in &[0x1000, 0xFFFF] {
+            let analysis = Analysis::new(&rom, start);
+            assert!(analysis.instrs.is_empty());
+            assert_eq!(analysis.external, vec![start]);
+            assert_eq!(analysis.unreachable(), vec![]);
+            let mut json = vec![];
+            analysis.write_json(&mut json).unwrap();
+        }
+    }
+}
@@ -0,0 +1,481 @@
+/// Everything the CPU is connected to: memory, I/O ports and the EF flag
+/// inputs. Only memory access is required.
+pub trait Bus {
+    fn read(&mut self, addr: u16) -> u8;
+    fn write(&mut self, addr: u16, value: u8);
+
+    /// OUT instruction (`port` is 1-7).
+    fn output(&mut self, _port: u8, _value: u8) {}
+
+    /// INP instruction (`port` is 1-7).
+    fn input(&mut self, _port: u8) -> u8 {
+        0
+    }
+
+    /// Whether the EF line (1-4) is asserted.
+    fn ef(&self, _line: u8) -> bool {
+        false
+    }
+}
+
+/// The RCA CDP1802 CPU. Timing is counted in machine cycles (8 clock
+/// cycles each): 2 for most instructions, 3 for long branches and skips.
+pub struct Cpu {
+    /// Scratchpad registers. Any of them can be the program counter (P) or
+    /// the data pointer (X).
+    pub r: [u16; 16],
+    pub p: u8,
+    pub x: u8,
+    pub d: u8,
+    pub df: bool,
+    /// X and P saved on interrupt.
+    pub t: u8,
+    /// Interrupt enable.
+    pub ie: bool,
+    pub q: bool,
+    /// Stopped by IDL, until an interrupt or DMA.
+    pub idle: bool,
+}
+
+impl Default for Cpu {
+    fn default() -> Cpu {
+        Cpu::new()
+    }
+}
+
+impl Cpu {
+    pub fn new() -> Cpu {
+        let mut cpu = Cpu {
+            r: [0; 16],
+            p: 0,
+            x: 0,
+            d: 0,
+            df: false,
+            t: 0,
+            ie: true,
+            q: false,
+            idle: false,
+        };
+        cpu.reset();
+        cpu
+    }
+
+    /// State after the CLEAR input: execution starts at 0 with R0 as the
+    /// program counter. Other registers are left as they were.
+    pub fn reset(&mut self) {
+        self.r[0] = 0;
+        self.p = 0;
+        self.x = 0;
+        self.q = false;
+        self.ie = true;
+        self.idle = false;
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.r[self.p as usize]
+    }
+
+    /// Takes an interrupt, if they are enabled. Returns the number of
+    /// machine cycles spent.
+    pub fn interrupt(&mut self) -> u32 {
+        if !self.ie {
+            return 0;
+        }
+        self.t = (self.x << 4) | self.p;
+        self.p = 1;
+        self.x = 2;
+        self.ie = false;
+        self.idle = false;
+        1
+    }
+
+    /// One DMA output cycle: reads the byte at R0 for a device, and
+    /// advances R0.
+    pub fn dma_out(&mut self, bus: &mut dyn Bus) -> u8 {
+        let value = bus.read(self.r[0]);
+        self.r[0] = self.r[0].wrapping_add(1);
+        self.idle = false;
+        value
+    }
+
+    /// Executes one instruction. Returns the number of machine cycles spent.
+    pub fn step(&mut self, bus: &mut dyn Bus) -> u32 {
+        if self.idle {
+            return 1;
+        }
+        let opcode = self.fetch(bus);
+        let (i, n) = (opcode >> 4, opcode & 0xF);
+        let rn = n as usize;
+        let rx = self.x as usize;
+        match i {
+            0x0 if n == 0 => self.idle = true,
+            0x0 => self.d = bus.read(self.r[rn]),
+            0x1 => self.r[rn] = self.r[rn].wrapping_add(1),
+            0x2 => self.r[rn] = self.r[rn].wrapping_sub(1),
+            0x3 => {
+                let taken = match n & 7 {
+                    0 => true,
+                    1 => self.q,
+                    2 => self.d == 0,
+                    3 => self.df,
+                    line => bus.ef(line - 3),
+                };
+                // 38 (SKP) is the negation of BR.
+                self.short_branch(bus, taken != (n >= 8));
+            }
+            0x4 => {
+                self.d = bus.read(self.r[rn]);
+                self.r[rn] = self.r[rn].wrapping_add(1);
+            }
+            0x5 => bus.write(self.r[rn], self.d),
+            0x6 => match n {
+                0x0 => self.r[rx] = self.r[rx].wrapping_add(1),
+                0x1..=0x7 => {
+                    let value = bus.read(self.r[rx]);
+                    bus.output(n, value);
+                    self.r[rx] = self.r[rx].wrapping_add(1);
+                }
+                // 68 is not an 1802 instruction.
+                0x8 => (),
+                _ => {
+                    let value = bus.input(n - 8);
+                    bus.write(self.r[rx], value);
+                    self.d = value;
+                }
+            },
+            0x7 => self.run_7(bus, n),
+            0x8 => self.d = self.r[rn] as u8,
+            0x9 => self.d = (self.r[rn] >> 8) as u8,
+            0xA => self.r[rn] = (self.r[rn] & 0xFF00) | self.d as u16,
+            0xB => self.r[rn] = (self.r[rn] & 0x00FF) | ((self.d as u16) << 8),
+            0xC => return self.long_branch(bus, n),
+            0xD => self.p = n,
+            0xE => self.x = n,
+            _ => self.run_f(bus, n),
+        }
+        2
+    }
+
+    fn fetch(&mut self, bus: &mut dyn Bus) -> u8 {
+        let p = self.p as usize;
+        let value = bus.read(self.r[p]);
+        self.r[p] = self.r[p].wrapping_add(1);
+        value
+    }
+
+    fn short_branch(&mut self, bus: &mut dyn Bus, taken: bool) {
+        let p = self.p as usize;
+        if taken {
+            let low = bus.read(self.r[p]);
+            self.r[p] = (self.r[p] & 0xFF00) | low as u16;
+        } else {
+            self.r[p] = self.r[p].wrapping_add(1);
+        }
+    }
+
+    fn long_branch(&mut self, bus: &mut dyn Bus, n: u8) -> u32 {
+        let p = self.p as usize;
+        let condition = match n & 3 {
+            0 => true,
+            1 => self.q,
+            2 => self.d == 0,
+            _ => self.df,
+        };
+        match n {
+            // NOP.
+            0x4 => (),
+            // Long skips: C5-C7 skip if the condition is false, CC-CF if
+            // it's true, C8 always.
+            0x5..=0x7 => if !condition { self.r[p] = self.r[p].wrapping_add(2) },
+            0xC => if self.ie { self.r[p] = self.r[p].wrapping_add(2) },
+            0xD..=0xF => if condition { self.r[p] = self.r[p].wrapping_add(2) },
+            0x8 => self.r[p] = self.r[p].wrapping_add(2),
+            // Long branches: C0-C3 if the condition is true, C9-CB if it's
+            // false.
+            _ => {
+                if condition != (n >= 8) {
+                    let high = bus.read(self.r[p]);
+                    let low = bus.read(self.r[p].wrapping_add(1));
+                    self.r[p] = ((high as u16) << 8) | low as u16;
+                } else {
+                    self.r[p] = self.r[p].wrapping_add(2);
+                }
+            }
+        }
+        3
+    }
+
+    fn run_7(&mut self, bus: &mut dyn Bus, n: u8) {
+        let rx = self.x as usize;
+        match n {
+            // RET, DIS
+            0x0 | 0x1 => {
+                let value = bus.read(self.r[rx]);
+                self.r[rx] = self.r[rx].wrapping_add(1);
+                self.x = value >> 4;
+                self.p = value & 0xF;
+                self.ie = n == 0;
+            }
+            // LDXA
+            0x2 => {
+                self.d = bus.read(self.r[rx]);
+                self.r[rx] = self.r[rx].wrapping_add(1);
+            }
+            // STXD
+            0x3 => {
+                bus.write(self.r[rx], self.d);
+                self.r[rx] = self.r[rx].wrapping_sub(1);
+            }
+            // ADC, SDB, SMB
+            0x4 => self.add(bus.read(self.r[rx]), self.d, self.df),
+            0x5 => self.add(bus.read(self.r[rx]), !self.d, self.df),
+            0x7 => self.add(self.d, !bus.read(self.r[rx]), self.df),
+            // SHRC, SHLC
+            0x6 => {
+                let carry = self.d & 1 != 0;
+                self.d = (self.d >> 1) | ((self.df as u8) << 7);
+                self.df = carry;
+            }
+            0xE => {
+                let carry = self.d & 0x80 != 0;
+                self.d = (self.d << 1) | self.df as u8;
+                self.df = carry;
+            }
+            // SAV
+            0x8 => bus.write(self.r[rx], self.t),
+            // MARK
+            0x9 => {
+                self.t = (self.x << 4) | self.p;
+                bus.write(self.r[2], self.t);
+                self.x = self.p;
+                self.r[2] = self.r[2].wrapping_sub(1);
+            }
+            // REQ, SEQ
+            0xA => self.q = false,
+            0xB => self.q = true,
+            // ADCI, SDBI, SMBI
+            _ => {
+                let value = self.fetch(bus);
+                match n {
+                    0xC => self.add(value, self.d, self.df),
+                    0xD => self.add(value, !self.d, self.df),
+                    _ => self.add(self.d, !value, self.df),
+                }
+            }
+        }
+    }
+
+    fn run_f(&mut self, bus: &mut dyn Bus, n: u8) {
+        // F8-FF are the same as F0-F7, but with an immediate operand
+        // (except for the shifts).
+        let value = match n {
+            0x6 | 0xE => 0,
+            0x0..=0x7 => bus.read(self.r[self.x as usize]),
+            _ => self.fetch(bus),
+        };
+        match n & 7 {
+            0x0 => self.d = value,
+            0x1 => self.d |= value,
+            0x2 => self.d &= value,
+            0x3 => self.d ^= value,
+            0x4 => self.add(value, self.d, false),
+            0x5 => self.add(value, !self.d, true),
+            0x6 if n == 0x6 => {
+                self.df = self.d & 1 != 0;
+                self.d >>= 1;
+            }
+            0x6 => {
+                self.df = self.d & 0x80 != 0;
+                self.d <<= 1;
+            }
+            _ => self.add(self.d, !value, true),
+        }
+    }
+
+    /// Sets D to a + b + carry, and DF to the carry out. Subtraction is
+    /// addition of the complement, so DF = 1 means no borrow.
+    fn add(&mut self, a: u8, b: u8, carry: bool) {
+        let sum = a as u16 + b as u16 + carry as u16;
+        self.d = sum as u8;
+        self.df = sum > 0xFF;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestBus {
+        memory: Vec<u8>,
+        out: Vec<(u8, u8)>,
+        ef: [bool; 4],
+    }
+
+    impl TestBus {
+        fn new(program: &[u8]) -> TestBus {
+            let mut memory = vec![0; 0x100];
+            memory[..program.len()].copy_from_slice(program);
+            TestBus { memory, out: vec![], ef: [false; 4] }
+        }
+    }
+
+    impl Bus for TestBus {
+        fn read(&mut self, addr: u16) -> u8 {
+            self.memory[addr as usize & 0xFF]
+        }
+
+        fn write(&mut self, addr: u16, value: u8) {
+            self.memory[addr as usize & 0xFF] = value;
+        }
+
+        fn output(&mut self, port: u8, value: u8) {
+            self.out.push((port, value));
+        }
+
+        fn input(&mut self, port: u8) -> u8 {
+            0x10 + port
+        }
+
+        fn ef(&self, line: u8) -> bool {
+            self.ef[line as usize - 1]
+        }
+    }
+
+    // Runs until the program reaches IDL.
+    fn run(cpu: &mut Cpu, bus: &mut TestBus) -> u32 {
+        let mut cycles = 0;
+        while !cpu.idle {
+            cycles += cpu.step(bus);
+        }
+        cycles
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let mut bus = TestBus::new(&[
+            0xF8, 0x80,       // LDI 80
+            0xFC, 0x90,       // ADI 90
+            0xA3,             // PLO 3
+            0x7C, 0x01,       // ADCI 01
+            0xA4,             // PLO 4
+            0xF8, 0x05,       // LDI 05
+            0xFF, 0x07,       // SMI 07
+            0xB5,             // PHI 5
+            0x7F, 0x00,       // SMBI 00
+            0xA5,             // PLO 5
+            0xF8, 0x81,       // LDI 81
+            0xF6,             // SHR
+            0x76,             // SHRC
+            0xA6,             // PLO 6
+            0xF8, 0x0F,       // LDI 0F
+            0xFA, 0x3C,       // ANI 3C
+            0xFB, 0xFF,       // XRI FF
+            0xA7,             // PLO 7
+            0x00,             // IDL
+        ]);
+        let mut cpu = Cpu::new();
+        let cycles = run(&mut cpu, &mut bus);
+        assert_eq!(cycles, 19 * 2);
+        assert_eq!(cpu.r[3], 0x10);
+        assert_eq!(cpu.r[4], 0x12);
+        // 5 - 7 borrows, then 0xFE - 0 - borrow.
+        assert_eq!(cpu.r[5], 0xFEFD);
+        assert_eq!(cpu.r[6], 0xA0);
+        assert!(!cpu.df);
+        assert_eq!(cpu.r[7], 0xF3);
+    }
+
+    #[test]
+    fn test_branches() {
+        let mut bus = TestBus::new(&[
+            0xF8, 0x00,       // LDI 00
+            0x3A, 0x10,       // BNZ 10
+            0x32, 0x08,       // BZ 08
+            0x00,             // IDL
+            0x00,             // IDL
+            0x35, 0x00,       // B2 00
+            0x3D, 0x0E,       // BN2 0E
+            0x00,             // IDL
+            0x00,             // IDL
+            0x7B,             // SEQ
+            0xC5,             // LSNQ
+            0xC1, 0x00, 0x20, // LBQ 0020
+        ]);
+        bus.memory[0x20..0x25].copy_from_slice(&[
+            0x38,             // SKP
+            0x00,             // IDL
+            0xCA, 0x00, 0x00, // LBNZ 0000
+        ]);
+        bus.memory[0x25] = 0x00;
+        bus.ef[0] = true;
+        let mut cpu = Cpu::new();
+        let cycles = run(&mut cpu, &mut bus);
+        assert_eq!(cpu.pc(), 0x26);
+        assert_eq!(cycles, 8 * 2 + 3 * 3);
+    }
+
+    #[test]
+    fn test_subroutines_and_io() {
+        let mut bus = TestBus::new(&[
+            0xF8, 0x40,       // LDI 40
+            0xA2,             // PLO 2
+            0xE2,             // SEX 2
+            0x6B,             // INP 3
+            0x65,             // OUT 5
+            0x22,             // DEC 2
+            0xF8, 0x30,       // LDI 30
+            0xA3,             // PLO 3
+            0x79,             // MARK
+            0xD3,             // SEP 3
+            0x00,             // IDL
+        ]);
+        bus.memory[0x30..0x33].copy_from_slice(&[
+            0xE2,             // SEX 2
+            0x12,             // INC 2
+            0x70,             // RET
+        ]);
+        let mut cpu = Cpu::new();
+        run(&mut cpu, &mut bus);
+        assert_eq!(bus.out, vec![(5, 0x13)]);
+        // MARK saved X=2, P=0 over the input byte, and RET restored them.
+        assert_eq!(bus.memory[0x40], 0x20);
+        assert_eq!((cpu.x, cpu.p), (2, 0));
+        assert_eq!(cpu.r[2], 0x41);
+        assert_eq!(cpu.pc(), 0x0D);
+        assert!(cpu.ie);
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let mut bus = TestBus::new(&[
+            0xF8, 0x10,       // LDI 10
+            0xA1,             // PLO 1
+            0x30, 0x03,       // BR 03
+        ]);
+        bus.memory[0x10..0x13].copy_from_slice(&[
+            0x78,             // SAV
+            0x7B,             // SEQ
+            0x70,             // RET
+        ]);
+        let mut cpu = Cpu::new();
+        cpu.r[2] = 0x50;
+        for _ in 0..3 {
+            cpu.step(&mut bus);
+        }
+        assert_eq!(cpu.interrupt(), 1);
+        assert_eq!(cpu.interrupt(), 0);
+        assert_eq!((cpu.x, cpu.p), (2, 1));
+        for _ in 0..3 {
+            cpu.step(&mut bus);
+        }
+        assert_eq!(bus.memory[0x50], 0x00);
+        assert!(cpu.q);
+        assert!(cpu.ie);
+        assert_eq!((cpu.x, cpu.p), (0, 0));
+        assert_eq!(cpu.pc(), 0x03);
+
+        cpu.r[0] = 0x10;
+        assert_eq!(cpu.dma_out(&mut bus), 0x78);
+        assert_eq!(cpu.r[0], 0x11);
+    }
+}
@@ -0,0 +1,751 @@
+//! A compiler for Octo, the assembly language of Octo cartridges (see
+//! `octo`). It handles the core language: statements, `if`/`loop` control
+//! flow, labels, and the `:const`, `:alias`, `:unpack`, `:next`, `:org`,
+//! `:byte` and `:call` directives. Macros, `:calc` expressions and
+//! `:stringmode` are not supported.
+
+use std::collections::HashMap;
+
+use crate::instr::Instr::{self, *};
+
+/// Where programs are loaded, and start executing.
+const START: usize = 0x200;
+
+/// Compiles `source` to a ROM loaded at 0x200. If the program doesn't start
+/// with the `main` label, it starts with a jump to it.
+pub fn compile(source: &str) -> Result<Vec<u8>, String> {
+    let mut compiler = Compiler::new(source);
+    compiler.compile()?;
+    Ok(compiler.rom)
+}
+
+struct Token<'a> {
+    text: &'a str,
+    line: usize,
+}
+
+/// Operand that can be a label defined later.
+enum Value {
+    Number(i32),
+    Label(String),
+}
+
+#[derive(Clone, Copy)]
+enum Fixup {
+    /// 12-bit address in the low bits of an instruction.
+    Nnn,
+    /// 16-bit address.
+    Long,
+    /// Parts of an address (for `:unpack`).
+    HighNibble,
+    HighByte,
+    LowByte,
+}
+
+enum Block {
+    /// `if ... begin`, with the jump to the `else` part (or the end).
+    If(usize),
+    /// `else`, with the jump from the `if` part to the end.
+    Else(usize),
+    /// `loop`, with its start and the jumps out of it (from `while`).
+    Loop(usize, Vec<usize>),
+}
+
+#[derive(Clone, Copy)]
+enum Operand {
+    Reg(u8),
+    Byte(u8),
+}
+
+#[derive(Clone, Copy)]
+enum Cond {
+    Eq(u8, Operand),
+    Ne(u8, Operand),
+    Key(u8),
+    NotKey(u8),
+    Lt(u8, Operand),
+    Gt(u8, Operand),
+    Le(u8, Operand),
+    Ge(u8, Operand),
+}
+
+const VF: u8 = 0xF;
+
+struct Compiler<'a> {
+    tokens: Vec<Token<'a>>,
+    pos: usize,
+    rom: Vec<u8>,
+    // Address of the next byte.
+    here: usize,
+    labels: HashMap<String, u16>,
+    consts: HashMap<String, i32>,
+    aliases: HashMap<String, u8>,
+    // Operands to fill in once all labels are known: position in `rom`,
+    // label, kind, and line for errors.
+    fixups: Vec<(usize, String, Fixup, usize)>,
+    blocks: Vec<Block>,
+}
+
+impl<'a> Compiler<'a> {
+    fn new(source: &'a str) -> Compiler<'a> {
+        let tokens = source.lines().enumerate()
+            .flat_map(|(i, line)| {
+                let code = line.split('#').next().unwrap();
+                code.split_whitespace().map(move |text| Token { text, line: i + 1 })
+            })
+            .collect();
+        Compiler {
+            tokens,
+            pos: 0,
+            rom: vec![],
+            here: START,
+            labels: HashMap::new(),
+            consts: HashMap::new(),
+            aliases: HashMap::new(),
+            fixups: vec![],
+            blocks: vec![],
+        }
+    }
+
+    fn compile(&mut self) -> Result<(), String> {
+        let texts: Vec<&str> = self.tokens.iter().map(|t| t.text).collect();
+        let has_main = texts.windows(2).any(|pair| pair == [":", "main"]);
+        if has_main && !texts.starts_with(&[":", "main"]) {
+            self.jump_to(JP(0), "main".to_string())?;
+        }
+        while self.pos < self.tokens.len() {
+            self.statement().map_err(|e| format!("line {}: {}", self.line(), e))?;
+        }
+        if !self.blocks.is_empty() {
+            return Err("missing 'end' or 'again' at the end of the program".to_string());
+        }
+
+        for (at, name, fixup, line) in std::mem::take(&mut self.fixups) {
+            let addr = *self.labels.get(&name)
+                .ok_or_else(|| format!("line {}: undefined label {:?}", line, name))?;
+            match fixup {
+                Fixup::Nnn if addr > 0xFFF =>
+                    return Err(format!("line {}: address of {:?} doesn't fit in 12 bits", line, name)),
+                Fixup::Nnn => {
+                    self.rom[at] |= (addr >> 8) as u8;
+                    self.rom[at + 1] = addr as u8;
+                }
+                Fixup::Long => {
+                    self.rom[at] = (addr >> 8) as u8;
+                    self.rom[at + 1] = addr as u8;
+                }
+                Fixup::HighNibble => self.rom[at] |= (addr >> 8) as u8 & 0xF,
+                Fixup::HighByte => self.rom[at] = (addr >> 8) as u8,
+                Fixup::LowByte => self.rom[at] = addr as u8,
+            }
+        }
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<&'a str, String> {
+        let token = self.tokens.get(self.pos).ok_or("unexpected end of program")?;
+        self.pos += 1;
+        Ok(token.text)
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.pos).map(|t| t.text)
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), String> {
+        match self.next()? {
+            t if t == text => Ok(()),
+            t => Err(format!("expected {:?}, got {:?}", text, t)),
+        }
+    }
+
+    fn statement(&mut self) -> Result<(), String> {
+        let token = self.next()?;
+        match token {
+            ":" => {
+                let name = self.next()?;
+                self.define_label(name, self.here)?;
+            }
+            ":next" => {
+                let name = self.next()?;
+                self.define_label(name, self.here + 1)?;
+            }
+            ":const" => {
+                let name = self.next()?;
+                let value = self.number()?;
+                self.consts.insert(name.to_string(), value);
+            }
+            ":alias" => {
+                let name = self.next()?;
+                let reg = self.register()?;
+                self.aliases.insert(name.to_string(), reg);
+            }
+            ":unpack" => {
+                // v0 := high bits of the address (after a nibble, unless
+                // it's `long`), v1 := low byte.
+                let long = self.peek() == Some("long");
+                let nibble = if long {
+                    self.next()?;
+                    0
+                } else {
+                    self.nibble()? << 4
+                };
+                let at = self.rom_index();
+                match self.value()? {
+                    Value::Number(n) => {
+                        let high = if long { (n >> 8) as u8 } else { nibble | ((n >> 8) as u8 & 0xF) };
+                        self.emit(LD_R_B(0, high))?;
+                        self.emit(LD_R_B(1, n as u8))?;
+                    }
+                    Value::Label(name) => {
+                        let line = self.line();
+                        let high = if long { Fixup::HighByte } else { Fixup::HighNibble };
+                        self.fixups.push((at + 1, name.clone(), high, line));
+                        self.fixups.push((at + 3, name, Fixup::LowByte, line));
+                        self.emit(LD_R_B(0, nibble))?;
+                        self.emit(LD_R_B(1, 0))?;
+                    }
+                }
+            }
+            ":org" => {
+                let addr = self.number()?;
+                if !(START as i32..=0xFFFF).contains(&addr) {
+                    return Err(format!(":org address {:#X} is out of range", addr));
+                }
+                self.here = addr as usize;
+            }
+            ":byte" => {
+                let b = self.byte()?;
+                self.emit_byte(b)?;
+            }
+            ":call" => self.address(CALL(0))?,
+            // Debugger directives.
+            ":breakpoint" => {
+                self.next()?;
+            }
+            ":monitor" => {
+                self.next()?;
+                self.next()?;
+            }
+            _ if token.starts_with(':') => return Err(format!("unsupported directive {:?}", token)),
+
+            "return" | ";" => self.emit(RET)?,
+            "clear" => self.emit(CLS)?,
+            "hires" => self.emit(HIGH)?,
+            "lores" => self.emit(LOW)?,
+            "scroll-left" => self.emit(SCL)?,
+            "scroll-right" => self.emit(SCR)?,
+            "exit" => self.emit(EXIT)?,
+            "audio" => self.emit(AUDIO)?,
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit(SCD(n))?;
+            }
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.emit(SCU(n))?;
+            }
+            "plane" => {
+                let n = self.nibble()?;
+                self.emit(PLANE(n))?;
+            }
+            "bcd" => {
+                let x = self.register()?;
+                self.emit(LD_B_R(x))?;
+            }
+            "save" | "load" => {
+                let x = self.register()?;
+                let instr = if self.peek() == Some("-") {
+                    self.next()?;
+                    let y = self.register()?;
+                    if token == "save" { LD_II_RR(x, y) } else { LD_RR_II(x, y) }
+                } else if token == "save" {
+                    LD_II_R(x)
+                } else {
+                    LD_R_II(x)
+                };
+                self.emit(instr)?;
+            }
+            "saveflags" => {
+                let x = self.register()?;
+                self.emit(LD_RPL_R(x))?;
+            }
+            "loadflags" => {
+                let x = self.register()?;
+                self.emit(LD_R_RPL(x))?;
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.nibble()?;
+                self.emit(DRW(x, y, n))?;
+            }
+            "jump" => self.address(JP(0))?,
+            "jump0" => self.address(JP_V0(0))?,
+            "native" => self.address(SYS(0))?,
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                self.emit(match token {
+                    "delay" => LD_DT_R(x),
+                    "buzzer" => LD_ST_R(x),
+                    _ => PITCH(x),
+                })?;
+            }
+            "i" => self.assign_i()?,
+
+            "if" => {
+                let cond = self.condition()?;
+                match self.next()? {
+                    "then" => self.skip_if(cond, false)?,
+                    "begin" => {
+                        self.skip_if(cond, true)?;
+                        let at = self.rom_index();
+                        self.emit(JP(0))?;
+                        self.blocks.push(Block::If(at));
+                    }
+                    t => return Err(format!("expected \"then\" or \"begin\", got {:?}", t)),
+                }
+            }
+            "else" => match self.blocks.pop() {
+                Some(Block::If(jump)) => {
+                    let at = self.rom_index();
+                    self.emit(JP(0))?;
+                    self.patch(jump, self.here)?;
+                    self.blocks.push(Block::Else(at));
+                }
+                _ => return Err("'else' without 'if ... begin'".to_string()),
+            },
+            "end" => match self.blocks.pop() {
+                Some(Block::If(jump)) | Some(Block::Else(jump)) => self.patch(jump, self.here)?,
+                _ => return Err("'end' without 'if ... begin'".to_string()),
+            },
+            "loop" => self.blocks.push(Block::Loop(self.here, vec![])),
+            "while" => {
+                let cond = self.condition()?;
+                self.skip_if(cond, true)?;
+                let at = self.rom_index();
+                self.emit(JP(0))?;
+                match self.blocks.iter_mut().rev().find(|b| matches!(b, Block::Loop(..))) {
+                    Some(Block::Loop(_, breaks)) => breaks.push(at),
+                    _ => return Err("'while' outside of a loop".to_string()),
+                }
+            }
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop(start, breaks)) => {
+                    let at = self.rom_index();
+                    self.emit(JP(0))?;
+                    self.patch(at, start)?;
+                    for jump in breaks {
+                        self.patch(jump, self.here)?;
+                    }
+                }
+                _ => return Err("'again' without 'loop'".to_string()),
+            },
+
+            _ => {
+                if let Some(x) = self.try_register(token) {
+                    return self.assign_register(x);
+                }
+                if let Some(n) = parse_number(token).or_else(|| self.consts.get(token).cloned()) {
+                    let b = to_byte(n)?;
+                    return self.emit_byte(b);
+                }
+                // A label on its own is a call.
+                self.pos -= 1;
+                self.address(CALL(0))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn assign_i(&mut self) -> Result<(), String> {
+        match self.next()? {
+            ":=" => match self.peek() {
+                Some("hex") => {
+                    self.next()?;
+                    let x = self.register()?;
+                    self.emit(LD_F_R(x))
+                }
+                Some("bighex") => {
+                    self.next()?;
+                    let x = self.register()?;
+                    self.emit(LD_HF_R(x))
+                }
+                Some("long") => {
+                    self.next()?;
+                    self.emit(LD_I_LONG)?;
+                    match self.value()? {
+                        Value::Number(n) => {
+                            self.emit_byte((n >> 8) as u8)?;
+                            self.emit_byte(n as u8)
+                        }
+                        Value::Label(name) => {
+                            let (at, line) = (self.rom_index(), self.line());
+                            self.fixups.push((at, name, Fixup::Long, line));
+                            self.emit_byte(0)?;
+                            self.emit_byte(0)
+                        }
+                    }
+                }
+                _ => self.address(LD_I_A(0)),
+            },
+            "+=" => {
+                let x = self.register()?;
+                self.emit(ADD_I_R(x))
+            }
+            t => Err(format!("unexpected {:?} after i", t)),
+        }
+    }
+
+    fn assign_register(&mut self, x: u8) -> Result<(), String> {
+        let op = self.next()?;
+        if op == ":=" {
+            match self.peek() {
+                Some("random") => {
+                    self.next()?;
+                    let mask = self.byte()?;
+                    return self.emit(RND(x, mask));
+                }
+                Some("delay") => {
+                    self.next()?;
+                    return self.emit(LD_R_DT(x));
+                }
+                Some("key") => {
+                    self.next()?;
+                    return self.emit(LD_R_K(x));
+                }
+                _ => (),
+            }
+        }
+        let instr = match (op, self.operand()?) {
+            (":=", Operand::Reg(y)) => LD_R_R(x, y),
+            (":=", Operand::Byte(n)) => LD_R_B(x, n),
+            ("+=", Operand::Reg(y)) => ADD(x, y),
+            ("+=", Operand::Byte(n)) => ADD_R_B(x, n),
+            ("-=", Operand::Reg(y)) => SUB(x, y),
+            ("-=", Operand::Byte(n)) => ADD_R_B(x, n.wrapping_neg()),
+            ("=-", Operand::Reg(y)) => SUBN(x, y),
+            ("|=", Operand::Reg(y)) => OR(x, y),
+            ("&=", Operand::Reg(y)) => AND(x, y),
+            ("^=", Operand::Reg(y)) => XOR(x, y),
+            (">>=", Operand::Reg(y)) => SHR(x, y),
+            ("<<=", Operand::Reg(y)) => SHL(x, y),
+            (op, _) => return Err(format!("unexpected {:?} after register", op)),
+        };
+        self.emit(instr)
+    }
+
+    fn condition(&mut self) -> Result<Cond, String> {
+        let x = self.register()?;
+        Ok(match self.next()? {
+            "key" => Cond::Key(x),
+            "-key" => Cond::NotKey(x),
+            op => {
+                let operand = self.operand()?;
+                match op {
+                    "==" => Cond::Eq(x, operand),
+                    "!=" => Cond::Ne(x, operand),
+                    "<" => Cond::Lt(x, operand),
+                    ">" => Cond::Gt(x, operand),
+                    "<=" => Cond::Le(x, operand),
+                    ">=" => Cond::Ge(x, operand),
+                    _ => return Err(format!("unknown comparison {:?}", op)),
+                }
+            }
+        })
+    }
+
+    /// Emits instructions that skip the next one if `cond` is `value`.
+    fn skip_if(&mut self, cond: Cond, value: bool) -> Result<(), String> {
+        // Comparisons are done by subtracting into VF: it's then 1 if there
+        // was no borrow, and the condition becomes VF == 0 or VF == 1.
+        let cond = match cond {
+            Cond::Lt(x, operand) | Cond::Ge(x, operand) => {
+                self.no_borrow(Operand::Reg(x), operand)?;
+                Cond::Eq(VF, Operand::Byte(matches!(cond, Cond::Ge(..)) as u8))
+            }
+            Cond::Gt(x, operand) | Cond::Le(x, operand) => {
+                self.no_borrow(operand, Operand::Reg(x))?;
+                Cond::Eq(VF, Operand::Byte(matches!(cond, Cond::Le(..)) as u8))
+            }
+            _ => cond,
+        };
+        let (cond, value) = match cond {
+            Cond::Ne(x, operand) => (Cond::Eq(x, operand), !value),
+            Cond::NotKey(x) => (Cond::Key(x), !value),
+            _ => (cond, value),
+        };
+        self.emit(match (cond, value) {
+            (Cond::Eq(x, Operand::Byte(n)), false) => SNE(x, n),
+            (Cond::Eq(x, Operand::Byte(n)), true) => SE(x, n),
+            (Cond::Eq(x, Operand::Reg(y)), false) => SNE_R(x, y),
+            (Cond::Eq(x, Operand::Reg(y)), true) => SE_R(x, y),
+            (Cond::Key(x), false) => SKNP(x),
+            (Cond::Key(x), true) => SKP(x),
+            _ => unreachable!(),
+        })
+    }
+
+    /// Sets VF to 1 if `a >= b`, otherwise 0 (at least one has to be a
+    /// register).
+    fn no_borrow(&mut self, a: Operand, b: Operand) -> Result<(), String> {
+        match (a, b) {
+            (a, Operand::Reg(y)) => {
+                self.emit(match a {
+                    Operand::Reg(x) => LD_R_R(VF, x),
+                    Operand::Byte(n) => LD_R_B(VF, n),
+                })?;
+                self.emit(SUB(VF, y))
+            }
+            (Operand::Reg(x), Operand::Byte(n)) => {
+                self.emit(LD_R_B(VF, n))?;
+                self.emit(SUBN(VF, x))
+            }
+            _ => Err("can't compare two constants".to_string()),
+        }
+    }
+
+    /// Instruction with a 12-bit address operand (given as 0).
+    fn address(&mut self, instr: Instr) -> Result<(), String> {
+        match self.value()? {
+            Value::Number(n) if (0..=0xFFF).contains(&n) => self.emit(with_address(instr, n as u16)),
+            Value::Number(n) => Err(format!("address {:#X} doesn't fit in 12 bits", n)),
+            Value::Label(name) => self.jump_to(instr, name),
+        }
+    }
+
+    fn jump_to(&mut self, instr: Instr, label: String) -> Result<(), String> {
+        let (at, line) = (self.rom_index(), self.line());
+        self.fixups.push((at, label, Fixup::Nnn, line));
+        self.emit(instr)
+    }
+
+    fn patch(&mut self, at: usize, addr: usize) -> Result<(), String> {
+        if addr > 0xFFF {
+            return Err(format!("jump target {:#X} doesn't fit in 12 bits", addr));
+        }
+        self.rom[at] |= (addr >> 8) as u8;
+        self.rom[at + 1] = addr as u8;
+        Ok(())
+    }
+
+    fn define_label(&mut self, name: &str, addr: usize) -> Result<(), String> {
+        if self.labels.insert(name.to_string(), addr as u16).is_some() {
+            return Err(format!("label {:?} is defined twice", name));
+        }
+        Ok(())
+    }
+
+    fn try_register(&self, token: &str) -> Option<u8> {
+        if let Some(&reg) = self.aliases.get(token) {
+            return Some(reg);
+        }
+        let mut chars = token.chars();
+        match (chars.next(), chars.next(), chars.next()) {
+            (Some('v'), Some(c), None) | (Some('V'), Some(c), None) => c.to_digit(16).map(|d| d as u8),
+            _ => None,
+        }
+    }
+
+    fn register(&mut self) -> Result<u8, String> {
+        let token = self.next()?;
+        self.try_register(token).ok_or_else(|| format!("expected a register, got {:?}", token))
+    }
+
+    fn operand(&mut self) -> Result<Operand, String> {
+        match self.peek().and_then(|t| self.try_register(t)) {
+            Some(reg) => {
+                self.next()?;
+                Ok(Operand::Reg(reg))
+            }
+            None => Ok(Operand::Byte(self.byte()?)),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        let token = self.next()?;
+        if let Some(n) = parse_number(token).or_else(|| self.consts.get(token).cloned()) {
+            Ok(Value::Number(n))
+        } else if token.starts_with(|c: char| c.is_ascii_digit() || c == '-') {
+            Err(format!("bad number {:?}", token))
+        } else {
+            Ok(Value::Label(token.to_string()))
+        }
+    }
+
+    /// A number known at this point: a literal, a constant, or a label
+    /// defined earlier.
+    fn number(&mut self) -> Result<i32, String> {
+        match self.value()? {
+            Value::Number(n) => Ok(n),
+            Value::Label(name) => match self.labels.get(&name) {
+                Some(&addr) => Ok(addr as i32),
+                None => Err(format!("undefined name {:?}", name)),
+            },
+        }
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        let n = self.number()?;
+        to_byte(n)
+    }
+
+    fn nibble(&mut self) -> Result<u8, String> {
+        match self.number()? {
+            n @ 0..=15 => Ok(n as u8),
+            n => Err(format!("{} doesn't fit in 4 bits", n)),
+        }
+    }
+
+    fn line(&self) -> usize {
+        self.tokens.get(self.pos.saturating_sub(1)).map_or(0, |t| t.line)
+    }
+
+    fn rom_index(&self) -> usize {
+        self.here - START
+    }
+
+    fn emit(&mut self, instr: Instr) -> Result<(), String> {
+        let b = instr.to();
+        self.emit_byte((b >> 8) as u8)?;
+        self.emit_byte(b as u8)
+    }
+
+    fn emit_byte(&mut self, b: u8) -> Result<(), String> {
+        if self.here > 0xFFFF {
+            return Err("program is too large".to_string());
+        }
+        let index = self.rom_index();
+        if index >= self.rom.len() {
+            self.rom.resize(index + 1, 0);
+        }
+        self.rom[index] = b;
+        self.here += 1;
+        Ok(())
+    }
+}
+
+fn with_address(instr: Instr, addr: u16) -> Instr {
+    match instr {
+        JP(_) => JP(addr),
+        CALL(_) => CALL(addr),
+        JP_V0(_) => JP_V0(addr),
+        SYS(_) => SYS(addr),
+        LD_I_A(_) => LD_I_A(addr),
+        _ => instr,
+    }
+}
+
+/// Decimal (possibly negative), `0x` hex or `0b` binary number.
+fn parse_number(word: &str) -> Option<i32> {
+    if let Some(hex) = word.strip_prefix("0x") {
+        i32::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = word.strip_prefix("0b") {
+        i32::from_str_radix(bin, 2).ok()
+    } else {
+        word.parse().ok()
+    }
+}
+
+/// Bytes can also be given as negative numbers.
+fn to_byte(n: i32) -> Result<u8, String> {
+    if (-128..=255).contains(&n) {
+        Ok(n as u8)
+    } else {
+        Err(format!("{} doesn't fit in a byte", n))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compile() {
+        let source = "
+            # Move a ball until F is pressed.
+            :alias x v1
+            :alias y v2
+            :const SPEED 2
+
+            : ball
+              0b01100000 0b11110000 0b11110000 0b01100000
+
+            : main
+              hires
+              x := 10
+              y := 0
+              i := ball
+              loop
+                sprite x y 4
+                if x == 60 then x := 0
+                if y < 28 begin
+                  y += SPEED
+                else
+                  y := 0
+                end
+                v0 := key
+                while v0 != 0xF
+                sprite x y 4
+                x += 1
+              again
+              exit
+        ";
+        assert_eq!(compile(source), Ok(vec![
+            0x12, 0x06,             // jump main
+            0x60, 0xF0, 0xF0, 0x60, // ball
+            0x00, 0xFF,
+            0x61, 0x0A,
+            0x62, 0x00,
+            0xA2, 0x02,
+            0xD1, 0x24,             // loop
+            0x41, 0x3C, 0x61, 0x00,
+            0x6F, 0x1C, 0x8F, 0x27, // vf := 28 - y, with no borrow
+            0x3F, 0x00, 0x12, 0x20, // if vf == 0 (y < 28)
+            0x72, 0x02, 0x12, 0x22,
+            0x62, 0x00,             // else
+            0xF0, 0x0A,             // end
+            0x40, 0x0F, 0x12, 0x2E, // while
+            0xD1, 0x24,
+            0x71, 0x01,
+            0x12, 0x0E,             // again
+            0x00, 0xFD,
+        ]));
+    }
+
+    #[test]
+    fn test_directives() {
+        let source = "
+            : main
+              :unpack 0xA data
+              i := long data
+              :next target
+              v3 := 0
+              jump0 target
+            :org 0x300
+            : data 1 2 -1
+        ";
+        let rom = compile(source).unwrap();
+        assert_eq!(&rom[..12], &[
+            0x60, 0xA3, 0x61, 0x00,
+            0xF0, 0x00, 0x03, 0x00,
+            0x63, 0x00,
+            0xB2, 0x09,
+        ]);
+        assert_eq!(&rom[0x100..], &[1, 2, 0xFF]);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(compile(": main\n  jump nowhere"),
+                   Err("line 2: undefined label \"nowhere\"".to_string()));
+        assert_eq!(compile(": main\n  v0 := 256"),
+                   Err("line 2: 256 doesn't fit in a byte".to_string()));
+        assert_eq!(compile(":macro twice X { X X }"),
+                   Err("line 1: unsupported directive \":macro\"".to_string()));
+        assert!(compile(": main loop v0 += 1").is_err());
+        assert!(compile(": main else").is_err());
+        assert!(compile(": main : main").is_err());
+    }
+}
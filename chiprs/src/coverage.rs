@@ -0,0 +1,274 @@
+use std::io::{self, Write};
+
+use crate::Chip;
+use crate::hook::Hook;
+use crate::memory::{FONT_ADDR, BIG_FONT_ADDR};
+use crate::platform::{self, Platform};
+
+pub const EXECUTED: u8 = 1;
+pub const READ: u8 = 2;
+pub const WRITTEN: u8 = 4;
+
+/// Tracks how each byte of memory was used: executed as an instruction,
+/// read as data (by DRW, the font loads and the register loads), or
+/// written (by the register stores and LD B, Vx).
+pub struct Coverage {
+    flags: Vec<u8>,
+    // Number of times an instruction was executed, for each address.
+    counts: Vec<u64>,
+    // Original contents of the ROM, for the listing.
+    rom: Vec<u8>,
+    start: usize,
+    end: usize,
+    pending: Vec<(usize, usize, u8)>,
+    // Used for decoding the instructions in the listing.
+    platform: &'static Platform,
+}
+
+impl Coverage {
+    /// Track coverage for `rom` loaded at `start`, in memory of given size
+    /// (see `Platform::memory_size`).
+    pub fn new(memory_size: usize, start: usize, rom: &[u8]) -> Coverage {
+        Coverage {
+            flags: vec![0; memory_size],
+            counts: vec![0; memory_size],
+            rom: rom.to_vec(),
+            start: start.min(memory_size),
+            end: (start + rom.len()).min(memory_size),
+            pending: vec![],
+            platform: &platform::CHIP8,
+        }
+    }
+
+    pub fn flags_at(&self, addr: usize) -> u8 {
+        self.flags[addr]
+    }
+
+    // Flags and execution count of the 2-byte word at `addr`.
+    fn word(&self, addr: usize) -> (u8, u64) {
+        let next = addr + 1;
+        (self.flags[addr] | self.flags.get(next).cloned().unwrap_or(0),
+         self.counts[addr].max(self.counts.get(next).cloned().unwrap_or(0)))
+    }
+
+    fn mark(&mut self, start: usize, end: usize, flag: u8) {
+        let end = end.min(self.flags.len());
+        for flags in self.flags[start.min(end)..end].iter_mut() {
+            *flags |= flag;
+        }
+    }
+
+    /// Number of ROM bytes that were executed, read, written, and not used
+    /// at all.
+    pub fn summary(&self) -> (usize, usize, usize, usize) {
+        let rom = &self.flags[self.start..self.end];
+        let count = |flag| rom.iter().filter(|&&f| f & flag != 0).count();
+        (count(EXECUTED), count(READ), count(WRITTEN), rom.iter().filter(|&&f| f == 0).count())
+    }
+
+    /// Writes annotated disassembly of the (original) ROM: one line for each
+    /// 2-byte word, with flags (`X` for executed, `R` for read, `W` for
+    /// written), execution count and disassembly (for executed instructions
+    /// only).
+    pub fn write_listing(&self, w: &mut dyn Write) -> io::Result<()> {
+        for addr in (self.start..self.end).step_by(2) {
+            let (flags, count) = self.word(addr);
+            let flag = |f, c| if flags & f != 0 { c } else { '-' };
+            let offset = addr - self.start;
+            let b = ((self.rom[offset] as u16) << 8) |
+                self.rom.get(offset + 1).cloned().unwrap_or(0) as u16;
+
+            write!(w, "{:04X}  {:04X}  {}{}{}", addr, b,
+                   flag(EXECUTED, 'X'), flag(READ, 'R'), flag(WRITTEN, 'W'))?;
+            if flags & EXECUTED != 0 {
+                match self.platform.decode(b) {
+                    Some(instr) => write!(w, "  {:8}  {}", count, instr)?,
+                    None => write!(w, "  {:8}  ????", count)?,
+                }
+            }
+            writeln!(w)?;
+        }
+        Ok(())
+    }
+
+    /// Writes coverage in lcov format, referring to the listing produced by
+    /// `write_listing` (saved as `listing_name`). Each instruction is a
+    /// line; lines with data only are not reported.
+    pub fn write_lcov(&self, w: &mut dyn Write, listing_name: &str) -> io::Result<()> {
+        writeln!(w, "TN:")?;
+        writeln!(w, "SF:{}", listing_name)?;
+        let mut found = 0;
+        let mut hit = 0;
+        for (line, addr) in (self.start..self.end).step_by(2).enumerate() {
+            let (flags, count) = self.word(addr);
+            // Data that was only read or written doesn't count as code.
+            if flags & (READ | WRITTEN) != 0 && flags & EXECUTED == 0 {
+                continue;
+            }
+            writeln!(w, "DA:{},{}", line + 1, count)?;
+            found += 1;
+            if count > 0 {
+                hit += 1;
+            }
+        }
+        writeln!(w, "LF:{}", found)?;
+        writeln!(w, "LH:{}", hit)?;
+        writeln!(w, "end_of_record")
+    }
+}
+
+impl Hook for Coverage {
+    fn before_step(&mut self, chip: &Chip, pc: u16, b: u16) {
+        let pc = pc as usize;
+        let i = chip.i() as usize;
+        self.platform = chip.platform();
+        self.pending.clear();
+        let instr = self.platform.decode(b);
+        let size = instr.map_or(2, |instr| instr.size());
+        self.pending.push((pc, pc + size, EXECUTED));
+
+        use crate::instr::Instr::*;
+        match instr {
+            Some(DRW(_, _, z)) => {
+                let len = sprite_len(chip, i, z);
+                self.pending.push((i, i + len, READ));
+            }
+            Some(LD_R_II(x)) => self.pending.push((i, i + x as usize + 1, READ)),
+            Some(LD_F_R(x)) => {
+                let font = FONT_ADDR + chip.v()[x as usize] as usize * 5;
+                self.pending.push((font, font + 5, READ));
+            }
+            Some(LD_HF_R(x)) => {
+                let font = BIG_FONT_ADDR + chip.v()[x as usize] as usize * 10;
+                self.pending.push((font, font + 10, READ));
+            }
+            Some(LD_RR_II(x, y)) | Some(LD_RR_IIP(x, y)) =>
+                self.pending.push((i, i + reg_count(x, y), READ)),
+            Some(LD_II_R(x)) => self.pending.push((i, i + x as usize + 1, WRITTEN)),
+            Some(LD_II_RR(x, y)) | Some(LD_IIP_RR(x, y)) =>
+                self.pending.push((i, i + reg_count(x, y), WRITTEN)),
+            Some(LD_B_R(_)) => self.pending.push((i, i + 3, WRITTEN)),
+            _ => (),
+        }
+    }
+
+    fn after_step(&mut self, _chip: &Chip, result: &Result<(), String>) {
+        if result.is_ok() {
+            let pending: Vec<_> = self.pending.drain(..).collect();
+            for (start, end, flag) in pending {
+                if flag == EXECUTED && start < self.counts.len() {
+                    self.counts[start] += 1;
+                }
+                self.mark(start, end, flag);
+            }
+        }
+    }
+}
+
+/// Number of registers from Vx to Vy (in either order).
+fn reg_count(x: u8, y: u8) -> usize {
+    x.abs_diff(y) as usize + 1
+}
+
+/// Number of bytes read by DRW, the same way `Chip` computes it.
+fn sprite_len(chip: &Chip, i: usize, z: u8) -> usize {
+    if let Some(ref framebuffer) = chip.display.framebuffer {
+        let font_end = BIG_FONT_ADDR + chip.platform().big_font.len();
+        if i < font_end {
+            z as usize
+        } else {
+            framebuffer.sprite_width * framebuffer.sprite_height
+        }
+    } else if z == 0 && chip.platform().wide_sprites {
+        32 * chip.planes().count_ones() as usize
+    } else {
+        z as usize * chip.planes().count_ones() as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instr::Instr::*;
+
+    #[test]
+    fn test_coverage() {
+        let mut chip = Chip::new();
+        chip.memory.load_font();
+        chip.memory.load_program(0x200, &[
+            LD_I_A(0x20A),
+            DRW(0, 0, 2),
+            LD_B_R(0),
+            JP(0xFFF),
+            // 0x208: never executed
+            CLS,
+            // 0x20A: sprite
+            LD_R_B(0, 0xFF),
+        ]);
+        chip.jump(0x200);
+
+        let mut coverage = Coverage::new(0x1000, 0x200, &chip.memory.bytes[0x200..0x20C]);
+        while chip.pc() != 0xFFF {
+            chip.step_with(&mut coverage).unwrap();
+        }
+
+        assert_eq!(coverage.flags_at(0x200), EXECUTED);
+        assert_eq!(coverage.flags_at(0x208), 0);
+        assert_eq!(coverage.flags_at(0x20A), READ | WRITTEN);
+        assert_eq!(coverage.flags_at(0x20C), WRITTEN);
+        assert_eq!(coverage.summary(), (8, 2, 2, 2));
+
+        let mut listing = vec![];
+        coverage.write_listing(&mut listing).unwrap();
+        assert_eq!(String::from_utf8(listing).unwrap(), "\
+0200  A20A  X--         1  LD I, #20A
+0202  D002  X--         1  DRW V0, V0, #2
+0204  F033  X--         1  LD B, V0
+0206  1FFF  X--         1  JP #FFF
+0208  00E0  ---
+020A  60FF  -RW
+");
+
+        let mut lcov = vec![];
+        coverage.write_lcov(&mut lcov, "rom.lst").unwrap();
+        assert_eq!(String::from_utf8(lcov).unwrap(), "\
+TN:
+SF:rom.lst
+DA:1,1
+DA:2,1
+DA:3,1
+DA:4,1
+DA:5,0
+LF:5
+LH:4
+end_of_record
+");
+    }
+
+    #[test]
+    fn test_coverage_xochip() {
+        // A program that runs past the first 4K.
+        let mut chip = Chip::with_platform(&platform::XOCHIP);
+        let rom = [
+            0xF0, 0x00, 0x10, 0x10, // LD I, #1010
+            0x50, 0x12,             // LD [I], V0 - V1
+            0x50, 0x13,             // LD V0 - V1, [I]
+            0xF1, 0x30,             // LD HF, V1
+        ];
+        chip.memory.bytes[0xFFC..0xFFC + rom.len()].copy_from_slice(&rom);
+        chip.v[1] = 2;
+        chip.jump(0xFFC);
+
+        let mut coverage = Coverage::new(chip.memory.size(), 0xFFC, &rom);
+        for _ in 0..4 {
+            chip.step_with(&mut coverage).unwrap();
+        }
+        assert_eq!(coverage.flags_at(0xFFE), EXECUTED);
+        assert_eq!(coverage.flags_at(0x1002), EXECUTED);
+        assert_eq!(coverage.flags_at(0x1010), READ | WRITTEN);
+        assert_eq!(coverage.flags_at(0x1011), READ | WRITTEN);
+        assert_eq!(coverage.flags_at(0x1012), 0);
+        assert_eq!(coverage.flags_at(BIG_FONT_ADDR + 20), READ);
+        assert_eq!(coverage.summary(), (10, 0, 0, 0));
+    }
+}
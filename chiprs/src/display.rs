@@ -1,53 +1,364 @@
+/// Size of the plain CHIP-8 screen. Other platforms can have more display
+/// modes, see `Platform::display_modes`.
 pub const DISPLAY_W: usize = 64;
 pub const DISPLAY_H: usize = 32;
 
+/// Size of a CHIP-8X color zone, in pixels.
+pub const ZONE_W: usize = 8;
+pub const ZONE_H: usize = 4;
+
+/// Order in which CHIP-8X cycles through background colors.
+pub const BACKGROUNDS: [u8; 4] = [COLOR_BLUE, COLOR_BLACK, COLOR_GREEN, COLOR_RED];
+
+// VP-590 colors are 3 bits: red, blue, green.
+pub const COLOR_BLACK: u8 = 0;
+pub const COLOR_RED: u8 = 1;
+pub const COLOR_BLUE: u8 = 2;
+pub const COLOR_GREEN: u8 = 4;
+
+/// Size of the MegaChip screen.
+pub const MEGA_W: usize = 256;
+pub const MEGA_H: usize = 192;
+
+/// The screen. Each pixel is a bit mask of the planes it's lit in; plain
+/// CHIP-8 only uses the first plane, XO-CHIP has two.
+#[derive(Clone)]
 pub struct Display {
-    pub pixels: [bool; DISPLAY_W * DISPLAY_H],
+    width: usize,
+    height: usize,
+    pub pixels: Vec<u8>,
+    /// Color attributes, for platforms that have them (CHIP-8X).
+    pub colors: Option<ColorLayer>,
+    /// Color framebuffer, used instead of `pixels` in MegaChip mode.
+    pub framebuffer: Option<Framebuffer>,
 }
 
-impl Display {
-    pub fn new() -> Display {
-        return Display {
-            pixels: [false; DISPLAY_W * DISPLAY_H],
+/// CHIP-8X color attributes (from the VP-590 color board): one background
+/// color, and a foreground color for each zone of 8x4 pixels. The colors
+/// survive clearing the screen.
+#[derive(Clone)]
+pub struct ColorLayer {
+    /// Index into `BACKGROUNDS`.
+    background: usize,
+    columns: usize,
+    pub zones: Vec<u8>,
+}
+
+impl ColorLayer {
+    pub fn new(width: usize, height: usize) -> ColorLayer {
+        let columns = width.div_ceil(ZONE_W);
+        let rows = height.div_ceil(ZONE_H);
+        ColorLayer {
+            background: 0,
+            columns,
+            zones: vec![COLOR_RED; columns * rows],
+        }
+    }
+
+    pub fn background(&self) -> u8 {
+        BACKGROUNDS[self.background]
+    }
+
+    pub fn next_background(&mut self) {
+        self.background = (self.background + 1) % BACKGROUNDS.len();
+    }
+
+    /// Foreground color of the pixel at (x, y).
+    pub fn at(&self, x: usize, y: usize) -> u8 {
+        self.zones[(y / ZONE_H) * self.columns + x / ZONE_W]
+    }
+
+    /// Sets the color of a zone, given in zone coordinates (wrapping around
+    /// the screen).
+    pub fn set_zone(&mut self, column: usize, row: usize, color: u8) {
+        let rows = self.zones.len() / self.columns;
+        let index = (row % rows) * self.columns + column % self.columns;
+        self.zones[index] = color & 7;
+    }
+}
+
+/// How MegaChip sprites are combined with the screen.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Blend {
+    Normal,
+    Alpha25,
+    Alpha50,
+    Add,
+    Multiply,
+}
+
+impl Blend {
+    pub fn from_u8(n: u8) -> Option<Blend> {
+        match n {
+            0 => Some(Blend::Normal),
+            1 => Some(Blend::Alpha25),
+            2 => Some(Blend::Alpha50),
+            3 => Some(Blend::Add),
+            4 => Some(Blend::Multiply),
+            _ => None,
         }
     }
 
+    /// Combines one color channel of a sprite with the screen.
+    fn apply(self, src: u32, dst: u32) -> u32 {
+        match self {
+            Blend::Normal => src,
+            Blend::Alpha25 => (src + 3 * dst) / 4,
+            Blend::Alpha50 => (src + dst) / 2,
+            Blend::Add => (src + dst).min(0xFF),
+            Blend::Multiply => src * dst / 0xFF,
+        }
+    }
+}
+
+/// MegaChip screen: 32-bit ARGB pixels, and the state of the sprite
+/// drawing instructions. Sprites are made of palette indices (with 0 being
+/// transparent), and don't wrap around the screen.
+#[derive(Clone)]
+pub struct Framebuffer {
+    pub pixels: Vec<u32>,
+    /// Palette index each pixel was drawn with, for collisions.
+    indices: Vec<u8>,
+    pub palette: [u32; 256],
+    pub sprite_width: usize,
+    pub sprite_height: usize,
+    pub blend: Blend,
+    /// Drawing over a pixel of this palette index is a collision (0 means
+    /// none).
+    pub collision_color: u8,
+    /// Opacity of the whole screen, for fading in and out.
+    pub alpha: u8,
+}
+
+impl Default for Framebuffer {
+    fn default() -> Framebuffer {
+        Framebuffer::new()
+    }
+}
+
+impl Framebuffer {
+    pub fn new() -> Framebuffer {
+        Framebuffer {
+            pixels: vec![0; MEGA_W * MEGA_H],
+            indices: vec![0; MEGA_W * MEGA_H],
+            palette: [0; 256],
+            sprite_width: 0,
+            sprite_height: 0,
+            blend: Blend::Normal,
+            collision_color: 0,
+            alpha: 0xFF,
+        }
+    }
+
+    pub fn at(&self, x: usize, y: usize) -> u32 {
+        self.pixels[y * MEGA_W + x]
+    }
+
+    pub fn clear(&mut self) {
+        for p in self.pixels.iter_mut() {
+            *p = 0;
+        }
+        for index in self.indices.iter_mut() {
+            *index = 0;
+        }
+    }
+
+    /// Draws a sprite of palette indices, `sprite_width` pixels wide.
+    /// Returns whether it touched a pixel of the collision color.
     pub fn draw(&mut self, x: usize, y: usize, data: &[u8]) -> bool {
+        let width = self.sprite_width.max(1);
         let mut collision = false;
+        // Skip the parts outside of the screen.
+        let rows = data.chunks(width).enumerate().take(MEGA_H.saturating_sub(y));
+        for (i, row) in rows {
+            for (j, &index) in row.iter().enumerate().take(MEGA_W.saturating_sub(x)) {
+                if index != 0 {
+                    let color = self.palette[index as usize];
+                    collision |= self.put(x + j, y + i, color, index);
+                }
+            }
+        }
+        collision
+    }
 
-        for (i, b) in data.iter().enumerate() {
+    /// Draws a plain 8-pixel wide sprite (a font character) in white.
+    pub fn draw_mono(&mut self, x: usize, y: usize, data: &[u8]) -> bool {
+        let mut collision = false;
+        for (i, &row) in data.iter().enumerate() {
             for j in 0..8 {
-                let xp = (x + j) % DISPLAY_W;
-                let yp = (y + i) % DISPLAY_H;
-                let index = yp * DISPLAY_W + xp;
-                let val = (b & (1 << (7 - j))) != 0;
+                if row & (0x80 >> j) != 0 {
+                    collision |= self.put(x + j, y + i, 0xFFFF_FFFF, 0xFF);
+                }
+            }
+        }
+        collision
+    }
+
+    fn put(&mut self, x: usize, y: usize, color: u32, index: u8) -> bool {
+        if x >= MEGA_W || y >= MEGA_H {
+            return false;
+        }
+        let pos = y * MEGA_W + x;
+        if self.blend == Blend::Normal {
+            self.pixels[pos] = color;
+        } else {
+            let dst = self.pixels[pos];
+            let mut blended = 0;
+            for shift in [0, 8, 16, 24].iter() {
+                let channel = self.blend.apply((color >> shift) & 0xFF, (dst >> shift) & 0xFF);
+                blended |= channel << shift;
+            }
+            self.pixels[pos] = blended;
+        }
+        let collision = self.indices[pos] != 0 && self.indices[pos] == self.collision_color;
+        self.indices[pos] = index;
+        collision
+    }
 
-                if self.pixels[index] && val {
-                    collision = true;
+    /// Moves the screen contents by (dx, dy), like `Display::scroll`.
+    pub fn scroll(&mut self, dx: isize, dy: isize) {
+        let (old_pixels, old_indices) = (self.pixels.clone(), self.indices.clone());
+        for y in 0..MEGA_H {
+            for x in 0..MEGA_W {
+                let sx = x as isize - dx;
+                let sy = y as isize - dy;
+                let inside = sx >= 0 && sy >= 0 &&
+                    (sx as usize) < MEGA_W && (sy as usize) < MEGA_H;
+                let index = y * MEGA_W + x;
+                if inside {
+                    let src = sy as usize * MEGA_W + sx as usize;
+                    self.pixels[index] = old_pixels[src];
+                    self.indices[index] = old_indices[src];
+                } else {
+                    self.pixels[index] = 0;
+                    self.indices[index] = 0;
+                }
+            }
+        }
+    }
+}
+
+impl Default for Display {
+    fn default() -> Display {
+        Display::new()
+    }
+}
+
+impl Display {
+    pub fn new() -> Display {
+        Display::with_size(DISPLAY_W, DISPLAY_H)
+    }
+
+    pub fn with_size(width: usize, height: usize) -> Display {
+        Display {
+            width,
+            height,
+            pixels: vec![0; width * height],
+            colors: None,
+            framebuffer: None,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Switches to a different resolution, clearing the screen (and
+    /// resetting the colors). This also leaves MegaChip mode.
+    pub fn resize(&mut self, width: usize, height: usize) {
+        let colors = self.colors.take().map(|_| ColorLayer::new(width, height));
+        *self = Display::with_size(width, height);
+        self.colors = colors;
+    }
+
+    /// Draws an 8-pixel wide sprite on the first plane. Returns whether any
+    /// lit pixel was turned off.
+    pub fn draw(&mut self, x: usize, y: usize, data: &[u8]) -> bool {
+        self.draw_sprite(x, y, data, 8, 1)
+    }
+
+    /// Draws a sprite `width` (a multiple of 8) pixels wide on the planes
+    /// selected by `planes`. `data` has the rows for each selected plane in
+    /// turn.
+    pub fn draw_sprite(&mut self, x: usize, y: usize, data: &[u8], width: usize, planes: u8)
+                       -> bool {
+        let row_bytes = width / 8;
+        let selected = (0..8).map(|n| 1 << n).filter(|plane| planes & plane != 0);
+        let rows = data.len() / row_bytes / (planes.count_ones() as usize).max(1);
+
+        let mut collision = false;
+        let mut sprite = data.chunks(row_bytes);
+        for plane in selected {
+            for i in 0..rows {
+                let row = match sprite.next() {
+                    Some(row) => row,
+                    None => return collision,
+                };
+                for j in 0..width {
+                    let xp = (x + j) % self.width;
+                    let yp = (y + i) % self.height;
+                    let index = yp * self.width + xp;
+                    let val = (row[j / 8] & (1 << (7 - j % 8))) != 0;
+
+                    if val {
+                        if self.pixels[index] & plane != 0 {
+                            collision = true;
+                        }
+                        self.pixels[index] ^= plane;
+                    }
                 }
-                self.pixels[index] ^= val;
             }
         }
         collision
     }
 
+    /// Moves the contents of the given planes by (dx, dy). Pixels moved
+    /// off the screen are lost, the ones moved in are off.
+    pub fn scroll(&mut self, dx: isize, dy: isize, planes: u8) {
+        let old = self.pixels.clone();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let sx = x as isize - dx;
+                let sy = y as isize - dy;
+                let inside = sx >= 0 && sy >= 0 &&
+                    (sx as usize) < self.width && (sy as usize) < self.height;
+                let src = if inside { old[sy as usize * self.width + sx as usize] } else { 0 };
+                let index = y * self.width + x;
+                self.pixels[index] = (self.pixels[index] & !planes) | (src & planes);
+            }
+        }
+    }
+
     pub fn dump(&self) {
-        for i in 0..DISPLAY_H {
-            for j in 0..DISPLAY_W {
-                let index = i * DISPLAY_W + j;
-                print!("{}", if self.pixels[index] { "#" } else { "." });
+        for i in 0..self.height {
+            for j in 0..self.width {
+                print!("{}", if self.at(j, i) { "#" } else { "." });
             }
             println!();
         }
     }
 
+    /// Whether the pixel is lit in any plane.
     pub fn at(&self, x: usize, y: usize) -> bool {
-        return self.pixels[y * DISPLAY_W + x];
+        self.color(x, y) != 0
+    }
+
+    /// Planes the pixel is lit in.
+    pub fn color(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y * self.width + x]
     }
 
     pub fn clear(&mut self) {
+        self.clear_planes(0xFF);
+    }
+
+    pub fn clear_planes(&mut self, planes: u8) {
         for b in self.pixels.iter_mut() {
-            *b = false;
+            *b &= !planes;
         }
     }
 }
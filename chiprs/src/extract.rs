@@ -0,0 +1,335 @@
+//! Finding ROMs embedded in text, such as Usenet posts and articles in
+//! `games/`: uuencoded files, HP-48 ASC-> strings, and hex dumps.
+
+use std::fmt;
+
+use crate::analysis::Analysis;
+use crate::hp48;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Encoding {
+    Uuencode,
+    Asc,
+    HexDump,
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Encoding::Uuencode => write!(f, "uuencoded file"),
+            Encoding::Asc => write!(f, "ASC string"),
+            Encoding::HexDump => write!(f, "hex dump"),
+        }
+    }
+}
+
+/// A block of encoded data found in text.
+#[derive(Debug, PartialEq)]
+pub struct Payload {
+    pub encoding: Encoding,
+    /// Line number where the block starts (from 1).
+    pub line: usize,
+    /// File name, for uuencoded files.
+    pub name: Option<String>,
+    /// Decoded contents. HP-48 string objects are unwrapped.
+    pub data: Result<Vec<u8>, String>,
+}
+
+// Shorter hex dumps are probably just numbers in the text.
+const MIN_HEX_DUMP: usize = 8;
+// Same for ASC strings (the object header alone is 10 nibbles).
+const MIN_ASC_DIGITS: usize = 16;
+
+/// Finds all the payloads in `text`.
+pub fn scan(text: &str) -> Vec<Payload> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut payloads = vec![];
+    let mut i = 0;
+    while i < lines.len() {
+        let found = uuencoded(&lines, i)
+            .or_else(|| asc(&lines, i))
+            .or_else(|| hex_dump(&lines, i));
+        match found {
+            Some((payload, next)) => {
+                payloads.push(payload);
+                i = next;
+            }
+            None => i += 1,
+        }
+    }
+    payloads
+}
+
+/// Does `rom` look like a CHIP-8 program: do all the instructions reachable
+/// from the start decode?
+pub fn is_plausible(rom: &[u8]) -> bool {
+    let analysis = Analysis::new(rom, 0x200);
+    !analysis.instrs.is_empty() && analysis.invalid.is_empty()
+}
+
+fn unwrap_hp48(data: Vec<u8>) -> Result<Vec<u8>, String> {
+    match hp48::unwrap(&data) {
+        Some(result) => result.map_err(|e| e.to_string()),
+        None => Ok(data),
+    }
+}
+
+/// `begin <mode> <name>`, lines of data, and `end`.
+fn uuencoded(lines: &[&str], start: usize) -> Option<(Payload, usize)> {
+    let mut words = lines[start].split_whitespace();
+    if words.next() != Some("begin") {
+        return None;
+    }
+    let mode = words.next()?;
+    if !mode.chars().all(|c| c.is_digit(8)) {
+        return None;
+    }
+    let name = words.collect::<Vec<_>>().join(" ");
+
+    let mut data = vec![];
+    let mut error = None;
+    let mut i = start + 1;
+    while i < lines.len() && lines[i].trim_end() != "end" {
+        if error.is_none() {
+            if let Err(e) = uudecode_line(lines[i].trim_end(), &mut data) {
+                error = Some(format!("line {}: {}", i + 1, e));
+            }
+        }
+        i += 1;
+    }
+    if i == lines.len() {
+        error = error.or_else(|| Some("no end line".to_string()));
+    }
+    let payload = Payload {
+        encoding: Encoding::Uuencode,
+        line: start + 1,
+        name: Some(name),
+        data: match error {
+            Some(e) => Err(e),
+            None => unwrap_hp48(data),
+        },
+    };
+    Some((payload, i + 1))
+}
+
+fn uudecode_line(line: &str, data: &mut Vec<u8>) -> Result<(), String> {
+    // Each character encodes 6 bits, as 32 + value; 0 is also written as `.
+    let value = |c: u8| -> Result<u32, String> {
+        match c {
+            b'`' => Ok(0),
+            b' '..=b'_' => Ok((c - b' ') as u32),
+            _ => Err(format!("unexpected {:?}", c as char)),
+        }
+    };
+    let bytes = line.as_bytes();
+    let len = match bytes.first() {
+        Some(&c) => value(c)? as usize,
+        None => return Ok(()),
+    };
+    let mut chars = bytes[1..].to_vec();
+    // Trailing spaces are often stripped.
+    chars.resize(chars.len().max(len.div_ceil(3) * 4), b' ');
+    let mut decoded = vec![];
+    for group in chars.chunks(4).take(len.div_ceil(3)) {
+        let mut n = 0;
+        for &c in group {
+            n = (n << 6) | value(c)?;
+        }
+        decoded.extend(&[(n >> 16) as u8, (n >> 8) as u8, n as u8]);
+    }
+    data.extend(&decoded[..len]);
+    Ok(())
+}
+
+/// A string starting with a quote and made of hex digits, possibly spanning
+/// several lines, and possibly preceded by a `%%HP:` header.
+fn asc(lines: &[&str], start: usize) -> Option<(Payload, usize)> {
+    let mut i = start;
+    if lines[i].trim_start().starts_with("%%HP:") && i + 1 < lines.len() {
+        i += 1;
+    }
+    let first = lines[i].trim().strip_prefix('"')?;
+    let mut text = String::new();
+    let mut rest = first;
+    loop {
+        if let Some(end) = rest.find('"') {
+            text.push_str(&rest[..end]);
+            break;
+        }
+        text.push_str(rest);
+        i += 1;
+        if i == lines.len() {
+            return None;
+        }
+        rest = lines[i].trim();
+    }
+    let digits = text.chars().filter(|c| !c.is_whitespace()).count();
+    if digits < MIN_ASC_DIGITS || !text.chars().all(|c| c.is_ascii_hexdigit() || c.is_whitespace()) {
+        return None;
+    }
+    let payload = Payload {
+        encoding: Encoding::Asc,
+        line: start + 1,
+        name: None,
+        data: hp48::decode_asc(&format!("\"{}\"", text)).map_err(|e| e.to_string()),
+    };
+    Some((payload, i + 1))
+}
+
+/// Consecutive lines of hex bytes (or 16-bit words), possibly with
+/// addresses: `0200: 00 E0 A2 2A`, `200 00E0 A22A`.
+fn hex_dump(lines: &[&str], start: usize) -> Option<(Payload, usize)> {
+    let mut rows = vec![];
+    let mut i = start;
+    while i < lines.len() {
+        match hex_line(lines[i]) {
+            Some(row) => rows.push(row),
+            None => break,
+        }
+        i += 1;
+    }
+    if rows.is_empty() {
+        return None;
+    }
+
+    // Is the first number on a line an address? It is if it's marked (with
+    // a colon, or by having 3 digits), or if the numbers on all lines count
+    // the bytes on previous lines.
+    let counting = rows.len() > 1 && rows.windows(2).all(|pair| {
+        let (a, b) = (&pair[0], &pair[1]);
+        let len: usize = a.words[1..].iter().map(|w| w.len() / 2).sum();
+        number(&a.words[0]) + len == number(&b.words[0])
+    });
+    let mut data = vec![];
+    for row in rows.iter() {
+        let words = if row.address || counting { &row.words[1..] } else { &row.words[..] };
+        for word in words {
+            if word.len() % 2 != 0 {
+                return None;
+            }
+            for k in (0..word.len()).step_by(2) {
+                data.push(u8::from_str_radix(&word[k..k + 2], 16).ok()?);
+            }
+        }
+    }
+    if data.len() < MIN_HEX_DUMP {
+        return None;
+    }
+    let payload = Payload {
+        encoding: Encoding::HexDump,
+        line: start + 1,
+        name: None,
+        data: Ok(data),
+    };
+    Some((payload, i))
+}
+
+struct HexLine {
+    words: Vec<String>,
+    // First word is an address (ends with a colon, or has 3 digits).
+    address: bool,
+}
+
+fn hex_line(line: &str) -> Option<HexLine> {
+    let mut words = vec![];
+    let mut address = false;
+    for (k, word) in line.split_whitespace().enumerate() {
+        let word = if k == 0 && word.ends_with(':') {
+            address = true;
+            &word[..word.len() - 1]
+        } else {
+            word
+        };
+        let is_hex = !word.is_empty() && word.chars().all(|c| c.is_ascii_hexdigit());
+        let len_ok = word.len() == 2 || word.len() == 4 || (k == 0 && word.len() == 3);
+        if !is_hex || !len_ok {
+            return None;
+        }
+        words.push(word.to_string());
+    }
+    // An odd-length first number has to be an address. A line of data
+    // needs at least a few numbers, or an address and two.
+    if words.first()?.len() == 3 {
+        address = true;
+    }
+    let min = if address { 3 } else { 4 };
+    if words.len() < min {
+        return None;
+    }
+    Some(HexLine { words, address })
+}
+
+fn number(word: &str) -> usize {
+    usize::from_str_radix(word, 16).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uuencode() {
+        let text = "\
+Here's the game:
+
+begin 644 cat.ch8
+#0V%T
+`
+end
+
+Enjoy!
+";
+        let payloads = scan(text);
+        assert_eq!(payloads, vec![Payload {
+            encoding: Encoding::Uuencode,
+            line: 3,
+            name: Some("cat.ch8".to_string()),
+            data: Ok(b"Cat".to_vec()),
+        }]);
+    }
+
+    #[test]
+    fn test_hex_dump() {
+        let text = "\
+The 2 games were written in 1990 for CAFE BABE.
+
+0200: 00 E0 A2 0A
+0204: 60 00 D0 05
+0208: 12 08 F0 90
+
+200 00E0 A20A
+204 6000 D005
+
+00E0 A20A 6000 D005
+
+200 00E0 A20A
+00E0 A20A 6000 D005
+";
+        let payloads = scan(text);
+        let data: Vec<Vec<u8>> = payloads.into_iter().map(|p| p.data.unwrap()).collect();
+        assert_eq!(data, vec![
+            vec![0x00, 0xE0, 0xA2, 0x0A, 0x60, 0x00, 0xD0, 0x05, 0x12, 0x08, 0xF0, 0x90],
+            vec![0x00, 0xE0, 0xA2, 0x0A, 0x60, 0x00, 0xD0, 0x05],
+            vec![0x00, 0xE0, 0xA2, 0x0A, 0x60, 0x00, 0xD0, 0x05],
+            vec![0x00, 0xE0, 0xA2, 0x0A, 0x00, 0xE0, 0xA2, 0x0A, 0x60, 0x00, 0xD0, 0x05],
+        ]);
+        assert!(is_plausible(&data[0]));
+        assert!(!is_plausible(&[0xFF, 0xFF, 0xFF, 0xFF]));
+    }
+
+    #[test]
+    fn test_asc() {
+        let text = "\
+Use ASC-> on this:
+
+%%HP: T(3)A(R)F(.);
+\"C2A2011000000E2AA22100
+1458\"
+";
+        let payloads = scan(text);
+        assert_eq!(payloads.len(), 1);
+        assert_eq!(payloads[0].encoding, Encoding::Asc);
+        assert_eq!(payloads[0].line, 3);
+        assert_eq!(payloads[0].data, Ok(vec![0x00, 0xE0, 0xA2, 0x2A, 0x12, 0x00]));
+    }
+}
@@ -0,0 +1,285 @@
+//! A GIF decoder, just enough to read Octo cartridges (see `octo`): it
+//! returns the color indices of each frame, and ignores everything else.
+
+/// Decoded frames, as palette indices, row by row.
+#[derive(Debug, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub frames: Vec<Vec<u8>>,
+}
+
+const MAX_CODE_SIZE: u32 = 12;
+
+pub fn is_gif(data: &[u8]) -> bool {
+    data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a")
+}
+
+pub fn decode(data: &[u8]) -> Result<Image, String> {
+    if !is_gif(data) {
+        return Err("not a GIF file".to_string());
+    }
+    let mut reader = Reader { data, pos: 6 };
+    let width = reader.u16()? as usize;
+    let height = reader.u16()? as usize;
+    let flags = reader.u8()?;
+    reader.skip(2)?;
+    if flags & 0x80 != 0 {
+        reader.skip(3 << ((flags & 7) + 1))?;
+    }
+
+    let mut frames = vec![];
+    loop {
+        match reader.u8()? {
+            // Extension: label and sub-blocks.
+            0x21 => {
+                reader.u8()?;
+                reader.sub_blocks()?;
+            }
+            0x2C => frames.push(reader.frame()?),
+            0x3B => break,
+            b => return Err(format!("unexpected block {:#04X}", b)),
+        }
+    }
+    Ok(Image { width, height, frames })
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn u8(&mut self) -> Result<u8, String> {
+        let b = *self.data.get(self.pos).ok_or("GIF file is truncated")?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(self.u8()? as u16 | (self.u8()? as u16) << 8)
+    }
+
+    fn skip(&mut self, n: usize) -> Result<(), String> {
+        if self.pos + n > self.data.len() {
+            return Err("GIF file is truncated".to_string());
+        }
+        self.pos += n;
+        Ok(())
+    }
+
+    fn sub_blocks(&mut self) -> Result<Vec<u8>, String> {
+        let mut result = vec![];
+        loop {
+            let len = self.u8()? as usize;
+            if len == 0 {
+                return Ok(result);
+            }
+            let start = self.pos;
+            self.skip(len)?;
+            result.extend(&self.data[start..start + len]);
+        }
+    }
+
+    fn frame(&mut self) -> Result<Vec<u8>, String> {
+        self.skip(4)?;
+        let width = self.u16()? as usize;
+        let height = self.u16()? as usize;
+        let flags = self.u8()?;
+        if flags & 0x80 != 0 {
+            self.skip(3 << ((flags & 7) + 1))?;
+        }
+        let min_code_size = self.u8()? as u32;
+        if !(2..MAX_CODE_SIZE).contains(&min_code_size) {
+            return Err(format!("bad LZW code size: {}", min_code_size));
+        }
+        let data = self.sub_blocks()?;
+        let mut pixels = lzw_decode(&data, min_code_size, width * height)?;
+        pixels.resize(width * height, 0);
+        if flags & 0x40 != 0 {
+            pixels = deinterlace(&pixels, width, height);
+        }
+        Ok(pixels)
+    }
+}
+
+fn lzw_decode(data: &[u8], min_code_size: u32, size: usize) -> Result<Vec<u8>, String> {
+    let clear = 1 << min_code_size;
+    let end = clear + 1;
+    // Each code is an earlier code (prefix) plus a byte.
+    let mut table: Vec<(Option<u16>, u8)> = vec![];
+    let reset = |table: &mut Vec<(Option<u16>, u8)>| {
+        table.clear();
+        table.extend((0..clear + 2).map(|c| (None, c as u8)));
+    };
+    reset(&mut table);
+
+    let mut code_size = min_code_size + 1;
+    let mut prev: Option<u16> = None;
+    let mut pixels = vec![];
+    let mut bits: u32 = 0;
+    let mut nbits = 0;
+    let mut bytes = data.iter();
+    while pixels.len() < size {
+        while nbits < code_size {
+            match bytes.next() {
+                Some(&b) => bits |= (b as u32) << nbits,
+                None => return Ok(pixels),
+            }
+            nbits += 8;
+        }
+        let code = (bits & ((1 << code_size) - 1)) as u16;
+        bits >>= code_size;
+        nbits -= code_size;
+
+        if code == clear {
+            reset(&mut table);
+            code_size = min_code_size + 1;
+            prev = None;
+            continue;
+        }
+        if code == end {
+            break;
+        }
+        let known = (code as usize) < table.len();
+        let start = pixels.len();
+        match (known, prev) {
+            (true, _) => expand(&table, code, &mut pixels),
+            // The code being defined: previous string plus its first byte.
+            (false, Some(p)) if code as usize == table.len() => {
+                expand(&table, p, &mut pixels);
+                pixels.push(pixels[start]);
+            }
+            _ => return Err(format!("bad LZW code: {}", code)),
+        }
+        if let Some(p) = prev {
+            if table.len() < 1 << MAX_CODE_SIZE {
+                table.push((Some(p), pixels[start]));
+            }
+        }
+        if table.len() == 1 << code_size && code_size < MAX_CODE_SIZE {
+            code_size += 1;
+        }
+        prev = Some(code);
+    }
+    Ok(pixels)
+}
+
+fn expand(table: &[(Option<u16>, u8)], code: u16, pixels: &mut Vec<u8>) {
+    let start = pixels.len();
+    let mut code = Some(code);
+    while let Some(c) = code {
+        let (prefix, byte) = table[c as usize];
+        pixels.push(byte);
+        code = prefix;
+    }
+    pixels[start..].reverse();
+}
+
+/// Interlaced images store every 8th row starting from 0, then every 8th
+/// from 4, every 4th from 2, and every 2nd from 1.
+fn deinterlace(pixels: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let rows = [(0, 8), (4, 8), (2, 4), (1, 2)].iter()
+        .flat_map(|&(start, step)| (start..height).step_by(step));
+    let mut result = vec![0; pixels.len()];
+    for (i, y) in rows.enumerate() {
+        result[y * width..(y + 1) * width].copy_from_slice(&pixels[i * width..(i + 1) * width]);
+    }
+    result
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// Encodes an image with a 256-color palette. To keep it simple, the
+    /// LZW data has only single-byte codes, with a clear code often enough
+    /// that the code size stays at 9 bits.
+    pub fn encode(width: usize, height: usize, frames: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = b"GIF89a".to_vec();
+        data.extend(&[width as u8, (width >> 8) as u8, height as u8, (height >> 8) as u8]);
+        data.extend(&[0xF7, 0, 0]);
+        data.extend((0..256).flat_map(|c| vec![c as u8; 3]));
+        // Some extension, to be skipped.
+        data.extend(&[0x21, 0xF9, 4, 0, 0, 0, 0, 0]);
+        for pixels in frames {
+            data.push(0x2C);
+            data.extend(&[0, 0, 0, 0]);
+            data.extend(&[width as u8, (width >> 8) as u8, height as u8, (height >> 8) as u8]);
+            data.extend(&[0, 8]);
+
+            let mut codes = vec![];
+            for chunk in pixels.chunks(250) {
+                codes.push(0x100);
+                codes.extend(chunk.iter().map(|&p| p as u32));
+            }
+            codes.push(0x101);
+            let mut bytes = vec![];
+            let (mut bits, mut nbits) = (0u32, 0);
+            for code in codes {
+                bits |= code << nbits;
+                nbits += 9;
+                while nbits >= 8 {
+                    bytes.push(bits as u8);
+                    bits >>= 8;
+                    nbits -= 8;
+                }
+            }
+            if nbits > 0 {
+                bytes.push(bits as u8);
+            }
+            for block in bytes.chunks(255) {
+                data.push(block.len() as u8);
+                data.extend(block);
+            }
+            data.push(0);
+        }
+        data.push(0x3B);
+        data
+    }
+
+    #[test]
+    fn test_decode() {
+        // The smallest GIF: one transparent pixel.
+        let pixel = b"GIF89a\x01\x00\x01\x00\x80\x00\x00\xff\xff\xff\x00\x00\x00\
+                      !\xf9\x04\x01\x00\x00\x00\x00,\x00\x00\x00\x00\x01\x00\x01\x00\
+                      \x00\x02\x02D\x01\x00;";
+        assert_eq!(decode(pixel), Ok(Image { width: 1, height: 1, frames: vec![vec![0]] }));
+
+        let frames = vec![
+            (0..600).map(|i| (i * 7) as u8).collect(),
+            vec![0xAB; 600],
+        ];
+        assert_eq!(decode(&encode(30, 20, &frames)),
+                   Ok(Image { width: 30, height: 20, frames: frames.clone() }));
+
+        assert!(decode(&pixel[..30]).is_err());
+        assert!(decode(b"\x89PNG").is_err());
+    }
+
+    #[test]
+    fn test_lzw() {
+        // Codes with min size 2: clear, 1, 1 (defines 6 = 1 1), 6 (defines
+        // 7 = 1 1, and the code size grows to 4), 8 (not defined yet, so
+        // 6 plus its first byte), end.
+        let codes: &[(u32, u32)] = &[(4, 3), (1, 3), (1, 3), (6, 3), (8, 4), (5, 4)];
+        let mut bytes = vec![];
+        let (mut bits, mut nbits) = (0u32, 0);
+        for &(code, size) in codes {
+            bits |= code << nbits;
+            nbits += size;
+        }
+        while nbits > 0 {
+            bytes.push(bits as u8);
+            bits >>= 8;
+            nbits = nbits.saturating_sub(8);
+        }
+        assert_eq!(lzw_decode(&bytes, 2, 100), Ok(vec![1; 7]));
+    }
+
+    #[test]
+    fn test_deinterlace() {
+        let pixels: Vec<u8> = (0..10).collect();
+        assert_eq!(deinterlace(&pixels, 1, 10), vec![0, 5, 3, 6, 2, 7, 4, 8, 1, 9]);
+    }
+}
@@ -0,0 +1,51 @@
+use std::io;
+
+use crate::Chip;
+
+/// Observes the execution of a `Chip`. Used for tracing, profiling and
+/// similar tools. All methods do nothing by default.
+pub trait Hook {
+    /// Called before executing the instruction at `pc` (the raw opcode is
+    /// `b`).
+    fn before_step(&mut self, _chip: &Chip, _pc: u16, _b: u16) {}
+
+    /// Called after executing an instruction, with the result of the step.
+    fn after_step(&mut self, _chip: &Chip, _result: &Result<(), String>) {}
+
+    /// Called on every timer tick, i.e. once per frame.
+    fn on_tick(&mut self, _chip: &Chip) {}
+
+    /// Called when the emulator is done, to write out any results.
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Hook for () {}
+
+impl Hook for Vec<Box<dyn Hook>> {
+    fn before_step(&mut self, chip: &Chip, pc: u16, b: u16) {
+        for hook in self.iter_mut() {
+            hook.before_step(chip, pc, b);
+        }
+    }
+
+    fn after_step(&mut self, chip: &Chip, result: &Result<(), String>) {
+        for hook in self.iter_mut() {
+            hook.after_step(chip, result);
+        }
+    }
+
+    fn on_tick(&mut self, chip: &Chip) {
+        for hook in self.iter_mut() {
+            hook.on_tick(chip);
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        for hook in self.iter_mut() {
+            hook.finish()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use rand::Rng;
+use rand::rngs::SmallRng;
+
+use crate::KEYS_SIZE;
+use crate::display::Display;
+
+/// Receives the screen contents, once per frame (on every timer tick).
+pub trait FrameSink {
+    fn frame(&mut self, display: &Display);
+}
+
+/// Source of key presses, polled before every instruction. This is an
+/// alternative to calling `Chip::key_down` and `Chip::key_up`.
+pub trait Keypad {
+    /// Current state of all the keys (true means pressed).
+    fn poll(&mut self) -> [bool; KEYS_SIZE];
+}
+
+/// Receives the state of the buzzer, whenever it changes.
+pub trait Beeper {
+    fn set(&mut self, on: bool);
+
+    /// Starts playing a digitized sound (MegaChip), or stops it if `None`.
+    /// Ignored by default.
+    fn play(&mut self, _sample: Option<&Sample>) {}
+}
+
+// Shared sinks, so that the frontend can keep a handle to them (e.g. to
+// change settings) while they're installed in a `Chip`.
+impl<T: FrameSink> FrameSink for Rc<RefCell<T>> {
+    fn frame(&mut self, display: &Display) {
+        self.borrow_mut().frame(display);
+    }
+}
+
+impl<T: Beeper> Beeper for Rc<RefCell<T>> {
+    fn set(&mut self, on: bool) {
+        self.borrow_mut().set(on);
+    }
+
+    fn play(&mut self, sample: Option<&Sample>) {
+        self.borrow_mut().play(sample);
+    }
+}
+
+/// Digitized sound: 8-bit unsigned samples.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Sample {
+    /// Samples per second.
+    pub rate: u32,
+    pub data: Vec<u8>,
+    pub looping: bool,
+}
+
+/// Random numbers for the RND instruction.
+pub trait RandomSource {
+    fn next_u8(&mut self) -> u8;
+}
+
+impl RandomSource for SmallRng {
+    fn next_u8(&mut self) -> u8 {
+        self.gen()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Chip;
+    use crate::instr::Instr::*;
+
+    struct Frames(Rc<RefCell<Vec<bool>>>);
+
+    impl FrameSink for Frames {
+        fn frame(&mut self, display: &Display) {
+            self.0.borrow_mut().push(display.at(1, 1));
+        }
+    }
+
+    struct Buzzer(Rc<RefCell<Vec<bool>>>);
+
+    impl Beeper for Buzzer {
+        fn set(&mut self, on: bool) {
+            self.0.borrow_mut().push(on);
+        }
+    }
+
+    // Returns each keypad state in turn, then keeps the last one.
+    struct Script(Vec<[bool; KEYS_SIZE]>);
+
+    impl Keypad for Script {
+        fn poll(&mut self) -> [bool; KEYS_SIZE] {
+            if self.0.len() > 1 { self.0.remove(0) } else { self.0[0] }
+        }
+    }
+
+    struct Counter(u8);
+
+    impl RandomSource for Counter {
+        fn next_u8(&mut self) -> u8 {
+            self.0 += 1;
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_host() {
+        let frames = Rc::new(RefCell::new(vec![]));
+        let beeps = Rc::new(RefCell::new(vec![]));
+        let mut pressed = [false; KEYS_SIZE];
+        pressed[7] = true;
+
+        let mut chip = Chip::new();
+        chip.frame_sink = Some(Box::new(Frames(frames.clone())));
+        chip.beeper = Some(Box::new(Buzzer(beeps.clone())));
+        let released = [false; KEYS_SIZE];
+        chip.keypad = Some(Box::new(Script(vec![released, released, released, pressed])));
+        chip.set_random_source(Box::new(Counter(0)));
+
+        chip.memory.load_program(0x200, &[
+            RND(0, 0xFF),
+            RND(1, 0xFF),
+            LD_R_K(2),
+            LD_R_B(3, 2),
+            LD_ST_R(3),
+            LD_I_A(0x300),
+            DRW(0, 0, 1),
+            JP(0xFFF),
+        ]);
+        chip.memory.bytes[0x300] = 0x80;
+        chip.jump(0x200);
+        while chip.pc() != 0xFFF {
+            chip.step().unwrap();
+        }
+        for _ in 0..3 {
+            chip.tick();
+        }
+
+        assert_eq!(&chip.v()[..3], &[1, 2, 7]);
+        assert_eq!(*frames.borrow(), vec![true, true, true]);
+        assert_eq!(*beeps.borrow(), vec![true, false]);
+    }
+}
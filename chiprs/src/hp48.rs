@@ -0,0 +1,233 @@
+//! CHIP-48 games were distributed as HP-48 string objects, either in binary
+//! transfer files (starting with `HPHP48-`), or as text to be decoded on
+//! the calculator with ASC-> (this is also what CHIPPER writes with
+//! `OPTION HPASC`). The ROM is the contents of the string.
+
+use std::fmt;
+
+const BINARY_MAGIC: &[u8] = b"HPHP48-";
+// "HPHP48-" and a ROM version letter.
+const BINARY_HEADER: usize = 8;
+// Header of the text transfer format, e.g. `%%HP: T(3)A(R)F(.);`.
+const TEXT_HEADER: &str = "%%HP:";
+
+// Objects start with a 5-nibble prolog saying what they are.
+const PROLOG_NIBBLES: usize = 5;
+const DOCSTR: u32 = 0x02A2C;
+// Followed by a 5-nibble length, which counts itself.
+const LENGTH_NIBBLES: usize = 5;
+// ASC-> strings end with a CRC of the object.
+const CRC_NIBBLES: usize = 4;
+
+#[derive(Debug, PartialEq)]
+pub enum DecodeError {
+    /// Something other than a hex digit in an ASC-> string.
+    BadCharacter(char),
+    /// ASC-> string without the closing quote.
+    Unterminated,
+    /// File ends in the middle of the object.
+    Truncated,
+    BadChecksum { expected: u16, actual: u16 },
+    /// Object is not a string (the prolog is given).
+    NotAString(u32),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::BadCharacter(c) =>
+                write!(f, "unexpected {:?} in ASC string", c),
+            DecodeError::Unterminated => write!(f, "ASC string is not terminated"),
+            DecodeError::Truncated => write!(f, "HP-48 object is truncated"),
+            DecodeError::BadChecksum { expected, actual } =>
+                write!(f, "bad checksum: expected #{:X}h, got #{:X}h", expected, actual),
+            DecodeError::NotAString(prolog) =>
+                write!(f, "HP-48 object is not a string (prolog #{:05X}h)", prolog),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// If `file` is an HP-48 container, returns the ROM inside it.
+pub fn unwrap(file: &[u8]) -> Option<Result<Vec<u8>, DecodeError>> {
+    if file.starts_with(BINARY_MAGIC) {
+        return Some(decode_binary(file));
+    }
+    let text = std::str::from_utf8(file).ok()?;
+    if is_asc(text) {
+        Some(decode_asc(text))
+    } else {
+        None
+    }
+}
+
+/// Is `text` an ASC-> string: hex digits in quotes, long enough for the
+/// object header and the CRC, possibly after a text transfer header?
+fn is_asc(text: &str) -> bool {
+    let mut text = text.trim();
+    if let Some(rest) = text.strip_prefix(TEXT_HEADER) {
+        text = match rest.find(';') {
+            Some(end) => rest[end + 1..].trim(),
+            None => return false,
+        };
+    }
+    let body = match text.strip_prefix('"').and_then(|rest| rest.strip_suffix('"')) {
+        Some(body) => body,
+        None => return false,
+    };
+    let digits = body.chars().filter(|c| !c.is_whitespace()).count();
+    digits >= PROLOG_NIBBLES + LENGTH_NIBBLES + CRC_NIBBLES &&
+        body.chars().all(|c| c.is_ascii_hexdigit() || c.is_whitespace())
+}
+
+/// Decodes a binary transfer file. The object follows the header, packed 2
+/// nibbles per byte.
+pub fn decode_binary(file: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    let data = file.get(BINARY_HEADER..).ok_or(DecodeError::Truncated)?;
+    let nibbles: Vec<u8> = data.iter().flat_map(|&b| vec![b & 0xF, b >> 4]).collect();
+    string_contents(&nibbles)
+}
+
+/// Decodes the output of ->ASC: a string of hex digits, one per nibble in
+/// memory order, ending with a CRC. Whitespace is ignored, and so is the
+/// text transfer header if present.
+pub fn decode_asc(text: &str) -> Result<Vec<u8>, DecodeError> {
+    let mut text = text.trim();
+    if text.starts_with(TEXT_HEADER) {
+        let end = text.find(';').map_or(text.len(), |i| i + 1);
+        text = text[end..].trim();
+    }
+    if let Some(rest) = text.strip_prefix('"') {
+        let end = rest.find('"').ok_or(DecodeError::Unterminated)?;
+        text = &rest[..end];
+    }
+
+    let mut nibbles = vec![];
+    for c in text.chars() {
+        match c.to_digit(16) {
+            Some(n) => nibbles.push(n as u8),
+            None if c.is_whitespace() => (),
+            None => return Err(DecodeError::BadCharacter(c)),
+        }
+    }
+    if nibbles.len() < CRC_NIBBLES {
+        return Err(DecodeError::Truncated);
+    }
+    let (object, crc) = nibbles.split_at(nibbles.len() - CRC_NIBBLES);
+    let expected = number(crc) as u16;
+    let actual = checksum(object);
+    if expected != actual {
+        return Err(DecodeError::BadChecksum { expected, actual });
+    }
+    string_contents(object)
+}
+
+fn string_contents(nibbles: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    let header = PROLOG_NIBBLES + LENGTH_NIBBLES;
+    if nibbles.len() < header {
+        return Err(DecodeError::Truncated);
+    }
+    let prolog = number(&nibbles[..PROLOG_NIBBLES]);
+    if prolog != DOCSTR {
+        return Err(DecodeError::NotAString(prolog));
+    }
+    let length = number(&nibbles[PROLOG_NIBBLES..header]) as usize;
+    let end = PROLOG_NIBBLES + length;
+    if length < LENGTH_NIBBLES || end > nibbles.len() {
+        return Err(DecodeError::Truncated);
+    }
+    Ok(nibbles[header..end].chunks(2)
+       .filter(|pair| pair.len() == 2)
+       .map(|pair| pair[0] | (pair[1] << 4))
+       .collect())
+}
+
+/// A number stored in nibbles, least significant first.
+fn number(nibbles: &[u8]) -> u32 {
+    nibbles.iter().rev().fold(0, |acc, &n| (acc << 4) | n as u32)
+}
+
+/// The CRC computed by the calculator (and shown by BYTES).
+fn checksum(nibbles: &[u8]) -> u16 {
+    nibbles.iter().fold(0, |crc, &n| {
+        (crc >> 4) ^ (((crc ^ n as u16) & 0xF) * 0x1081)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROM: &[u8] = &[0x00, 0xE0, 0xA2, 0x2A, 0x12, 0x00];
+
+    fn string_object(rom: &[u8]) -> Vec<u8> {
+        let mut nibbles = vec![];
+        let length = (LENGTH_NIBBLES + rom.len() * 2) as u32;
+        for i in 0..5 {
+            nibbles.push(((DOCSTR >> (i * 4)) & 0xF) as u8);
+        }
+        for i in 0..5 {
+            nibbles.push(((length >> (i * 4)) & 0xF) as u8);
+        }
+        for &b in rom {
+            nibbles.push(b & 0xF);
+            nibbles.push(b >> 4);
+        }
+        nibbles
+    }
+
+    fn asc(nibbles: &[u8]) -> String {
+        let crc = checksum(nibbles);
+        let mut text = String::from("%%HP: T(3)A(R)F(.);\n\"");
+        for (i, n) in nibbles.iter().enumerate() {
+            if i > 0 && i % 64 == 0 {
+                text.push('\n');
+            }
+            text.push_str(&format!("{:X}", n));
+        }
+        for i in 0..4 {
+            text.push_str(&format!("{:X}", (crc >> (i * 4)) & 0xF));
+        }
+        text.push_str("\"\n");
+        text
+    }
+
+    #[test]
+    fn test_binary() {
+        let mut file = b"HPHP48-E".to_vec();
+        let nibbles = string_object(ROM);
+        file.extend(nibbles.chunks(2).map(|pair| pair[0] | (pair[1] << 4)));
+        assert_eq!(&file[8..11], &[0x2C, 0x2A, 0x10]);
+        assert_eq!(unwrap(&file), Some(Ok(ROM.to_vec())));
+
+        file.truncate(12);
+        assert_eq!(unwrap(&file), Some(Err(DecodeError::Truncated)));
+    }
+
+    #[test]
+    fn test_asc() {
+        let text = asc(&string_object(ROM));
+        assert!(text.contains("\"C2A2011000000E2AA2210"));
+        assert_eq!(unwrap(text.as_bytes()), Some(Ok(ROM.to_vec())));
+        // Without the header.
+        let text = text.lines().skip(1).collect::<Vec<_>>().join("\n");
+        assert_eq!(decode_asc(&text), Ok(ROM.to_vec()));
+
+        let bad = text.replacen("E2A", "E3A", 1);
+        assert!(matches!(decode_asc(&bad), Err(DecodeError::BadChecksum { .. })));
+        let mut object = string_object(ROM);
+        object[0] = 0xD;
+        assert_eq!(decode_asc(&asc(&object)), Err(DecodeError::NotAString(0x02A2D)));
+        assert_eq!(decode_asc("\"C2A2"), Err(DecodeError::Unterminated));
+        assert_eq!(decode_asc("\"C2AX\""), Err(DecodeError::BadCharacter('X')));
+
+        assert_eq!(unwrap(ROM), None);
+        // Other text files that happen to start with a quote.
+        assert_eq!(unwrap(b"\"Hello\", he said.\n"), None);
+        assert_eq!(unwrap(b"\"C2A2\"\n"), None);
+        assert_eq!(unwrap(b"%%HP: T(3)A(R)F(.);\n\\<< 1 2 + \\>>\n"), None);
+        // The right form, but a bad checksum.
+        assert!(matches!(unwrap(bad.as_bytes()), Some(Err(DecodeError::BadChecksum { .. }))));
+    }
+}
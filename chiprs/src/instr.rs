@@ -1,3 +1,5 @@
+use std::fmt;
+
 type Reg = u8;
 type Addr = u16;
 
@@ -46,73 +48,332 @@ pub enum Instr {
     DRW(Reg, Reg, u8),
     SKP(Reg),
     SKNP(Reg),
+
+    // SUPER-CHIP
+    SCD(u8),
+    SCR,
+    SCL,
+    EXIT,
+    LOW,
+    HIGH,
+    LD_HF_R(Reg),
+    LD_RPL_R(Reg),
+    LD_R_RPL(Reg),
+
+    // XO-CHIP
+    SCU(u8),
+    LD_II_RR(Reg, Reg),
+    LD_RR_II(Reg, Reg),
+    /// Loads I from the 16 bits following the instruction.
+    LD_I_LONG,
+    PLANE(u8),
+    AUDIO,
+    PITCH(Reg),
+
+    // CHIP-8X
+    BG,
+    ADDN(Reg, Reg),
+    COL(Reg, Reg, u8),
+    SKP2(Reg),
+    SKNP2(Reg),
+    OUT(Reg),
+    IN(Reg),
+
+    // CHIP-8E
+    SGT_R(Reg, Reg),
+    /// Like LD_II_RR, but leaves I pointing past the stored registers.
+    LD_IIP_RR(Reg, Reg),
+    LD_RR_IIP(Reg, Reg),
+    SKIP(Reg),
+    LD_DT_R_WAIT(Reg),
+    JP_BACK(u8),
+    JP_FWD(u8),
+
+    // CHIP-8E and CHIP-8I
+    OUT3(Reg),
+
+    // CHIP-8I
+    IN3(Reg),
+    IN3_WAIT(Reg),
+
+    // Hires (64x64) CHIP-8
+    /// Clears the screen, by calling the machine code routine at 0x230.
+    CLS_HIRES,
+
+    // MegaChip
+    MEGA_OFF,
+    MEGA_ON,
+    /// Loads I with a 24-bit address: `nn` and the 16 bits following the
+    /// instruction.
+    LDHI(u8),
+    LDPAL(u8),
+    SPRW(u8),
+    SPRH(u8),
+    ALPHA(u8),
+    /// Plays the sound at I, once if the argument is 1, otherwise in a loop.
+    DIGISND(u8),
+    STOPSND,
+    BMODE(u8),
+    CCOL(u8),
+}
+
+/// Entry of a decoding table: opcodes `b` with `b & mask == pattern` decode
+/// to `decode(b)`.
+pub struct Opcode {
+    pub mask: u16,
+    pub pattern: u16,
+    pub decode: fn(u16) -> Instr,
+}
+
+const fn op(mask: u16, pattern: u16, decode: fn(u16) -> Instr) -> Opcode {
+    Opcode { mask, pattern, decode }
+}
+
+fn x(b: u16) -> u8 { ((b & 0x0F00) >> 8) as u8 }
+fn y(b: u16) -> u8 { ((b & 0x00F0) >> 4) as u8 }
+fn n(b: u16) -> u8 { (b & 0x000F) as u8 }
+fn nn(b: u16) -> u8 { (b & 0x00FF) as u8 }
+fn nnn(b: u16) -> u16 { b & 0x0FFF }
+
+/// The original instruction set.
+pub const CHIP8: &[Opcode] = &[
+    op(0xFFFF, 0x00E0, |_| Instr::CLS),
+    op(0xFFFF, 0x00EE, |_| Instr::RET),
+    op(0xF000, 0x0000, |b| Instr::SYS(nnn(b))),
+    op(0xF000, 0x1000, |b| Instr::JP(nnn(b))),
+    op(0xF000, 0x2000, |b| Instr::CALL(nnn(b))),
+    op(0xF000, 0x3000, |b| Instr::SE(x(b), nn(b))),
+    op(0xF000, 0x4000, |b| Instr::SNE(x(b), nn(b))),
+    op(0xF00F, 0x5000, |b| Instr::SE_R(x(b), y(b))),
+    op(0xF000, 0x6000, |b| Instr::LD_R_B(x(b), nn(b))),
+    op(0xF000, 0x7000, |b| Instr::ADD_R_B(x(b), nn(b))),
+    op(0xF00F, 0x8000, |b| Instr::LD_R_R(x(b), y(b))),
+    op(0xF00F, 0x8001, |b| Instr::OR(x(b), y(b))),
+    op(0xF00F, 0x8002, |b| Instr::AND(x(b), y(b))),
+    op(0xF00F, 0x8003, |b| Instr::XOR(x(b), y(b))),
+    op(0xF00F, 0x8004, |b| Instr::ADD(x(b), y(b))),
+    op(0xF00F, 0x8005, |b| Instr::SUB(x(b), y(b))),
+    op(0xF00F, 0x8006, |b| Instr::SHR(x(b), y(b))),
+    op(0xF00F, 0x8007, |b| Instr::SUBN(x(b), y(b))),
+    op(0xF00F, 0x800E, |b| Instr::SHL(x(b), y(b))),
+    op(0xF00F, 0x9000, |b| Instr::SNE_R(x(b), y(b))),
+    op(0xF000, 0xA000, |b| Instr::LD_I_A(nnn(b))),
+    op(0xF000, 0xB000, |b| Instr::JP_V0(nnn(b))),
+    op(0xF000, 0xC000, |b| Instr::RND(x(b), nn(b))),
+    op(0xF000, 0xD000, |b| Instr::DRW(x(b), y(b), n(b))),
+    op(0xF0FF, 0xE09E, |b| Instr::SKP(x(b))),
+    op(0xF0FF, 0xE0A1, |b| Instr::SKNP(x(b))),
+    op(0xF0FF, 0xF007, |b| Instr::LD_R_DT(x(b))),
+    op(0xF0FF, 0xF00A, |b| Instr::LD_R_K(x(b))),
+    op(0xF0FF, 0xF015, |b| Instr::LD_DT_R(x(b))),
+    op(0xF0FF, 0xF018, |b| Instr::LD_ST_R(x(b))),
+    op(0xF0FF, 0xF01E, |b| Instr::ADD_I_R(x(b))),
+    op(0xF0FF, 0xF029, |b| Instr::LD_F_R(x(b))),
+    op(0xF0FF, 0xF033, |b| Instr::LD_B_R(x(b))),
+    op(0xF0FF, 0xF055, |b| Instr::LD_II_R(x(b))),
+    op(0xF0FF, 0xF065, |b| Instr::LD_R_II(x(b))),
+];
+
+/// SUPER-CHIP additions, to be used before `CHIP8` (they take over some of
+/// the 0nnn machine code calls).
+pub const SCHIP: &[Opcode] = &[
+    op(0xFFF0, 0x00C0, |b| Instr::SCD(n(b))),
+    op(0xFFFF, 0x00FB, |_| Instr::SCR),
+    op(0xFFFF, 0x00FC, |_| Instr::SCL),
+    op(0xFFFF, 0x00FD, |_| Instr::EXIT),
+    op(0xFFFF, 0x00FE, |_| Instr::LOW),
+    op(0xFFFF, 0x00FF, |_| Instr::HIGH),
+    op(0xF0FF, 0xF030, |b| Instr::LD_HF_R(x(b))),
+    op(0xF0FF, 0xF075, |b| Instr::LD_RPL_R(x(b))),
+    op(0xF0FF, 0xF085, |b| Instr::LD_R_RPL(x(b))),
+];
+
+/// XO-CHIP additions, to be used before `SCHIP` and `CHIP8`.
+pub const XOCHIP: &[Opcode] = &[
+    op(0xFFF0, 0x00D0, |b| Instr::SCU(n(b))),
+    op(0xF00F, 0x5002, |b| Instr::LD_II_RR(x(b), y(b))),
+    op(0xF00F, 0x5003, |b| Instr::LD_RR_II(x(b), y(b))),
+    op(0xFFFF, 0xF000, |_| Instr::LD_I_LONG),
+    op(0xF0FF, 0xF001, |b| Instr::PLANE(x(b))),
+    op(0xFFFF, 0xF002, |_| Instr::AUDIO),
+    op(0xF0FF, 0xF03A, |b| Instr::PITCH(x(b))),
+];
+
+/// CHIP-8X additions, to be used before `CHIP8` (Bxyn replaces JP V0).
+pub const CHIP8X: &[Opcode] = &[
+    op(0xFFFF, 0x02A0, |_| Instr::BG),
+    op(0xF00F, 0x5001, |b| Instr::ADDN(x(b), y(b))),
+    op(0xF000, 0xB000, |b| Instr::COL(x(b), y(b), n(b))),
+    op(0xF0FF, 0xE0F2, |b| Instr::SKP2(x(b))),
+    op(0xF0FF, 0xE0F5, |b| Instr::SKNP2(x(b))),
+    op(0xF0FF, 0xF0F8, |b| Instr::OUT(x(b))),
+    op(0xF0FF, 0xF0FB, |b| Instr::IN(x(b))),
+];
+
+/// CHIP-8E additions, to be used before `CHIP8` (BBnn and BFnn replace
+/// some of the JP V0 instructions).
+pub const CHIP8E: &[Opcode] = &[
+    op(0xF00F, 0x5001, |b| Instr::SGT_R(x(b), y(b))),
+    op(0xF00F, 0x5002, |b| Instr::LD_IIP_RR(x(b), y(b))),
+    op(0xF00F, 0x5003, |b| Instr::LD_RR_IIP(x(b), y(b))),
+    op(0xFF00, 0xBB00, |b| Instr::JP_BACK(nn(b))),
+    op(0xFF00, 0xBF00, |b| Instr::JP_FWD(nn(b))),
+    op(0xF0FF, 0xF003, |b| Instr::OUT3(x(b))),
+    op(0xF0FF, 0xF01B, |b| Instr::SKIP(x(b))),
+    op(0xF0FF, 0xF04F, |b| Instr::LD_DT_R_WAIT(x(b))),
+];
+
+/// CHIP-8I (I/O) additions, to be used before `CHIP8`.
+pub const CHIP8I: &[Opcode] = &[
+    op(0xF0FF, 0xF003, |b| Instr::OUT3(x(b))),
+    op(0xF0FF, 0xF0E3, |b| Instr::IN3_WAIT(x(b))),
+    op(0xF0FF, 0xF0E7, |b| Instr::IN3(x(b))),
+];
+
+/// Hires CHIP-8 additions, to be used before `CHIP8`.
+pub const HIRES: &[Opcode] = &[
+    op(0xFFFF, 0x0230, |_| Instr::CLS_HIRES),
+];
+
+/// MegaChip additions, to be used before `SCHIP` and `CHIP8`.
+pub const MEGACHIP: &[Opcode] = &[
+    op(0xFFFF, 0x0010, |_| Instr::MEGA_OFF),
+    op(0xFFFF, 0x0011, |_| Instr::MEGA_ON),
+    op(0xFF00, 0x0100, |b| Instr::LDHI(nn(b))),
+    op(0xFF00, 0x0200, |b| Instr::LDPAL(nn(b))),
+    op(0xFF00, 0x0300, |b| Instr::SPRW(nn(b))),
+    op(0xFF00, 0x0400, |b| Instr::SPRH(nn(b))),
+    op(0xFF00, 0x0500, |b| Instr::ALPHA(nn(b))),
+    op(0xFFF0, 0x0600, |b| Instr::DIGISND(n(b))),
+    op(0xFFFF, 0x0700, |_| Instr::STOPSND),
+    op(0xFFF0, 0x0800, |b| Instr::BMODE(n(b))),
+    op(0xFF00, 0x0900, |b| Instr::CCOL(nn(b))),
+];
+
+/// Every table, for listing all the instruction kinds.
+pub const ALL: &[&[Opcode]] = &[CHIP8, SCHIP, XOCHIP, CHIP8X, CHIP8E, CHIP8I, HIRES, MEGACHIP];
+
+/// Names of all instruction kinds (see `Instr::name`), without repeats.
+pub fn names() -> Vec<&'static str> {
+    let mut names = vec![];
+    for op in ALL.iter().flat_map(|table| table.iter()) {
+        let name = (op.decode)(op.pattern).name();
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+/// Decodes `b` using the first matching entry of the first table that has
+/// one.
+pub fn decode(tables: &[&[Opcode]], b: u16) -> Option<Instr> {
+    tables.iter()
+        .flat_map(|table| table.iter())
+        .find(|op| b & op.mask == op.pattern)
+        .map(|op| (op.decode)(b))
 }
 
 impl Instr {
+    /// Decodes a CHIP-8 instruction. See `Platform::decode` for other
+    /// dialects.
     pub fn from(b: u16) -> Option<Instr> {
+        decode(&[CHIP8], b)
+    }
+
+    /// Size in bytes, including any operand following the opcode.
+    pub fn size(self) -> usize {
+        match self {
+            Instr::LD_I_LONG | Instr::LDHI(_) => 4,
+            _ => 2,
+        }
+    }
+
+    /// Name of the instruction kind, e.g. `"LD_R_B"` for `LD_R_B(1, 2)`.
+    pub fn name(self) -> &'static str {
         use self::Instr::*;
 
-        let a: u8 = ((b & 0xF000) >> 12) as u8;
-        let x: u8 = ((b & 0x0F00) >> 8) as u8;
-        let y: u8 = ((b & 0x00F0) >> 4) as u8;
-        let z: u8 = (b & 0x000F) as u8;
-        let yz: u8 = (b & 0x00FF) as u8;
-        let xyz: u16 = b & 0x0FFF;
-
-        match a {
-            0x0 => match xyz {
-                0x0E0 => Some(CLS),
-                0x0EE => Some(RET),
-                _ => Some(SYS(xyz)),
-            },
-            0x1 => Some(JP(xyz)),
-            0x2 => Some(CALL(xyz)),
-            0x3 => Some(SE(x, yz)),
-            0x4 => Some(SNE(x, yz)),
-            0x5 => match z {
-                0 =>  Some(SE_R(x, y)),
-                _ => None,
-            },
-            0x6 => Some(LD_R_B(x, yz)),
-            0x7 => Some(ADD_R_B(x, yz)),
-            0x8 => match z {
-                0 => Some(LD_R_R(x, y)),
-                1 => Some(OR(x, y)),
-                2 => Some(AND(x, y)),
-                3 => Some(XOR(x, y)),
-                4 => Some(ADD(x, y)),
-                5 => Some(SUB(x, y)),
-                6 => Some(SHR(x, y)),
-                7 => Some(SUBN(x, y)),
-                0xE => Some(SHL(x, y)),
-                _ => None,
-            },
-            0x9 => match z {
-                0 => Some(SNE_R(x, y)),
-                _ => None,
-            },
-            0xA => Some(LD_I_A(xyz)),
-            0xB => Some(JP_V0(xyz)),
-            0xC => Some(RND(x, yz)),
-            0xD => Some(DRW(x, y, z)),
-            0xE => match yz {
-                0x9E => Some(SKP(x)),
-                0xA1 => Some(SKNP(x)),
-                _ => None,
-            },
-            0xF => match yz {
-                0x07 => Some(LD_R_DT(x)),
-                0x0A => Some(LD_R_K(x)),
-                0x15 => Some(LD_DT_R(x)),
-                0x18 => Some(LD_ST_R(x)),
-                0x1E => Some(ADD_I_R(x)),
-                0x29 => Some(LD_F_R(x)),
-                0x33 => Some(LD_B_R(x)),
-                0x55 => Some(LD_II_R(x)),
-                0x65 => Some(LD_R_II(x)),
-                _ => None,
-            },
-            _ => unreachable!(a)
+        match self {
+            CLS => "CLS",
+            RET => "RET",
+            SYS(..) => "SYS",
+            JP(..) => "JP",
+            CALL(..) => "CALL",
+            JP_V0(..) => "JP_V0",
+            SE(..) => "SE",
+            SE_R(..) => "SE_R",
+            SNE(..) => "SNE",
+            SNE_R(..) => "SNE_R",
+            OR(..) => "OR",
+            AND(..) => "AND",
+            XOR(..) => "XOR",
+            ADD(..) => "ADD",
+            SUB(..) => "SUB",
+            SUBN(..) => "SUBN",
+            SHR(..) => "SHR",
+            SHL(..) => "SHL",
+            LD_R_B(..) => "LD_R_B",
+            LD_R_R(..) => "LD_R_R",
+            LD_I_A(..) => "LD_I_A",
+            LD_R_DT(..) => "LD_R_DT",
+            LD_R_K(..) => "LD_R_K",
+            LD_DT_R(..) => "LD_DT_R",
+            LD_ST_R(..) => "LD_ST_R",
+            LD_F_R(..) => "LD_F_R",
+            LD_B_R(..) => "LD_B_R",
+            LD_II_R(..) => "LD_II_R",
+            LD_R_II(..) => "LD_R_II",
+            ADD_R_B(..) => "ADD_R_B",
+            ADD_I_R(..) => "ADD_I_R",
+            RND(..) => "RND",
+            DRW(..) => "DRW",
+            SKP(..) => "SKP",
+            SKNP(..) => "SKNP",
+            SCD(..) => "SCD",
+            SCR => "SCR",
+            SCL => "SCL",
+            EXIT => "EXIT",
+            LOW => "LOW",
+            HIGH => "HIGH",
+            LD_HF_R(..) => "LD_HF_R",
+            LD_RPL_R(..) => "LD_RPL_R",
+            LD_R_RPL(..) => "LD_R_RPL",
+            SCU(..) => "SCU",
+            LD_II_RR(..) => "LD_II_RR",
+            LD_RR_II(..) => "LD_RR_II",
+            LD_I_LONG => "LD_I_LONG",
+            PLANE(..) => "PLANE",
+            AUDIO => "AUDIO",
+            PITCH(..) => "PITCH",
+            BG => "BG",
+            ADDN(..) => "ADDN",
+            COL(..) => "COL",
+            SKP2(..) => "SKP2",
+            SKNP2(..) => "SKNP2",
+            OUT(..) => "OUT",
+            IN(..) => "IN",
+            SGT_R(..) => "SGT_R",
+            LD_IIP_RR(..) => "LD_IIP_RR",
+            LD_RR_IIP(..) => "LD_RR_IIP",
+            SKIP(..) => "SKIP",
+            LD_DT_R_WAIT(..) => "LD_DT_R_WAIT",
+            JP_BACK(..) => "JP_BACK",
+            JP_FWD(..) => "JP_FWD",
+            OUT3(..) => "OUT3",
+            IN3(..) => "IN3",
+            IN3_WAIT(..) => "IN3_WAIT",
+            CLS_HIRES => "CLS_HIRES",
+            MEGA_OFF => "MEGA_OFF",
+            MEGA_ON => "MEGA_ON",
+            LDHI(..) => "LDHI",
+            LDPAL(..) => "LDPAL",
+            SPRW(..) => "SPRW",
+            SPRH(..) => "SPRH",
+            ALPHA(..) => "ALPHA",
+            DIGISND(..) => "DIGISND",
+            STOPSND => "STOPSND",
+            BMODE(..) => "BMODE",
+            CCOL(..) => "CCOL",
         }
     }
 
@@ -127,7 +388,7 @@ impl Instr {
         match self {
             CLS => 0x00E0,
             RET => 0x00EE,
-            SYS(xyz) => 0x0000 | xyz,
+            SYS(xyz) => xyz,
             JP(xyz) => 0x1000 | xyz,
             CALL(xyz) => 0x2000 | xyz,
             JP_V0(xyz) => 0xB000 | xyz,
@@ -165,17 +426,164 @@ impl Instr {
             DRW(x, y, z) => 0xD000 | _xyz(x, y, z),
             SKP(x) => 0xE09E | _x(x),
             SKNP(x) => 0xE0A1 | _x(x),
+
+            SCD(z) => 0x00C0 | z as u16,
+            SCR => 0x00FB,
+            SCL => 0x00FC,
+            EXIT => 0x00FD,
+            LOW => 0x00FE,
+            HIGH => 0x00FF,
+            LD_HF_R(x) => 0xF030 | _x(x),
+            LD_RPL_R(x) => 0xF075 | _x(x),
+            LD_R_RPL(x) => 0xF085 | _x(x),
+
+            SCU(z) => 0x00D0 | z as u16,
+            LD_II_RR(x, y) => 0x5002 | _xy(x, y),
+            LD_RR_II(x, y) => 0x5003 | _xy(x, y),
+            LD_I_LONG => 0xF000,
+            PLANE(x) => 0xF001 | _x(x),
+            AUDIO => 0xF002,
+            PITCH(x) => 0xF03A | _x(x),
+
+            BG => 0x02A0,
+            ADDN(x, y) => 0x5001 | _xy(x, y),
+            COL(x, y, z) => 0xB000 | _xyz(x, y, z),
+            SKP2(x) => 0xE0F2 | _x(x),
+            SKNP2(x) => 0xE0F5 | _x(x),
+            OUT(x) => 0xF0F8 | _x(x),
+            IN(x) => 0xF0FB | _x(x),
+
+            SGT_R(x, y) => 0x5001 | _xy(x, y),
+            LD_IIP_RR(x, y) => 0x5002 | _xy(x, y),
+            LD_RR_IIP(x, y) => 0x5003 | _xy(x, y),
+            SKIP(x) => 0xF01B | _x(x),
+            LD_DT_R_WAIT(x) => 0xF04F | _x(x),
+            JP_BACK(yz) => 0xBB00 | _yz(yz),
+            JP_FWD(yz) => 0xBF00 | _yz(yz),
+            OUT3(x) => 0xF003 | _x(x),
+            IN3(x) => 0xF0E7 | _x(x),
+            IN3_WAIT(x) => 0xF0E3 | _x(x),
+            CLS_HIRES => 0x0230,
+            MEGA_OFF => 0x0010,
+            MEGA_ON => 0x0011,
+            LDHI(yz) => 0x0100 | _yz(yz),
+            LDPAL(yz) => 0x0200 | _yz(yz),
+            SPRW(yz) => 0x0300 | _yz(yz),
+            SPRH(yz) => 0x0400 | _yz(yz),
+            ALPHA(yz) => 0x0500 | _yz(yz),
+            DIGISND(z) => 0x0600 | z as u16,
+            STOPSND => 0x0700,
+            BMODE(z) => 0x0800 | z as u16,
+            CCOL(yz) => 0x0900 | _yz(yz),
         }
     }
 }
 
 
+impl fmt::Display for Instr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Instr::*;
+
+        match *self {
+            CLS => write!(f, "CLS"),
+            RET => write!(f, "RET"),
+            SYS(xyz) => write!(f, "SYS #{:03X}", xyz),
+            JP(xyz) => write!(f, "JP #{:03X}", xyz),
+            CALL(xyz) => write!(f, "CALL #{:03X}", xyz),
+            JP_V0(xyz) => write!(f, "JP V0, #{:03X}", xyz),
+            SE(x, yz) => write!(f, "SE V{:X}, #{:02X}", x, yz),
+            SNE(x, yz) => write!(f, "SNE V{:X}, #{:02X}", x, yz),
+            SE_R(x, y) => write!(f, "SE V{:X}, V{:X}", x, y),
+            SNE_R(x, y) => write!(f, "SNE V{:X}, V{:X}", x, y),
+
+            OR(x, y) => write!(f, "OR V{:X}, V{:X}", x, y),
+            AND(x, y) => write!(f, "AND V{:X}, V{:X}", x, y),
+            XOR(x, y) => write!(f, "XOR V{:X}, V{:X}", x, y),
+            ADD(x, y) => write!(f, "ADD V{:X}, V{:X}", x, y),
+            SUB(x, y) => write!(f, "SUB V{:X}, V{:X}", x, y),
+            SUBN(x, y) => write!(f, "SUBN V{:X}, V{:X}", x, y),
+
+            SHR(x, y) => write!(f, "SHR V{:X}, V{:X}", x, y),
+            SHL(x, y) => write!(f, "SHL V{:X}, V{:X}", x, y),
+
+            LD_R_B(x, yz) => write!(f, "LD V{:X}, #{:02X}", x, yz),
+            LD_R_R(x, y) => write!(f, "LD V{:X}, V{:X}", x, y),
+            LD_I_A(xyz) => write!(f, "LD I, #{:03X}", xyz),
+            LD_R_DT(x) => write!(f, "LD V{:X}, DT", x),
+            LD_R_K(x) => write!(f, "LD V{:X}, K", x),
+            LD_DT_R(x) => write!(f, "LD DT, V{:X}", x),
+            LD_ST_R(x) => write!(f, "LD ST, V{:X}", x),
+            LD_F_R(x) => write!(f, "LD F, V{:X}", x),
+            LD_B_R(x) => write!(f, "LD B, V{:X}", x),
+            LD_II_R(x) => write!(f, "LD [I], V{:X}", x),
+            LD_R_II(x) => write!(f, "LD V{:X}, [I]", x),
+
+            ADD_R_B(x, yz) => write!(f, "ADD V{:X}, #{:02X}", x, yz),
+            ADD_I_R(x) => write!(f, "ADD I, V{:X}", x),
+
+            RND(x, yz) => write!(f, "RND V{:X}, #{:02X}", x, yz),
+            DRW(x, y, z) => write!(f, "DRW V{:X}, V{:X}, #{:X}", x, y, z),
+            SKP(x) => write!(f, "SKP V{:X}", x),
+            SKNP(x) => write!(f, "SKNP V{:X}", x),
+
+            SCD(z) => write!(f, "SCD #{:X}", z),
+            SCR => write!(f, "SCR"),
+            SCL => write!(f, "SCL"),
+            EXIT => write!(f, "EXIT"),
+            LOW => write!(f, "LOW"),
+            HIGH => write!(f, "HIGH"),
+            LD_HF_R(x) => write!(f, "LD HF, V{:X}", x),
+            LD_RPL_R(x) => write!(f, "LD R, V{:X}", x),
+            LD_R_RPL(x) => write!(f, "LD V{:X}, R", x),
+
+            SCU(z) => write!(f, "SCU #{:X}", z),
+            LD_II_RR(x, y) => write!(f, "LD [I], V{:X}-V{:X}", x, y),
+            LD_RR_II(x, y) => write!(f, "LD V{:X}-V{:X}, [I]", x, y),
+            LD_I_LONG => write!(f, "LD I, LONG"),
+            PLANE(x) => write!(f, "PLANE #{:X}", x),
+            AUDIO => write!(f, "AUDIO"),
+            PITCH(x) => write!(f, "PITCH V{:X}", x),
+
+            BG => write!(f, "BG"),
+            ADDN(x, y) => write!(f, "ADDN V{:X}, V{:X}", x, y),
+            COL(x, y, z) => write!(f, "COL V{:X}, V{:X}, #{:X}", x, y, z),
+            SKP2(x) => write!(f, "SKP2 V{:X}", x),
+            SKNP2(x) => write!(f, "SKNP2 V{:X}", x),
+            OUT(x) => write!(f, "OUT V{:X}", x),
+            IN(x) => write!(f, "IN V{:X}", x),
+
+            SGT_R(x, y) => write!(f, "SGT V{:X}, V{:X}", x, y),
+            LD_IIP_RR(x, y) => write!(f, "LD [I+], V{:X}-V{:X}", x, y),
+            LD_RR_IIP(x, y) => write!(f, "LD V{:X}-V{:X}, [I+]", x, y),
+            SKIP(x) => write!(f, "SKIP V{:X}", x),
+            LD_DT_R_WAIT(x) => write!(f, "LD DT, V{:X}, WAIT", x),
+            JP_BACK(yz) => write!(f, "JP -#{:02X}", yz),
+            JP_FWD(yz) => write!(f, "JP +#{:02X}", yz),
+            OUT3(x) => write!(f, "OUT3 V{:X}", x),
+            IN3(x) => write!(f, "IN3 V{:X}", x),
+            IN3_WAIT(x) => write!(f, "IN3 V{:X}, WAIT", x),
+            CLS_HIRES => write!(f, "CLS HIRES"),
+            MEGA_OFF => write!(f, "MEGAOFF"),
+            MEGA_ON => write!(f, "MEGAON"),
+            LDHI(yz) => write!(f, "LDHI I, #{:02X}, LONG", yz),
+            LDPAL(yz) => write!(f, "LDPAL #{:02X}", yz),
+            SPRW(yz) => write!(f, "SPRW #{:02X}", yz),
+            SPRH(yz) => write!(f, "SPRH #{:02X}", yz),
+            ALPHA(yz) => write!(f, "ALPHA #{:02X}", yz),
+            DIGISND(z) => write!(f, "DIGISND #{:X}", z),
+            STOPSND => write!(f, "STOPSND"),
+            BMODE(z) => write!(f, "BMODE #{:X}", z),
+            CCOL(yz) => write!(f, "CCOL #{:02X}", yz),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use super::Instr::*;
 
-    static LEGAL: &'static [(u16, Instr)] = &[
+    static LEGAL: &[(u16, Instr)] = &[
         (0x00E0, CLS),
         (0x00EE, RET),
         (0x0123, SYS(0x123)),
@@ -213,7 +621,7 @@ mod tests {
         (0xF165, LD_R_II(0x1)),
     ];
 
-    static ILLEGAL: &'static [u16] = &[
+    static ILLEGAL: &[u16] = &[
         0x5121,
         0x812A,
         0x9121,
@@ -221,6 +629,90 @@ mod tests {
         0xF100,
     ];
 
+    static SCHIP_LEGAL: &[(u16, Instr)] = &[
+        (0x00C3, SCD(0x3)),
+        (0x00FB, SCR),
+        (0x00FC, SCL),
+        (0x00FD, EXIT),
+        (0x00FE, LOW),
+        (0x00FF, HIGH),
+        (0xD120, DRW(0x1, 0x2, 0x0)),
+        (0xF130, LD_HF_R(0x1)),
+        (0xF175, LD_RPL_R(0x1)),
+        (0xF185, LD_R_RPL(0x1)),
+    ];
+
+    static XOCHIP_LEGAL: &[(u16, Instr)] = &[
+        (0x00D3, SCU(0x3)),
+        (0x5122, LD_II_RR(0x1, 0x2)),
+        (0x5123, LD_RR_II(0x1, 0x2)),
+        (0xF000, LD_I_LONG),
+        (0xF201, PLANE(0x2)),
+        (0xF002, AUDIO),
+        (0xF13A, PITCH(0x1)),
+    ];
+
+    static CHIP8X_LEGAL: &[(u16, Instr)] = &[
+        (0x02A0, BG),
+        (0x5121, ADDN(0x1, 0x2)),
+        (0xB123, COL(0x1, 0x2, 0x3)),
+        (0xB120, COL(0x1, 0x2, 0x0)),
+        (0xE1F2, SKP2(0x1)),
+        (0xE1F5, SKNP2(0x1)),
+        (0xF1F8, OUT(0x1)),
+        (0xF1FB, IN(0x1)),
+    ];
+
+    static CHIP8E_LEGAL: &[(u16, Instr)] = &[
+        (0x5121, SGT_R(0x1, 0x2)),
+        (0x5122, LD_IIP_RR(0x1, 0x2)),
+        (0x5123, LD_RR_IIP(0x1, 0x2)),
+        (0xBB12, JP_BACK(0x12)),
+        (0xBF12, JP_FWD(0x12)),
+        (0xB123, JP_V0(0x123)),
+        (0xF103, OUT3(0x1)),
+        (0xF11B, SKIP(0x1)),
+        (0xF14F, LD_DT_R_WAIT(0x1)),
+    ];
+
+    static CHIP8E_ILLEGAL: &[u16] = &[
+        0x5124,
+        0xF1E7,
+    ];
+
+    static CHIP8I_LEGAL: &[(u16, Instr)] = &[
+        (0xF103, OUT3(0x1)),
+        (0xF1E3, IN3_WAIT(0x1)),
+        (0xF1E7, IN3(0x1)),
+    ];
+
+    static CHIP8I_ILLEGAL: &[u16] = &[
+        0x5121,
+        0xF11B,
+    ];
+
+    static MEGACHIP_LEGAL: &[(u16, Instr)] = &[
+        (0x0010, MEGA_OFF),
+        (0x0011, MEGA_ON),
+        (0x0112, LDHI(0x12)),
+        (0x0212, LDPAL(0x12)),
+        (0x0312, SPRW(0x12)),
+        (0x0412, SPRH(0x12)),
+        (0x0512, ALPHA(0x12)),
+        (0x0601, DIGISND(0x1)),
+        (0x0700, STOPSND),
+        (0x0803, BMODE(0x3)),
+        (0x0912, CCOL(0x12)),
+        (0x00FF, HIGH),
+        (0x0612, SYS(0x612)),
+        (0x0701, SYS(0x701)),
+    ];
+
+    static MEGACHIP_ILLEGAL: &[u16] = &[
+        0x5121,
+        0xF000,
+    ];
+
     #[test]
     fn test_from() {
         for (b, i) in LEGAL.iter() {
@@ -247,4 +739,68 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_dialects() {
+        for (b, i) in SCHIP_LEGAL.iter() {
+            assert_eq!(decode(&[SCHIP, CHIP8], *b), Some(*i), "{:04X} should parse to {:?}", *b, *i);
+            assert_eq!(i.to(), *b, "{:?} should generate {:04X}", *i, *b);
+        }
+        for (b, i) in XOCHIP_LEGAL.iter() {
+            assert_eq!(decode(&[XOCHIP, SCHIP, CHIP8], *b), Some(*i),
+                       "{:04X} should parse to {:?}", *b, *i);
+            assert_eq!(i.to(), *b, "{:?} should generate {:04X}", *i, *b);
+        }
+
+        assert_eq!(decode(&[HIRES, CHIP8], 0x0230), Some(CLS_HIRES));
+        assert_eq!(CLS_HIRES.to(), 0x0230);
+        assert_eq!(decode(&[HIRES, CHIP8], 0x0231), Some(SYS(0x231)));
+
+        for (b, i) in CHIP8X_LEGAL.iter() {
+            assert_eq!(decode(&[CHIP8X, CHIP8], *b), Some(*i), "{:04X} should parse to {:?}", *b, *i);
+            assert_eq!(i.to(), *b, "{:?} should generate {:04X}", *i, *b);
+        }
+
+        for &(tables, legal, illegal) in
+            &[(&[CHIP8E, CHIP8][..], CHIP8E_LEGAL, CHIP8E_ILLEGAL),
+              (&[CHIP8I, CHIP8][..], CHIP8I_LEGAL, CHIP8I_ILLEGAL),
+              (&[MEGACHIP, SCHIP, CHIP8][..], MEGACHIP_LEGAL, MEGACHIP_ILLEGAL)] {
+            for (b, i) in legal.iter() {
+                assert_eq!(decode(tables, *b), Some(*i),
+                           "{:04X} should parse to {:?}", *b, *i);
+                assert_eq!(i.to(), *b, "{:?} should generate {:04X}", *i, *b);
+            }
+            for b in illegal.iter() {
+                assert_eq!(decode(tables, *b), None, "{:04X} should not parse", *b);
+            }
+            for b in 0x0000..=0xFFFF {
+                if let Some(i) = decode(tables, b) {
+                    assert_eq!(i.to(), b, "{:?} should generate back {:04X}", i, b)
+                }
+            }
+        }
+
+        // Not part of plain CHIP-8.
+        assert_eq!(Instr::from(0x00FF), Some(SYS(0x0FF)));
+        assert_eq!(Instr::from(0xF000), None);
+
+        for b in 0x0000..=0xFFFF {
+            if let Some(i) = decode(&[XOCHIP, SCHIP, CHIP8], b) {
+                assert_eq!(i.to(), b, "{:?} should generate back {:04X}", i, b)
+            }
+            if let Some(i) = decode(&[CHIP8X, CHIP8], b) {
+                assert_eq!(i.to(), b, "{:?} should generate back {:04X}", i, b)
+            }
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(CLS.to_string(), "CLS");
+        assert_eq!(JP_V0(0x123).to_string(), "JP V0, #123");
+        assert_eq!(LD_R_B(0xA, 0x5).to_string(), "LD VA, #05");
+        assert_eq!(LD_II_R(0xF).to_string(), "LD [I], VF");
+        assert_eq!(DRW(0x1, 0x2, 0xF).to_string(), "DRW V1, V2, #F");
+        assert_eq!(LD_II_RR(0x1, 0x2).to_string(), "LD [I], V1-V2");
+    }
 }
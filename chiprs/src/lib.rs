@@ -2,86 +2,239 @@ extern crate rand;
 
 pub mod instr;
 pub mod memory;
+pub mod hp48;
+pub mod extract;
+pub mod gif;
+pub mod octo;
+pub mod compiler;
 pub mod display;
+pub mod quirks;
+pub mod platform;
+pub mod hook;
+pub mod host;
+pub mod machine;
+pub mod cdp1802;
+pub mod vip;
+pub mod trace;
+pub mod profile;
+pub mod coverage;
+pub mod analysis;
+pub mod sha1;
+pub mod romdb;
+#[cfg(test)]
+mod reference;
 
 use crate::instr::Instr;
-use crate::memory::{Memory, MEMORY_SIZE};
-use crate::display::Display;
+use crate::memory::{Memory, MemoryPolicy, BIG_FONT_ADDR};
+use crate::display::{Display, ColorLayer, Framebuffer, Blend, ZONE_W, ZONE_H, MEGA_W, MEGA_H};
+use crate::quirks::Quirks;
+use crate::platform::Platform;
+use crate::hook::Hook;
+use crate::host::{FrameSink, Keypad, Beeper, RandomSource, Sample};
+
+use rand::{FromEntropy, SeedableRng};
+use rand::rngs::SmallRng;
 
 pub const V_SIZE: usize = 0x10;
+/// Stack depth of plain CHIP-8, see `Platform::stack_size`.
 pub const STACK_SIZE: usize = 0x10;
 pub const KEYS_SIZE: usize = 0x10;
+/// SUPER-CHIP has 8 of these, XO-CHIP 16.
+pub const RPL_SIZE: usize = 0x10;
+pub const AUDIO_SIZE: usize = 0x10;
 
 pub struct Chip {
     v: [u8; V_SIZE],
-    i: u16,
+    // 16 bits, or 24 on MegaChip.
+    i: u32,
     pc: u16,
     sp: u8,
-    stack: [u16; STACK_SIZE],
+    stack: Vec<u16>,
     dt: u8,
     st: u8,
     keys: [bool; KEYS_SIZE],
     key_wait_reg: Option<u8>,
+    // CHIP-8X second keypad, and I/O port.
+    keys2: [bool; KEYS_SIZE],
+    output: u8,
+    input: Option<u8>,
+    // Waiting for the delay timer to run out (CHIP-8E).
+    timer_wait: bool,
+    rpl: [u8; RPL_SIZE],
+    // Bit planes selected for drawing.
+    planes: u8,
+    audio: [u8; AUDIO_SIZE],
+    pitch: u8,
+    exited: bool,
+    // Digitized sound being played (MegaChip).
+    sample: Option<Sample>,
+
+    platform: &'static Platform,
 
     pub memory: Memory,
     pub display: Display,
+    pub quirks: Quirks,
+    pub memory_policy: MemoryPolicy,
+
+    pub frame_sink: Option<Box<dyn FrameSink>>,
+    pub keypad: Option<Box<dyn Keypad>>,
+    pub beeper: Option<Box<dyn Beeper>>,
+    // Last state reported to the beeper.
+    beeping: bool,
+    rng: Box<dyn RandomSource>,
+}
 
-    rng: rand::rngs::ThreadRng,
+impl Default for Chip {
+    fn default() -> Chip {
+        Chip::new()
+    }
 }
 
 impl Chip {
     pub fn new() -> Chip {
+        Chip::with_platform(&platform::CHIP8)
+    }
+
+    pub fn with_platform(platform: &'static Platform) -> Chip {
+        let (width, height) = platform.display_modes[0];
+        let mut display = Display::with_size(width, height);
+        if platform.colors {
+            display.colors = Some(ColorLayer::new(width, height));
+        }
         Chip {
             v: [0; V_SIZE],
             i: 0,
             pc: 0,
             sp: 0,
-            stack: [0; STACK_SIZE],
+            stack: vec![0; platform.stack_size],
             dt: 0,
             st: 0,
             keys: [false; KEYS_SIZE],
             key_wait_reg: None,
+            keys2: [false; KEYS_SIZE],
+            output: 0,
+            input: None,
+            timer_wait: false,
+            rpl: [0; RPL_SIZE],
+            planes: 1,
+            audio: [0; AUDIO_SIZE],
+            pitch: 64,
+            exited: false,
+            sample: None,
 
-            memory: Memory::new(),
-            display: Display::new(),
+            platform,
+            memory: Memory::with_size(platform.memory_size),
+            display,
+            quirks: platform.quirks,
+            memory_policy: MemoryPolicy::default(),
 
-            rng: rand::thread_rng(),
+            frame_sink: None,
+            keypad: None,
+            beeper: None,
+            beeping: false,
+            rng: Box::new(SmallRng::from_entropy()),
         }
     }
 
-    pub fn dump(&self) {
-        for i in 0..V_SIZE {
-            print!("V{:X} ", i);
-        }
-        println!("   I    PC  SP  instr");
-        for i in 0..V_SIZE {
-            print!("{:02X} ", self.v[i]);
-        }
-        print!("  {:02x}  {:04x}  {:02x}", self.i, self.pc, self.sp);
-        if self.pc as usize <= MEMORY_SIZE - 2 {
-            let b = self.memory.u16_at(self.pc as usize);
-            match Instr::from(b) {
-                Some(instr) => print!("  {:?}", instr),
-                None => print!("  ????"),
-            }
-        }
-        println!();
-        println!();
-        for i in 0..STACK_SIZE {
-            print!("S[{:X}] ", i);
-        }
-        println!();
-        for i in 0..V_SIZE {
-            print!("{:04X} ", self.stack[i]);
-        }
-        println!();
-        println!();
+    pub fn platform(&self) -> &'static Platform {
+        self.platform
+    }
+
+    /// Loads the platform's fonts into memory.
+    pub fn load_font(&mut self) {
+        self.memory.load_fonts(self.platform.font, self.platform.big_font);
     }
 
     pub fn jump(&mut self, addr: u16) {
         self.pc = addr;
     }
 
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn v(&self) -> &[u8; V_SIZE] {
+        &self.v
+    }
+
+    /// Sets Vx from outside, e.g. to follow another machine's random
+    /// numbers.
+    pub fn set_v(&mut self, x: u8, value: u8) {
+        self.v[x as usize] = value;
+    }
+
+    pub fn i(&self) -> u32 {
+        self.i
+    }
+
+    pub fn sp(&self) -> u8 {
+        self.sp
+    }
+
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    pub fn dt(&self) -> u8 {
+        self.dt
+    }
+
+    pub fn st(&self) -> u8 {
+        self.st
+    }
+
+    pub fn keys(&self) -> &[bool; KEYS_SIZE] {
+        &self.keys
+    }
+
+    /// Second keypad (CHIP-8X).
+    pub fn keys2(&self) -> &[bool; KEYS_SIZE] {
+        &self.keys2
+    }
+
+    /// Last value written to the I/O port (CHIP-8X, CHIP-8E, CHIP-8I).
+    pub fn output(&self) -> u8 {
+        self.output
+    }
+
+    /// Bit planes selected for drawing (XO-CHIP).
+    pub fn planes(&self) -> u8 {
+        self.planes
+    }
+
+    /// Sound pattern (XO-CHIP): 128 1-bit samples, played at the rate set
+    /// by `pitch`.
+    pub fn audio(&self) -> &[u8; AUDIO_SIZE] {
+        &self.audio
+    }
+
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    pub fn sample(&self) -> Option<&Sample> {
+        self.sample.as_ref()
+    }
+
+    /// Whether the program stopped itself (SUPER-CHIP EXIT).
+    pub fn exited(&self) -> bool {
+        self.exited
+    }
+
+    /// Make the random number generator (used by RND) deterministic.
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = Box::new(SmallRng::seed_from_u64(seed));
+    }
+
+    pub fn set_random_source(&mut self, rng: Box<dyn RandomSource>) {
+        self.rng = rng;
+    }
+
+    /// Whether the sound timer is active, i.e. the buzzer should sound.
+    pub fn sound_on(&self) -> bool {
+        self.st > 0
+    }
+
     pub fn key_down(&mut self, key: u8) {
         if let Some(x) = self.key_wait_reg {
             if !self.keys[key as usize] {
@@ -96,30 +249,213 @@ impl Chip {
         self.keys[key as usize] = false;
     }
 
+    pub fn key2_down(&mut self, key: u8) {
+        self.keys2[key as usize] = true;
+    }
+
+    pub fn key2_up(&mut self, key: u8) {
+        self.keys2[key as usize] = false;
+    }
+
+    /// Provides a byte on the I/O port. Instructions that wait for input
+    /// consume it, the others just read it.
+    pub fn input(&mut self, value: u8) {
+        self.input = Some(value);
+    }
+
+    /// Address in memory, according to memory policy.
+    fn mem_addr(&self, addr: usize) -> Result<usize, String> {
+        self.memory_policy.apply(addr, self.memory.size())
+            .ok_or_else(|| format!("address out of bounds: {:04X}", addr))
+    }
+
+    /// Addresses of `len` bytes starting at `start`. Checks all of them up
+    /// front, so that an instruction either runs completely or not at all.
+    fn mem_range(&self, start: usize, len: usize) -> Result<Vec<usize>, String> {
+        (start..start + len).map(|addr| self.mem_addr(addr)).collect()
+    }
+
+    /// Contents of `len` bytes starting at `start`, checked the same way as
+    /// `mem_range`.
+    fn read_bytes(&self, start: usize, len: usize) -> Result<Vec<u8>, String> {
+        if start + len <= self.memory.size() {
+            return Ok(self.memory.bytes[start..start + len].to_vec());
+        }
+        Ok(self.mem_range(start, len)?.into_iter().map(|addr| self.memory.bytes[addr]).collect())
+    }
+
+    fn set_i(&mut self, value: usize) {
+        self.i = match self.memory_policy {
+            // I wraps around, accessing memory through it will fail.
+            MemoryPolicy::Fault => (value & self.i_mask()) as u32,
+            policy => policy.apply(value, self.memory.size()).unwrap() as u32,
+        };
+    }
+
+    /// I is a 16-bit register, unless more is needed to address all of
+    /// memory.
+    fn i_mask(&self) -> usize {
+        (self.memory.size() - 1) | 0xFFFF
+    }
+
+    fn key_index(&self, x: u8) -> Result<usize, String> {
+        let key = self.v[x as usize] as usize;
+        self.memory_policy.apply(key, KEYS_SIZE)
+            .ok_or_else(|| format!("wrong key: {:02X}", key))
+    }
+
+    fn poll_keypad(&mut self) {
+        let pressed = match self.keypad.as_mut() {
+            Some(keypad) => keypad.poll(),
+            None => return,
+        };
+        for (key, &down) in pressed.iter().enumerate() {
+            if down && !self.keys[key] {
+                self.key_down(key as u8);
+            } else if !down && self.keys[key] {
+                self.key_up(key as u8);
+            }
+        }
+    }
+
+    fn update_beeper(&mut self) {
+        let on = self.sound_on();
+        if on != self.beeping {
+            self.beeping = on;
+            if let Some(beeper) = self.beeper.as_mut() {
+                beeper.set(on);
+            }
+        }
+    }
+
+    fn reset_vf(&mut self) {
+        if self.quirks.logic_resets_vf {
+            self.v[0xF] = 0;
+        }
+    }
+
     fn skip(&mut self) {
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
+    }
+
+    /// Skips the next instruction, which might be longer than 2 bytes.
+    fn skip_next(&mut self) {
+        let pc = self.pc as usize;
+        let size = if pc + 1 < self.memory.size() {
+            self.platform.decode(self.memory.u16_at(pc)).map_or(2, Instr::size)
+        } else {
+            2
+        };
+        self.pc = self.pc.wrapping_add(size as u16);
+    }
+
+    fn set_display_mode(&mut self, mode: usize) {
+        if let Some(&(width, height)) = self.platform.display_modes.get(mode) {
+            self.display.resize(width, height);
+        }
+    }
+
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        match self.display.framebuffer.as_mut() {
+            Some(framebuffer) => framebuffer.scroll(dx, dy),
+            None => self.display.scroll(dx, dy, self.planes),
+        }
+    }
+
+    fn mega_framebuffer(&mut self) -> Result<&mut Framebuffer, String> {
+        self.display.framebuffer.as_mut().ok_or_else(|| "not in MegaChip mode".to_string())
+    }
+
+    /// DRW in MegaChip mode. Sprites have the size set by SPRW and SPRH,
+    /// except for font characters, which are drawn as usual.
+    fn draw_mega(&mut self, x: u8, y: u8, z: u8) -> Result<bool, String> {
+        let (vx, vy) = (self.v[x as usize] as usize, self.v[y as usize] as usize);
+        let font_end = BIG_FONT_ADDR + self.platform.big_font.len();
+        let framebuffer = self.display.framebuffer.as_ref().unwrap();
+        let len = if (self.i as usize) < font_end {
+            z as usize
+        } else {
+            framebuffer.sprite_width * framebuffer.sprite_height
+        };
+        let sprite = self.read_bytes(self.i as usize, len)?;
+        let framebuffer = self.display.framebuffer.as_mut().unwrap();
+        if (self.i as usize) < font_end {
+            Ok(framebuffer.draw_mono(vx, vy, &sprite))
+        } else {
+            Ok(framebuffer.draw(vx, vy, &sprite))
+        }
+    }
+
+    /// Register indices from `x` to `y`, in either direction.
+    fn reg_range(x: u8, y: u8) -> Vec<usize> {
+        if x <= y {
+            (x as usize..=y as usize).collect()
+        } else {
+            (y as usize..=x as usize).rev().collect()
+        }
     }
 
     pub fn tick(&mut self) {
+        self.tick_with(&mut ())
+    }
+
+    /// Update the timers (60 times per second), reporting it to `hook`.
+    pub fn tick_with(&mut self, hook: &mut dyn Hook) {
+        hook.on_tick(self);
         if self.dt > 0 {
             self.dt -= 1;
         }
+        if self.st > 0 {
+            self.st -= 1;
+        }
+        self.update_beeper();
+        self.send_frame();
+    }
+
+    /// Sends the screen to the frame sink outside of the timer ticks, e.g.
+    /// after single-stepping.
+    pub fn send_frame(&mut self) {
+        if let Some(sink) = self.frame_sink.as_mut() {
+            sink.frame(&self.display);
+        }
     }
 
     pub fn step(&mut self) -> Result<(), String> {
-        if self.pc as usize > MEMORY_SIZE - 2 {
-            return Err("PC out of bounds".to_string());
+        self.step_with(&mut ())
+    }
+
+    /// Execute one instruction, reporting it to `hook`.
+    pub fn step_with(&mut self, hook: &mut dyn Hook) -> Result<(), String> {
+        self.poll_keypad();
+
+        if self.exited {
+            return Ok(());
         }
 
-        if self.key_wait_reg != None {
+        if self.pc as usize > self.memory.size() - 2 {
+            let result = Err("PC out of bounds".to_string());
+            hook.after_step(self, &result);
+            return result;
+        }
+
+        if self.key_wait_reg.is_some() {
             return Ok(());
         }
+        if self.timer_wait {
+            if self.dt > 0 {
+                return Ok(());
+            }
+            self.timer_wait = false;
+        }
 
         let b = self.memory.u16_at(self.pc as usize);
-        match Instr::from(b) {
+        hook.before_step(self, self.pc, b);
+        let result = match self.platform.decode(b) {
             Some(instr) => self.run_instr(instr),
             None => Err(format!("could not parse {:04X} as instruction", b)),
-        }
+        };
+        hook.after_step(self, &result);
+        result
     }
 
     fn run_instr(&mut self, instr: Instr) -> Result<(), String> {
@@ -127,8 +463,11 @@ impl Chip {
 
         use crate::instr::Instr::*;
         match instr {
-            CLS => {
-                self.display.clear();
+            CLS | CLS_HIRES => {
+                match self.display.framebuffer.as_mut() {
+                    Some(framebuffer) => framebuffer.clear(),
+                    None => self.display.clear_planes(self.planes),
+                }
             }
             RET => {
                 if self.sp == 0 {
@@ -142,10 +481,11 @@ impl Chip {
                 self.pc = xyz;
             }
             JP_V0(xyz) => {
-                self.pc = xyz + (self.v[0] as u16);
+                let x = if self.quirks.jump_vx { (xyz >> 8) as usize } else { 0 };
+                self.pc = self.mem_addr(xyz as usize + self.v[x] as usize)? as u16;
             }
             CALL(xyz) => {
-                if self.sp as usize > STACK_SIZE {
+                if self.sp as usize >= self.stack.len() {
                     return Err("stack overflow".to_string());
                 }
 
@@ -155,22 +495,22 @@ impl Chip {
             }
             SE(x, yz) => {
                 if self.v[x as usize] == yz {
-                    self.skip();
+                    self.skip_next();
                 }
             }
             SNE(x, yz) => {
                 if self.v[x as usize] != yz {
-                    self.skip();
+                    self.skip_next();
                 }
             }
             SE_R(x, y) => {
                 if self.v[x as usize] == self.v[y as usize] {
-                    self.skip();
+                    self.skip_next();
                 }
             }
             SNE_R(x, y) => {
                 if self.v[x as usize] != self.v[y as usize] {
-                    self.skip();
+                    self.skip_next();
                 }
             }
             LD_R_R(x, y) => {
@@ -180,7 +520,7 @@ impl Chip {
                 self.v[x as usize] = yz;
             }
             LD_I_A(xyz) => {
-                self.i = xyz;
+                self.i = xyz as u32;
             }
             LD_R_DT(x) => {
                 self.v[x as usize] = self.dt;
@@ -190,46 +530,50 @@ impl Chip {
             }
             LD_ST_R(x) => {
                 self.st = self.v[x as usize];
+                self.update_beeper();
             }
             LD_F_R(x) => {
-                // check if vx <= 0xF?
-                let vx = self.v[x as usize];
-                self.i = (vx as u16) * 5;
+                let vx = self.v[x as usize] as usize;
+                let digit = self.memory_policy.apply(vx, 0x10)
+                    .ok_or_else(|| format!("no font character for {:02X}", vx))?;
+                self.i = (digit * 5) as u32;
             }
             LD_B_R(x) => {
-                if self.i as usize > MEMORY_SIZE - 3 {
-                    return Err("I out of bounds".to_string());
-                }
+                let addrs = self.mem_range(self.i as usize, 3)?;
                 let vx = self.v[x as usize];
-                self.memory.bytes[self.i as usize] = vx / 100;
-                self.memory.bytes[(self.i + 1)as usize] = (vx / 10) % 10;
-                self.memory.bytes[(self.i + 2) as usize] = vx % 10;
+                self.memory.bytes[addrs[0]] = vx / 100;
+                self.memory.bytes[addrs[1]] = (vx / 10) % 10;
+                self.memory.bytes[addrs[2]] = vx % 10;
             }
             LD_II_R(x) => {
-                if self.i as usize + x as usize > MEMORY_SIZE - 1 {
-                    return Err("I out of bounds".to_string());
+                let addrs = self.mem_range(self.i as usize, x as usize + 1)?;
+                for (i, &addr) in addrs.iter().enumerate() {
+                    self.memory.bytes[addr] = self.v[i];
                 }
-                for i in 0..(x as usize + 1) {
-                    self.memory.bytes[self.i as usize + i] = self.v[i];
+                if self.quirks.load_store_increments_i {
+                    self.set_i(self.i as usize + x as usize + 1);
                 }
             }
             LD_R_II(x) => {
-                if self.i as usize + x as usize > MEMORY_SIZE - 1 {
-                    return Err("I out of bounds".to_string());
+                let addrs = self.mem_range(self.i as usize, x as usize + 1)?;
+                for (i, &addr) in addrs.iter().enumerate() {
+                    self.v[i] = self.memory.bytes[addr];
                 }
-                for i in 0..(x as usize + 1) {
-                    self.v[i] = self.memory.bytes[self.i as usize + i];
+                if self.quirks.load_store_increments_i {
+                    self.set_i(self.i as usize + x as usize + 1);
                 }
-
             }
             OR(x, y) => {
                 self.v[x as usize] |= self.v[y as usize];
+                self.reset_vf();
             }
             AND(x, y) => {
                 self.v[x as usize] &= self.v[y as usize];
+                self.reset_vf();
             }
             XOR(x, y) => {
                 self.v[x as usize] ^= self.v[y as usize];
+                self.reset_vf();
             }
             ADD(x, y) => {
                 let vx = self.v[x as usize];
@@ -239,10 +583,8 @@ impl Chip {
                 self.v[0xF] = bit as u8;
             }
             ADD_R_B(x, yz) => {
-                let vx = self.v[x as usize];
-                let (result, bit) = vx.overflowing_add(yz);
-                self.v[x as usize] = result;
-                self.v[0xF] = bit as u8;
+                // No carry flag for this one.
+                self.v[x as usize] = self.v[x as usize].wrapping_add(yz);
             }
             SUB(x, y) => {
                 let vx = self.v[x as usize];
@@ -258,54 +600,279 @@ impl Chip {
                 self.v[x as usize] = result;
                 self.v[0xF] = (!bit) as u8;
             }
-            SHL(x, _y) => {
-                let vx = self.v[x as usize];
-                let (result, bit) = vx.overflowing_shl(1);
-                self.v[x as usize] = result;
-                self.v[0xF] = bit as u8;
+            SHL(x, y) => {
+                let src = if self.quirks.shift_vy { y } else { x };
+                let value = self.v[src as usize];
+                self.v[x as usize] = value << 1;
+                self.v[0xF] = value >> 7;
             }
-            SHR(x, _y) => {
-                let vx = self.v[x as usize];
-                let (result, bit) = vx.overflowing_shr(1);
-                self.v[x as usize] = result;
-                self.v[0xF] = bit as u8;
+            SHR(x, y) => {
+                let src = if self.quirks.shift_vy { y } else { x };
+                let value = self.v[src as usize];
+                self.v[x as usize] = value >> 1;
+                self.v[0xF] = value & 1;
             }
             RND(x, yz) => {
-                use rand::Rng;
-                let r: u8 = self.rng.gen();
-                self.v[x as usize] = r & yz;
+                self.v[x as usize] = self.rng.next_u8() & yz;
+            }
+            DRW(x, y, z) if self.display.framebuffer.is_some() => {
+                self.v[0xF] = self.draw_mega(x, y, z)? as u8;
             }
             DRW(x, y, z) => {
-                let sprite = &self.memory.bytes[(self.i as usize)..((self.i + z as u16) as usize)];
-                let collision = self.display.draw(self.v[x as usize] as usize,
-                                             self.v[y as usize] as usize,
-                                             sprite);
+                let (width, height) = if z == 0 && self.platform.wide_sprites {
+                    (16, 16)
+                } else {
+                    (8, z as usize)
+                };
+                let len = width / 8 * height * self.planes.count_ones() as usize;
+                let sprite: Vec<u8> = self.mem_range(self.i as usize, len)?
+                    .into_iter().map(|addr| self.memory.bytes[addr]).collect();
+                let collision = self.display.draw_sprite(self.v[x as usize] as usize,
+                                                         self.v[y as usize] as usize,
+                                                         &sprite, width, self.planes);
                 self.v[0xF] = collision as u8;
             }
             ADD_I_R(x) => {
-                self.i =self.i.wrapping_add(self.v[x as usize] as u16);
+                self.set_i(self.i as usize + self.v[x as usize] as usize);
             }
             SKP(x) => {
-                let key = self.v[x as usize] as usize;
-                if key >= KEYS_SIZE {
-                    return Err(format!("SKP: wrong key: {:02X}", key));
-                }
+                let key = self.key_index(x)?;
                 if self.keys[key] {
-                    self.skip();
+                    self.skip_next();
                 }
             }
             SKNP(x) => {
-                let key = self.v[x as usize] as usize;
-                if key >= KEYS_SIZE {
-                    return Err(format!("SKP: wrong key: {:02X}", key));
-                }
+                let key = self.key_index(x)?;
                 if !self.keys[key] {
-                    self.skip();
+                    self.skip_next();
                 }
             }
             LD_R_K(x) => {
                 self.key_wait_reg = Some(x);
             }
+            SCD(z) => {
+                self.scroll(0, z as isize);
+            }
+            SCU(z) => {
+                self.scroll(0, -(z as isize));
+            }
+            SCR => {
+                self.scroll(4, 0);
+            }
+            SCL => {
+                self.scroll(-4, 0);
+            }
+            EXIT => {
+                self.exited = true;
+            }
+            LOW => {
+                self.set_display_mode(0);
+            }
+            HIGH => {
+                self.set_display_mode(1);
+            }
+            LD_HF_R(x) => {
+                let vx = self.v[x as usize] as usize;
+                let digit = self.memory_policy.apply(vx, self.platform.big_font.len() / 10)
+                    .ok_or_else(|| format!("no big font character for {:02X}", vx))?;
+                self.i = (BIG_FONT_ADDR + digit * 10) as u32;
+            }
+            LD_RPL_R(x) => {
+                self.rpl[..=x as usize].copy_from_slice(&self.v[..=x as usize]);
+            }
+            LD_R_RPL(x) => {
+                self.v[..=x as usize].copy_from_slice(&self.rpl[..=x as usize]);
+            }
+            LD_II_RR(x, y) => {
+                let regs = Chip::reg_range(x, y);
+                let addrs = self.mem_range(self.i as usize, regs.len())?;
+                for (&reg, &addr) in regs.iter().zip(addrs.iter()) {
+                    self.memory.bytes[addr] = self.v[reg];
+                }
+            }
+            LD_RR_II(x, y) => {
+                let regs = Chip::reg_range(x, y);
+                let addrs = self.mem_range(self.i as usize, regs.len())?;
+                for (&reg, &addr) in regs.iter().zip(addrs.iter()) {
+                    self.v[reg] = self.memory.bytes[addr];
+                }
+            }
+            LD_I_LONG => {
+                let addrs = self.mem_range(self.pc as usize, 2)?;
+                self.set_i(((self.memory.bytes[addrs[0]] as usize) << 8) |
+                           self.memory.bytes[addrs[1]] as usize);
+                self.skip();
+            }
+            PLANE(x) => {
+                self.planes = x & ((1 << self.platform.planes) - 1);
+            }
+            AUDIO => {
+                let addrs = self.mem_range(self.i as usize, AUDIO_SIZE)?;
+                for (i, &addr) in addrs.iter().enumerate() {
+                    self.audio[i] = self.memory.bytes[addr];
+                }
+            }
+            PITCH(x) => {
+                self.pitch = self.v[x as usize];
+            }
+            BG => {
+                if let Some(colors) = self.display.colors.as_mut() {
+                    colors.next_background();
+                }
+            }
+            ADDN(x, y) => {
+                // Each nibble separately, modulo 8 (colors are 3 bits).
+                let vx = self.v[x as usize];
+                let vy = self.v[y as usize];
+                let high = ((vx >> 4) + (vy >> 4)) & 7;
+                let low = ((vx & 0xF) + (vy & 0xF)) & 7;
+                self.v[x as usize] = (high << 4) | low;
+            }
+            COL(x, y, z) => {
+                let vx = self.v[x as usize] as usize;
+                let vx1 = self.v[(x as usize + 1) % V_SIZE] as usize;
+                let color = self.v[y as usize];
+                // With height 0, VX and VX+1 are zone coordinates (low
+                // nibble) and number of extra zones (high nibble).
+                // Otherwise, they are pixel coordinates of an area 8 pixels
+                // wide and `z` pixels tall.
+                let (columns, rows) = if z == 0 {
+                    ((vx & 0xF)..=(vx & 0xF) + (vx >> 4),
+                     (vx1 & 0xF)..=(vx1 & 0xF) + (vx1 >> 4))
+                } else {
+                    (vx / ZONE_W..=vx / ZONE_W,
+                     vx1 / ZONE_H..=(vx1 + z as usize - 1) / ZONE_H)
+                };
+                if let Some(colors) = self.display.colors.as_mut() {
+                    for row in rows {
+                        for column in columns.clone() {
+                            colors.set_zone(column, row, color);
+                        }
+                    }
+                }
+            }
+            SKP2(x) => {
+                let key = self.key_index(x)?;
+                if self.keys2[key] {
+                    self.skip_next();
+                }
+            }
+            SKNP2(x) => {
+                let key = self.key_index(x)?;
+                if !self.keys2[key] {
+                    self.skip_next();
+                }
+            }
+            OUT(x) => {
+                self.output = self.v[x as usize];
+            }
+            IN(x) | IN3_WAIT(x) => {
+                match self.input.take() {
+                    Some(value) => self.v[x as usize] = value,
+                    // Wait, by running this instruction again.
+                    None => self.pc = self.pc.wrapping_sub(2),
+                }
+            }
+            IN3(x) => {
+                self.v[x as usize] = self.input.unwrap_or(0);
+            }
+            OUT3(x) => {
+                self.output = self.v[x as usize];
+            }
+            SGT_R(x, y) => {
+                if self.v[x as usize] > self.v[y as usize] {
+                    self.skip_next();
+                }
+            }
+            LD_IIP_RR(x, y) => {
+                let regs = Chip::reg_range(x, y);
+                let addrs = self.mem_range(self.i as usize, regs.len())?;
+                for (&reg, &addr) in regs.iter().zip(addrs.iter()) {
+                    self.memory.bytes[addr] = self.v[reg];
+                }
+                self.set_i(self.i as usize + regs.len());
+            }
+            LD_RR_IIP(x, y) => {
+                let regs = Chip::reg_range(x, y);
+                let addrs = self.mem_range(self.i as usize, regs.len())?;
+                for (&reg, &addr) in regs.iter().zip(addrs.iter()) {
+                    self.v[reg] = self.memory.bytes[addr];
+                }
+                self.set_i(self.i as usize + regs.len());
+            }
+            SKIP(x) => {
+                self.pc = self.pc.wrapping_add(self.v[x as usize] as u16);
+            }
+            LD_DT_R_WAIT(x) => {
+                self.dt = self.v[x as usize];
+                self.timer_wait = true;
+            }
+            MEGA_OFF => {
+                self.set_display_mode(0);
+            }
+            MEGA_ON => {
+                self.display.resize(MEGA_W, MEGA_H);
+                self.display.framebuffer = Some(Framebuffer::new());
+            }
+            LDHI(yz) => {
+                let addrs = self.mem_range(self.pc as usize, 2)?;
+                self.i = ((yz as u32) << 16) |
+                    ((self.memory.bytes[addrs[0]] as u32) << 8) |
+                    self.memory.bytes[addrs[1]] as u32;
+                self.skip();
+            }
+            LDPAL(yz) => {
+                // Colors are ARGB, and go to entries starting from 1.
+                let colors: Vec<u32> = self.mem_range(self.i as usize, yz as usize * 4)?
+                    .chunks(4)
+                    .map(|color| color.iter()
+                         .fold(0, |acc, &addr| (acc << 8) | self.memory.bytes[addr] as u32))
+                    .collect();
+                let framebuffer = self.mega_framebuffer()?;
+                framebuffer.palette[1..=colors.len()].copy_from_slice(&colors);
+            }
+            SPRW(yz) => {
+                self.mega_framebuffer()?.sprite_width = if yz == 0 { 256 } else { yz as usize };
+            }
+            SPRH(yz) => {
+                self.mega_framebuffer()?.sprite_height = if yz == 0 { 256 } else { yz as usize };
+            }
+            ALPHA(yz) => {
+                self.mega_framebuffer()?.alpha = yz;
+            }
+            BMODE(z) => {
+                let blend = Blend::from_u8(z).ok_or_else(|| format!("wrong blend mode: {:X}", z))?;
+                self.mega_framebuffer()?.blend = blend;
+            }
+            CCOL(yz) => {
+                self.mega_framebuffer()?.collision_color = yz;
+            }
+            DIGISND(z) => {
+                // Header: sample rate (16 bits), length (24 bits), and a
+                // reserved byte.
+                let header = self.read_bytes(self.i as usize, 6)?;
+                let rate = ((header[0] as u32) << 8) | header[1] as u32;
+                let len = ((header[2] as usize) << 16) | ((header[3] as usize) << 8) |
+                    header[4] as usize;
+                let data = self.read_bytes(self.i as usize + 6, len)?;
+                self.sample = Some(Sample { rate, data, looping: z != 1 });
+                if let Some(beeper) = self.beeper.as_mut() {
+                    beeper.play(self.sample.as_ref());
+                }
+            }
+            STOPSND => {
+                self.sample = None;
+                if let Some(beeper) = self.beeper.as_mut() {
+                    beeper.play(None);
+                }
+            }
+            // Relative to the next instruction.
+            JP_BACK(yz) => {
+                self.pc = self.pc.wrapping_sub(yz as u16);
+            }
+            JP_FWD(yz) => {
+                self.pc = self.pc.wrapping_add(yz as u16);
+            }
         }
         Ok(())
     }
@@ -324,6 +891,7 @@ impl Chip {
 mod tests {
     use super::*;
     use super::Instr::*;
+    use crate::quirks::PRESETS;
 
     #[test]
     fn test_fib() {
@@ -360,6 +928,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_sprites() {
         let mut chip = Chip::new();
         chip.memory.load_font();
@@ -384,4 +953,493 @@ mod tests {
         assert_eq!(chip.display.at(3, 10), true);
         assert_eq!(chip.display.at(4, 11), false);
     }
+
+    #[test]
+    fn test_schip() {
+        let mut chip = Chip::with_platform(&platform::SCHIP);
+        chip.load_font();
+
+        chip.memory.load_program(0x200, &[
+            HIGH,
+            LD_R_B(0, 9),
+            LD_HF_R(0),
+            LD_R_B(1, 0),
+            DRW(1, 1, 10),
+            SCR,
+            SCD(2),
+
+            LD_I_A(0x300),
+            LD_R_B(3, 100),
+            LD_R_B(4, 40),
+            DRW(3, 4, 0),
+
+            LD_RPL_R(0),
+            LD_R_B(0, 0),
+            LD_R_RPL(0),
+            EXIT,
+        ]);
+        for addr in 0x300..0x320 {
+            chip.memory.bytes[addr] = 0xFF;
+        }
+
+        chip.jump(0x200);
+        while !chip.exited() {
+            chip.step().unwrap();
+        }
+        assert_eq!((chip.display.width(), chip.display.height()), (128, 64));
+        // Top row of the big 9 (0x3C), moved right and down.
+        assert!(!chip.display.at(5, 2));
+        assert!(chip.display.at(6, 2));
+        assert!(chip.display.at(9, 2));
+        assert!(!chip.display.at(6, 0));
+        // 16x16 sprite.
+        assert!(chip.display.at(115, 55));
+        assert!(!chip.display.at(116, 55));
+        assert!(!chip.display.at(115, 56));
+        assert_eq!(chip.v[0], 9);
+    }
+
+    #[test]
+    fn test_xochip() {
+        let mut chip = Chip::with_platform(&platform::XOCHIP);
+        chip.memory.load_rom(&[
+            0xF0, 0x00, 0x12, 0x34, // LD I, LONG #1234
+            0xF3, 0x01,             // PLANE 3
+            0x60, 0x05,             // LD V0, #05
+            0xD0, 0x01,             // DRW V0, V0, 1
+            0x30, 0x05,             // SE V0, #05
+            0xF0, 0x00, 0x00, 0x00, // LD I, LONG #0000 (skipped)
+            0x51, 0x33,             // LD V1-V3, [I]
+            0xF2, 0x01,             // PLANE 2
+            0x00, 0xE0,             // CLS
+            0x1F, 0xFF,             // JP #FFF
+        ], 0x200).unwrap();
+        chip.memory.bytes[0x1234..0x1237].copy_from_slice(&[0xFF, 0x81, 0x42]);
+
+        chip.run_program(0x200).unwrap();
+        assert_eq!(chip.i, 0x1234);
+        assert_eq!(&chip.v[1..4], &[0xFF, 0x81, 0x42]);
+        assert_eq!(chip.planes(), 2);
+        // Both planes were drawn, then the second one was cleared.
+        assert_eq!(chip.display.color(5, 5), 1);
+        assert_eq!(chip.display.color(12, 5), 1);
+        assert_eq!(chip.display.color(5, 6), 0);
+    }
+
+    #[test]
+    fn test_chip8x() {
+        use crate::display::{COLOR_BLACK, COLOR_GREEN, COLOR_RED};
+
+        let mut chip = Chip::with_platform(&platform::CHIP8X);
+        chip.memory.load_program(0x300, &[
+            // Zones 1-2 of the first row.
+            LD_R_B(0, 0x11),
+            LD_R_B(1, 0x00),
+            LD_R_B(2, COLOR_GREEN),
+            COL(0, 2, 0),
+            LD_R_B(3, 0x12),
+            LD_R_B(4, 0x37),
+            ADDN(3, 4),
+            BG,
+            LD_R_B(5, 0xA),
+            OUT(5),
+            IN(6),
+            SKP2(5),
+            LD_R_B(7, 1),
+            JP(0xFFF),
+        ]);
+        chip.key2_down(0xA);
+
+        chip.jump(0x300);
+        for _ in 0..20 {
+            chip.step().unwrap();
+        }
+        // Waiting for input.
+        assert_eq!(chip.pc(), 0x314);
+        chip.input(0x99);
+        while chip.pc() != 0xFFF {
+            chip.step().unwrap();
+        }
+
+        let colors = chip.display.colors.as_ref().unwrap();
+        assert_eq!(colors.at(7, 0), COLOR_RED);
+        assert_eq!(colors.at(8, 0), COLOR_GREEN);
+        assert_eq!(colors.at(23, 3), COLOR_GREEN);
+        assert_eq!(colors.at(24, 0), COLOR_RED);
+        assert_eq!(colors.at(8, 4), COLOR_RED);
+        assert_eq!(colors.background(), COLOR_BLACK);
+        assert_eq!(chip.v[3], 0x41);
+        assert_eq!(chip.output(), 0xA);
+        assert_eq!(chip.v[6], 0x99);
+        assert_eq!(chip.v[7], 0);
+    }
+
+    #[test]
+    fn test_chip8e() {
+        let mut chip = Chip::with_platform(&platform::CHIP8E);
+        chip.memory.load_program(0x200, &[
+            LD_R_B(0, 5),
+            LD_R_B(1, 3),
+            SGT_R(0, 1),
+            LD_R_B(2, 1),
+            LD_I_A(0x300),
+            LD_IIP_RR(0, 1),
+            LD_R_B(3, 2),
+            SKIP(3),
+            LD_R_B(4, 1),
+            JP_FWD(4),
+            LD_R_B(5, 1),
+            JP(0xFFF),
+            LD_R_B(6, 3),
+            LD_DT_R_WAIT(6),
+            JP_BACK(8),
+        ]);
+
+        chip.jump(0x200);
+        while chip.pc() != 0x21C {
+            chip.step().unwrap();
+        }
+        // Waiting for the delay timer.
+        for _ in 0..10 {
+            chip.step().unwrap();
+        }
+        assert_eq!(chip.pc(), 0x21C);
+        for _ in 0..3 {
+            chip.tick();
+        }
+        while chip.pc() != 0xFFF {
+            chip.step().unwrap();
+        }
+
+        assert_eq!(chip.v[2], 0);
+        assert_eq!(chip.v[4], 0);
+        assert_eq!(chip.v[5], 0);
+        assert_eq!(&chip.memory.bytes[0x300..0x302], &[5, 3]);
+        assert_eq!(chip.i(), 0x302);
+    }
+
+    #[test]
+    fn test_hires() {
+        let mut chip = Chip::with_platform(&platform::HIRES);
+        chip.load_font();
+        assert_eq!((chip.display.width(), chip.display.height()), (64, 64));
+        chip.memory.load_program(0x2C0, &[
+            LD_R_B(0, 60),
+            LD_R_B(1, 40),
+            LD_F_R(2),
+            DRW(0, 1, 5),
+            CLS_HIRES,
+            DRW(0, 1, 5),
+            JP(0xFFF),
+        ]);
+
+        chip.run_program(platform::HIRES.entry() as usize).unwrap();
+        assert!(chip.display.at(60, 40));
+        assert!(!chip.display.at(61, 41));
+        assert!(chip.display.at(63, 44));
+        assert!(!chip.display.at(60, 8));
+        assert_eq!(chip.v[0xF], 0);
+    }
+
+    #[test]
+    fn test_megachip() {
+        let mut chip = Chip::with_platform(&platform::MEGACHIP);
+        chip.memory.load_rom(&[
+            0x00, 0x11,             // MEGAON
+            0x01, 0x01, 0x00, 0x00, // LDHI I, #010000
+            0x02, 0x02,             // LDPAL #02
+            0x03, 0x02,             // SPRW #02
+            0x04, 0x02,             // SPRH #02
+            0x09, 0x01,             // CCOL #01
+            0x01, 0x01, 0x00, 0x08, // LDHI I, #010008
+            0x60, 0x0A,             // LD V0, #0A
+            0x61, 0x14,             // LD V1, #14
+            0xD0, 0x10,             // DRW V0, V1, 0
+            0x82, 0xF0,             // LD V2, VF
+            0x08, 0x03,             // BMODE #3
+            0xD0, 0x10,             // DRW V0, V1, 0
+            0x01, 0x01, 0x00, 0x0C, // LDHI I, #01000C
+            0x06, 0x01,             // DIGISND #1
+            0x1F, 0xFF,             // JP #FFF
+        ], 0x200).unwrap();
+        chip.memory.bytes[0x10000..0x10008].copy_from_slice(&[
+            0xFF, 0x80, 0x00, 0x00,
+            0xFF, 0x00, 0x40, 0x00,
+        ]);
+        chip.memory.bytes[0x10008..0x1000C].copy_from_slice(&[1, 2, 0, 1]);
+        chip.memory.bytes[0x1000C..0x10015].copy_from_slice(&[
+            0x1F, 0x40, 0x00, 0x00, 0x03, 0x00, 0x80, 0xFF, 0x00,
+        ]);
+
+        chip.run_program(0x200).unwrap();
+        assert_eq!(chip.i, 0x1000C);
+        assert_eq!((chip.display.width(), chip.display.height()), (256, 192));
+        let framebuffer = chip.display.framebuffer.as_ref().unwrap();
+        // Drawn twice, the second time adding to the colors.
+        assert_eq!(framebuffer.at(10, 20), 0xFFFF_0000);
+        assert_eq!(framebuffer.at(11, 20), 0xFF00_8000);
+        assert_eq!(framebuffer.at(10, 21), 0);
+        assert_eq!(framebuffer.at(11, 21), 0xFFFF_0000);
+        assert_eq!(chip.v[2], 0);
+        assert_eq!(chip.v[0xF], 1);
+        assert_eq!(chip.sample(), Some(&Sample {
+            rate: 8000,
+            data: vec![0x80, 0xFF, 0x00],
+            looping: false,
+        }));
+    }
+
+    #[test]
+    fn test_quirks() {
+        let program = &[
+            LD_R_B(0, 1),
+            LD_R_B(1, 4),
+            SHL(0, 1),
+            LD_I_A(0x300),
+            LD_II_R(1),
+            JP(0xFFF),
+        ];
+
+        let mut chip = Chip::new();
+        chip.memory.load_program(0x200, program);
+        chip.run_program(0x200).unwrap();
+        assert_eq!(chip.v[0], 2);
+        assert_eq!(chip.i, 0x300);
+
+        let mut chip = Chip::new();
+        chip.quirks = Quirks::preset("vip").unwrap();
+        chip.memory.load_program(0x200, program);
+        chip.run_program(0x200).unwrap();
+        assert_eq!(chip.v[0], 8);
+        assert_eq!(chip.i, 0x302);
+    }
+
+    #[test]
+    fn test_seed() {
+        let program = &[
+            RND(0, 0xFF),
+            RND(1, 0xFF),
+            JP(0xFFF),
+        ];
+
+        let mut a = Chip::new();
+        a.seed(1234);
+        a.memory.load_program(0x200, program);
+        a.run_program(0x200).unwrap();
+
+        let mut b = Chip::new();
+        b.seed(1234);
+        b.memory.load_program(0x200, program);
+        b.run_program(0x200).unwrap();
+
+        assert_eq!(a.v, b.v);
+    }
+
+    #[test]
+    fn test_memory_policy() {
+        let program = &[
+            LD_I_A(0xFFE),
+            DRW(0, 0, 4),
+            LD_R_B(0, 0x1A),
+            LD_F_R(0),
+            JP(0xFFF),
+        ];
+        let run = |policy| {
+            let mut chip = Chip::new();
+            chip.memory_policy = policy;
+            chip.memory.load_program(0x200, program);
+            chip.memory.bytes[0xFFE] = 0x80;
+            chip.memory.bytes[0xFFF] = 0x80;
+            chip.memory.bytes[0x000] = 0x80;
+            chip.run_program(0x200).map(|_| chip)
+        };
+
+        assert_eq!(run(MemoryPolicy::Fault).err(),
+                   Some("address out of bounds: 1000".to_string()));
+
+        let chip = run(MemoryPolicy::Wrap).unwrap();
+        assert_eq!((0..4).map(|y| chip.display.at(0, y)).collect::<Vec<_>>(),
+                   vec![true, true, true, false]);
+        assert_eq!(chip.i, 0xA * 5);
+
+        let chip = run(MemoryPolicy::Clamp).unwrap();
+        assert_eq!((0..4).map(|y| chip.display.at(0, y)).collect::<Vec<_>>(),
+                   vec![true, true, true, true]);
+        assert_eq!(chip.i, 0xF * 5);
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        let mut chip = Chip::new();
+        chip.memory.load_program(0x200, &[CALL(0x200)]);
+        chip.jump(0x200);
+        for _ in 0..STACK_SIZE {
+            chip.step().unwrap();
+        }
+        assert_eq!(chip.step(), Err("stack overflow".to_string()));
+    }
+
+    // Runs random programs, with random keys pressed, to check that no
+    // program can crash the emulator (it's fine to stop with an error).
+    #[test]
+    fn test_random_programs() {
+        use rand::Rng;
+
+        let mut rng = SmallRng::seed_from_u64(0);
+        for &policy in &[MemoryPolicy::Fault, MemoryPolicy::Wrap, MemoryPolicy::Clamp] {
+            // Each platform comes with its own quirks.
+            for &platform in platform::PLATFORMS {
+                // Programs only run from the first 64 KiB (PC is 16 bits).
+                let size = platform.memory_size.min(0x10000);
+                for _ in 0..100 {
+                    let mut chip = Chip::with_platform(platform);
+                    chip.seed(rng.gen());
+                    chip.memory_policy = policy;
+                    chip.load_font();
+                    let rom: Vec<u8> = (0..size - 0x200).map(|_| rng.gen()).collect();
+                    chip.memory.load_rom(&rom, 0x200).unwrap();
+                    // Jump anywhere, including the last (odd) address.
+                    chip.jump(rng.gen_range(0, size) as u16);
+
+                    for _ in 0..1000 {
+                        let key = rng.gen_range(0, KEYS_SIZE as u8);
+                        if rng.gen() {
+                            chip.key_down(key);
+                        } else {
+                            chip.key_up(key);
+                        }
+                        if rng.gen_range(0, 10) == 0 {
+                            chip.tick();
+                        }
+                        if chip.step().is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Arithmetic and logic instructions, including the cases when X or Y is
+    // VF. The flag is always written last.
+    #[test]
+    fn test_arithmetic() {
+        // (preset, instruction, VX, VY, VF before, VX after, VF after), with
+        // X = 1 and Y = 2 unless the instruction uses VF.
+        let cases: &[(&str, Instr, u8, u8, u8, u8, u8)] = &[
+            ("default", ADD(1, 2), 0x10, 0x20, 7, 0x30, 0),
+            ("default", ADD(1, 2), 0xF0, 0x20, 7, 0x10, 1),
+            ("default", ADD(0xF, 2), 0xF0, 0x20, 0xF0, 1, 1),
+            ("default", ADD(1, 0xF), 0x10, 0x20, 0x20, 0x30, 0),
+            ("default", ADD(0xF, 0xF), 0x80, 0x80, 0x80, 1, 1),
+            ("default", ADD_R_B(1, 0x20), 0xF0, 0, 7, 0x10, 7),
+            ("default", ADD_R_B(0xF, 0x20), 0xF0, 0, 0xF0, 0x10, 0x10),
+            ("default", SUB(1, 2), 0x30, 0x10, 7, 0x20, 1),
+            ("default", SUB(1, 2), 0x10, 0x30, 7, 0xE0, 0),
+            ("default", SUB(1, 2), 0x10, 0x10, 7, 0x00, 1),
+            ("default", SUB(0xF, 2), 0x10, 0x30, 0x10, 0, 0),
+            ("default", SUB(1, 0xF), 0x30, 0x10, 0x10, 0x20, 1),
+            ("default", SUBN(1, 2), 0x10, 0x30, 7, 0x20, 1),
+            ("default", SUBN(1, 2), 0x30, 0x10, 7, 0xE0, 0),
+            ("default", SUBN(0xF, 2), 0x30, 0x10, 0x30, 0, 0),
+            ("default", SHR(1, 2), 0x03, 0x10, 7, 0x01, 1),
+            ("default", SHR(1, 2), 0x02, 0x10, 7, 0x01, 0),
+            ("default", SHR(0xF, 2), 0x03, 0x10, 0x03, 1, 1),
+            ("default", SHL(1, 2), 0x81, 0x10, 7, 0x02, 1),
+            ("default", SHL(1, 2), 0x41, 0x10, 7, 0x82, 0),
+            ("default", SHL(0xF, 2), 0x41, 0x10, 0x41, 0, 0),
+            ("vip", SHR(1, 2), 0x10, 0x03, 7, 0x01, 1),
+            ("vip", SHL(1, 2), 0x10, 0x81, 7, 0x02, 1),
+            ("vip", SHR(1, 0xF), 0x10, 0x05, 0x05, 0x02, 1),
+            ("vip", SHL(0xF, 0xF), 0x80, 0x80, 0x80, 1, 1),
+            ("default", OR(1, 2), 0x0F, 0xF0, 7, 0xFF, 7),
+            ("default", AND(1, 2), 0x0F, 0xFF, 7, 0x0F, 7),
+            ("default", XOR(1, 2), 0x0F, 0xFF, 7, 0xF0, 7),
+            ("default", OR(0xF, 2), 0x0F, 0xF0, 0x0F, 0xFF, 0xFF),
+            ("vip", OR(1, 2), 0x0F, 0xF0, 7, 0xFF, 0),
+            ("vip", AND(1, 2), 0x0F, 0xFF, 7, 0x0F, 0),
+            ("vip", XOR(1, 2), 0x0F, 0xFF, 7, 0xF0, 0),
+            ("vip", XOR(0xF, 2), 0x0F, 0xFF, 0x0F, 0, 0),
+            ("chip48", OR(1, 2), 0x0F, 0xF0, 7, 0xFF, 7),
+        ];
+
+        for &(preset, instr, vx, vy, vf, vx_after, vf_after) in cases {
+            let (x, y) = match instr {
+                ADD(x, y) | SUB(x, y) | SUBN(x, y) | SHR(x, y) | SHL(x, y) |
+                OR(x, y) | AND(x, y) | XOR(x, y) => (x as usize, y as usize),
+                ADD_R_B(x, _) => (x as usize, 2),
+                _ => unreachable!(),
+            };
+            let mut chip = Chip::new();
+            chip.quirks = Quirks::preset(preset).unwrap();
+            chip.v[x] = vx;
+            chip.v[y] = vy;
+            chip.v[0xF] = vf;
+            chip.memory.load_program(0x200, &[instr]);
+            chip.jump(0x200);
+            chip.step().unwrap();
+            assert_eq!((chip.v[x], chip.v[0xF]), (vx_after, vf_after),
+                       "{} {}, VX={:02X} VY={:02X} VF={:02X}", preset, instr, vx, vy, vf);
+        }
+    }
+
+    #[test]
+    fn test_arithmetic_vf() {
+        // Every preset and instruction, with X and Y being VF or not. The
+        // operands are read first, then VX is written, then VF (unless
+        // it's left alone), so VF as X ends up holding the flag.
+        type Op = fn(u8, u8) -> Instr;
+        // VX and VF (if changed), from VX and VY.
+        type Expected = fn(&Quirks, u8, u8) -> (u8, Option<u8>);
+        let ops: &[(Op, Expected)] = &[
+            (ADD, |_, a, b| (a.wrapping_add(b), Some((a as u16 + b as u16 > 0xFF) as u8))),
+            (SUB, |_, a, b| (a.wrapping_sub(b), Some((a >= b) as u8))),
+            (SUBN, |_, a, b| (b.wrapping_sub(a), Some((b >= a) as u8))),
+            (SHR, |q, a, b| {
+                let src = if q.shift_vy { b } else { a };
+                (src >> 1, Some(src & 1))
+            }),
+            (SHL, |q, a, b| {
+                let src = if q.shift_vy { b } else { a };
+                (src << 1, Some(src >> 7))
+            }),
+            (OR, |q, a, b| (a | b, if q.logic_resets_vf { Some(0) } else { None })),
+            (AND, |q, a, b| (a & b, if q.logic_resets_vf { Some(0) } else { None })),
+            (XOR, |q, a, b| (a ^ b, if q.logic_resets_vf { Some(0) } else { None })),
+            (|x, _| ADD_R_B(x, 0x90), |_, a, _| (a.wrapping_add(0x90), None)),
+        ];
+        let regs = [(1, 2), (0xF, 2), (1, 0xF), (0xF, 0xF)];
+        let values = [(0x81, 0x83, 0x07), (0x03, 0xF1, 0x80)];
+
+        for &preset in PRESETS {
+            let quirks = Quirks::preset(preset).unwrap();
+            for &(op, expected) in ops {
+                for &(x, y) in regs.iter() {
+                    for &(vx, vy, vf) in values.iter() {
+                        let mut v = [0; V_SIZE];
+                        v[x as usize] = vx;
+                        v[y as usize] = vy;
+                        v[0xF] = vf;
+                        let (result, flag) = expected(&quirks, v[x as usize], v[y as usize]);
+                        v[x as usize] = result;
+                        if let Some(flag) = flag {
+                            v[0xF] = flag;
+                        }
+
+                        let instr = op(x, y);
+                        let mut chip = Chip::new();
+                        chip.quirks = quirks;
+                        chip.v[x as usize] = vx;
+                        chip.v[y as usize] = vy;
+                        chip.v[0xF] = vf;
+                        chip.memory.load_program(0x200, &[instr]);
+                        chip.jump(0x200);
+                        chip.step().unwrap();
+                        assert_eq!(chip.v, v, "{} {}, VX={:02X} VY={:02X} VF={:02X}",
+                                   preset, instr, vx, vy, vf);
+                    }
+                }
+            }
+        }
+    }
 }
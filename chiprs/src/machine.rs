@@ -0,0 +1,207 @@
+use crate::{Chip, V_SIZE};
+use crate::display::Display;
+use crate::hook::Hook;
+use crate::host::{Beeper, FrameSink};
+use crate::vip::Vip;
+
+/// What a frontend needs from an emulated CHIP-8 machine. Implemented by
+/// `Chip`, which runs CHIP-8 instructions itself, and by `Vip`, which runs
+/// the original interpreter on an emulated 1802, so that the two can be
+/// compared instruction by instruction, and run in the same frontend.
+pub trait Machine {
+    /// Runs one CHIP-8 instruction.
+    fn step(&mut self) -> Result<(), String>;
+
+    /// Like `step`, calling `hook`. Machines that don't run CHIP-8
+    /// instructions themselves don't call it.
+    fn step_with(&mut self, hook: &mut dyn Hook) -> Result<(), String>;
+
+    /// Runs one frame (60 times per second): updates the timers, or, for
+    /// machines that keep their own time, runs until the end of the frame.
+    fn tick(&mut self);
+
+    /// Like `tick`, calling `hook` (see `step_with`).
+    fn tick_with(&mut self, hook: &mut dyn Hook);
+
+    /// Whether the machine keeps its own time, so that it only has to be
+    /// ticked, and instructions aren't run at a given speed.
+    fn keeps_time(&self) -> bool;
+
+    fn key_down(&mut self, key: u8);
+    fn key_up(&mut self, key: u8);
+
+    /// The second keypad (CHIP-8X). Ignored by machines without one.
+    fn key2_down(&mut self, _key: u8) {}
+    fn key2_up(&mut self, _key: u8) {}
+
+    fn display(&self) -> &Display;
+    fn sound_on(&self) -> bool;
+
+    /// Whether the program has exited (SCHIP's EXIT).
+    fn exited(&self) -> bool;
+
+    /// Installs the frontend's frame sink and beeper (see `host`).
+    fn set_frame_sink(&mut self, sink: Box<dyn FrameSink>);
+    fn set_beeper(&mut self, beeper: Box<dyn Beeper>);
+
+    /// Sends the screen to the frame sink, e.g. after single-stepping.
+    fn send_frame(&mut self);
+
+    /// The machine as a `Chip`, for frontends that show its internal state
+    /// (e.g. a debugger).
+    fn as_chip(&self) -> Option<&Chip>;
+
+    /// CHIP-8 registers, between instructions.
+    fn pc(&self) -> u16;
+    fn i(&self) -> u32;
+    fn v(&self) -> [u8; V_SIZE];
+}
+
+impl Machine for Chip {
+    fn step(&mut self) -> Result<(), String> {
+        Chip::step(self)
+    }
+
+    fn step_with(&mut self, hook: &mut dyn Hook) -> Result<(), String> {
+        Chip::step_with(self, hook)
+    }
+
+    fn tick(&mut self) {
+        Chip::tick(self);
+    }
+
+    fn tick_with(&mut self, hook: &mut dyn Hook) {
+        Chip::tick_with(self, hook);
+    }
+
+    fn keeps_time(&self) -> bool {
+        false
+    }
+
+    fn key_down(&mut self, key: u8) {
+        Chip::key_down(self, key);
+    }
+
+    fn key_up(&mut self, key: u8) {
+        Chip::key_up(self, key);
+    }
+
+    fn key2_down(&mut self, key: u8) {
+        Chip::key2_down(self, key);
+    }
+
+    fn key2_up(&mut self, key: u8) {
+        Chip::key2_up(self, key);
+    }
+
+    fn display(&self) -> &Display {
+        &self.display
+    }
+
+    fn sound_on(&self) -> bool {
+        Chip::sound_on(self)
+    }
+
+    fn exited(&self) -> bool {
+        Chip::exited(self)
+    }
+
+    fn set_frame_sink(&mut self, sink: Box<dyn FrameSink>) {
+        self.frame_sink = Some(sink);
+    }
+
+    fn set_beeper(&mut self, beeper: Box<dyn Beeper>) {
+        self.beeper = Some(beeper);
+    }
+
+    fn send_frame(&mut self) {
+        Chip::send_frame(self);
+    }
+
+    fn as_chip(&self) -> Option<&Chip> {
+        Some(self)
+    }
+
+    fn pc(&self) -> u16 {
+        Chip::pc(self)
+    }
+
+    fn i(&self) -> u32 {
+        Chip::i(self)
+    }
+
+    fn v(&self) -> [u8; V_SIZE] {
+        *Chip::v(self)
+    }
+}
+
+impl Machine for Vip {
+    fn step(&mut self) -> Result<(), String> {
+        Vip::step(self)
+    }
+
+    fn step_with(&mut self, _hook: &mut dyn Hook) -> Result<(), String> {
+        Vip::step(self)
+    }
+
+    // The interpreter's timers run off the video interrupt.
+    fn tick(&mut self) {
+        self.run_frame();
+    }
+
+    fn tick_with(&mut self, _hook: &mut dyn Hook) {
+        self.run_frame();
+    }
+
+    fn keeps_time(&self) -> bool {
+        true
+    }
+
+    fn key_down(&mut self, key: u8) {
+        Vip::key_down(self, key);
+    }
+
+    fn key_up(&mut self, key: u8) {
+        Vip::key_up(self, key);
+    }
+
+    fn display(&self) -> &Display {
+        &self.display
+    }
+
+    fn sound_on(&self) -> bool {
+        Vip::sound_on(self)
+    }
+
+    fn exited(&self) -> bool {
+        false
+    }
+
+    fn set_frame_sink(&mut self, sink: Box<dyn FrameSink>) {
+        self.frame_sink = Some(sink);
+    }
+
+    fn set_beeper(&mut self, beeper: Box<dyn Beeper>) {
+        self.beeper = Some(beeper);
+    }
+
+    fn send_frame(&mut self) {
+        Vip::send_frame(self);
+    }
+
+    fn as_chip(&self) -> Option<&Chip> {
+        None
+    }
+
+    fn pc(&self) -> u16 {
+        self.chip8_pc()
+    }
+
+    fn i(&self) -> u32 {
+        self.chip8_i() as u32
+    }
+
+    fn v(&self) -> [u8; V_SIZE] {
+        self.chip8_v()
+    }
+}
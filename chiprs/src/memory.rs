@@ -1,20 +1,141 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+
 use crate::instr::Instr;
 
+/// Size of the plain CHIP-8 address space. Other platforms can have more,
+/// see `Platform::memory_size`.
 pub const MEMORY_SIZE: usize = 0x1000;
-pub const FONT_SIZE: usize = 0x1000;
+/// Where the fonts are loaded. Characters are 5 bytes long in the small
+/// font, and 10 in the big one.
+pub const FONT_ADDR: usize = 0;
+pub const BIG_FONT_ADDR: usize = 0x50;
+
+#[derive(Debug)]
+pub enum LoadError {
+    /// Load address outside of memory.
+    BadAddress(usize),
+    /// ROM doesn't fit in memory between the load address and the end.
+    TooLarge { size: usize, available: usize },
+    Io(io::Error),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::BadAddress(addr) =>
+                write!(f, "load address {:#X} is outside of memory", addr),
+            LoadError::TooLarge { size, available } =>
+                write!(f, "ROM is too large: {} bytes, only {} fit in memory ({} would be cut off)",
+                       size, available, size - available),
+            LoadError::Io(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> LoadError {
+        LoadError::Io(e)
+    }
+}
+
+/// Things that are suspicious about a ROM, but don't prevent loading it.
+#[derive(Debug, PartialEq)]
+pub enum LoadWarning {
+    Empty,
+    /// Instructions are 2 bytes long, so this is probably not a CHIP-8
+    /// program (or it's truncated).
+    OddLength(usize),
+}
+
+impl fmt::Display for LoadWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadWarning::Empty => write!(f, "ROM is empty"),
+            LoadWarning::OddLength(size) =>
+                write!(f, "ROM has odd length ({} bytes), it might be truncated", size),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct LoadedRom {
+    pub size: usize,
+    pub warnings: Vec<LoadWarning>,
+}
+
+/// What happens when an instruction accesses memory outside of the 4 KiB
+/// address space (or uses a font character or key number past F).
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum MemoryPolicy {
+    /// Stop with an error.
+    #[default]
+    Fault,
+    /// Wrap around to the beginning.
+    Wrap,
+    /// Use the last valid address instead.
+    Clamp,
+}
+
+pub const POLICIES: &[&str] = &["fault", "wrap", "clamp"];
+
+impl MemoryPolicy {
+    pub fn from_name(name: &str) -> Option<MemoryPolicy> {
+        match name {
+            "fault" => Some(MemoryPolicy::Fault),
+            "wrap" => Some(MemoryPolicy::Wrap),
+            "clamp" => Some(MemoryPolicy::Clamp),
+            _ => None,
+        }
+    }
+
+    /// Maps `addr` to a valid index (below `size`), or returns None if it's
+    /// out of bounds and the policy is `Fault`.
+    pub fn apply(self, addr: usize, size: usize) -> Option<usize> {
+        if addr < size {
+            return Some(addr);
+        }
+        match self {
+            MemoryPolicy::Fault => None,
+            MemoryPolicy::Wrap => Some(addr % size),
+            MemoryPolicy::Clamp => Some(size - 1),
+        }
+    }
+}
 
 pub struct Memory {
-    pub bytes: [u8; MEMORY_SIZE],
+    pub bytes: Vec<u8>,
+}
+
+impl Default for Memory {
+    fn default() -> Memory {
+        Memory::new()
+    }
 }
 
 impl Memory {
     pub fn new() -> Memory {
-        Memory { bytes: [0; MEMORY_SIZE] }
+        Memory::with_size(MEMORY_SIZE)
+    }
+
+    pub fn with_size(size: usize) -> Memory {
+        Memory { bytes: vec![0; size] }
+    }
+
+    pub fn size(&self) -> usize {
+        self.bytes.len()
     }
 
     pub fn load_font(&mut self) {
-        let font = parse_font(FONT_STR);
-        self.bytes[0..MEMORY_SIZE].copy_from_slice(&font[..]);
+        self.load_fonts(FONT, &[]);
+    }
+
+    pub fn load_fonts(&mut self, font: &[u8], big_font: &[u8]) {
+        self.bytes[FONT_ADDR..FONT_ADDR + font.len()].copy_from_slice(font);
+        self.bytes[BIG_FONT_ADDR..BIG_FONT_ADDR + big_font.len()].copy_from_slice(big_font);
     }
 
     pub fn load_program(&mut self, addr: usize, program: &[Instr]) {
@@ -25,132 +146,115 @@ impl Memory {
         }
     }
 
+    /// Loads a ROM at given address. Fails, without changing memory, if it
+    /// doesn't fit.
+    pub fn load_rom(&mut self, rom: &[u8], addr: usize) -> Result<LoadedRom, LoadError> {
+        if addr >= self.size() {
+            return Err(LoadError::BadAddress(addr));
+        }
+        let available = self.size() - addr;
+        if rom.len() > available {
+            return Err(LoadError::TooLarge { size: rom.len(), available });
+        }
+        self.bytes[addr..addr + rom.len()].copy_from_slice(rom);
+
+        let mut warnings = vec![];
+        if rom.is_empty() {
+            warnings.push(LoadWarning::Empty);
+        } else if !rom.len().is_multiple_of(2) {
+            warnings.push(LoadWarning::OddLength(rom.len()));
+        }
+        Ok(LoadedRom { size: rom.len(), warnings })
+    }
+
+    pub fn load_rom_from_reader(&mut self, reader: &mut dyn Read, addr: usize)
+                                -> Result<LoadedRom, LoadError> {
+        let mut buf = vec![];
+        reader.read_to_end(&mut buf)?;
+        self.load_rom(&buf, addr)
+    }
+
     pub fn load_program_from_file(&mut self, addr: usize, filename: &str)
-                                  -> ::std::io::Result<()> {
-        use std::fs::File;
-        use std::io::Read;
+                                  -> Result<LoadedRom, LoadError> {
         let mut f = File::open(filename)?;
-        let mut buf = vec![];
-        f.read_to_end(&mut buf)?;
-        &self.bytes[addr..addr+buf.len()].copy_from_slice(&buf);
-        Ok(())
+        self.load_rom_from_reader(&mut f, addr)
     }
 
     pub fn u16_at(&self, addr: usize) -> u16 {
-        return ((self.bytes[addr] as u16) << 8) |
-                (self.bytes[addr + 1] as u16);
+        ((self.bytes[addr] as u16) << 8) |
+            (self.bytes[addr + 1] as u16)
     }
 }
 
-fn parse_font(s: &str) -> [u8; FONT_SIZE] {
-    let mut result = [0; FONT_SIZE];
-    for (i, letter) in s.split(".").enumerate() {
-        for (j, line) in letter.lines().skip(1).enumerate() {
-            let mut a: u8 = 0;
-            if line.get(0..1) == Some("#") { a |= 0x80; }
-            if line.get(1..2) == Some("#") { a |= 0x40; }
-            if line.get(2..3) == Some("#") { a |= 0x20; }
-            if line.get(3..4) == Some("#") { a |= 0x10; }
-            result[i*5 + j] = a;
+/// Hexadecimal digits, 4x5 pixels.
+pub const FONT: &[u8] = &[
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+/// Hexadecimal digits, 8x10 pixels. SUPER-CHIP only has 0-9, XO-CHIP
+/// adds A-F.
+pub const BIG_FONT: &[u8] = &[
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x3C, 0x7E, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFE, 0xC3, 0xC3, 0xFE, 0xFE, 0xC3, 0xC3, 0xFE, 0xFC, // B
+    0x3C, 0x7E, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0x7E, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFE, 0xFE, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFE, 0xFE, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_rom() {
+        let mut memory = Memory::new();
+        assert_eq!(memory.load_rom(&[1, 2, 3, 4], 0x200).unwrap(),
+                   LoadedRom { size: 4, warnings: vec![] });
+        assert_eq!(&memory.bytes[0x200..0x204], &[1, 2, 3, 4]);
+
+        assert_eq!(memory.load_rom(&[], 0x200).unwrap().warnings, vec![LoadWarning::Empty]);
+        assert_eq!(memory.load_rom(&[1, 2, 3], 0x200).unwrap().warnings,
+                   vec![LoadWarning::OddLength(3)]);
+
+        let mut reader: &[u8] = &[5, 6];
+        assert_eq!(memory.load_rom_from_reader(&mut reader, 0xFFE).unwrap().size, 2);
+        assert_eq!(&memory.bytes[0xFFE..], &[5, 6]);
+
+        match memory.load_rom(&[0; 0xE01], 0x200) {
+            Err(LoadError::TooLarge { size: 0xE01, available: 0xE00 }) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(memory.bytes[0x200..0x204] == [1, 2, 3, 4]);
+        match memory.load_rom(&[0; 2], 0x1000) {
+            Err(LoadError::BadAddress(0x1000)) => (),
+            other => panic!("unexpected result: {:?}", other),
         }
     }
-    result
 }
-
-const FONT_STR: &str = r"
-####
-#  #
-#  #
-#  #
-####
-.
-  #
- ##
-  #
-  #
- ###
-.
-####
-   #
-####
-#
-####
-.
-####
-   #
-####
-   #
-####
-.
-#  #
-#  #
-####
-   #
-   #
-.
-####
-#
-####
-   #
-####
-.
-####
-#
-####
-#  #
-####
-.
-####
-   #
-  #
- #
- #
-.
-####
-#  #
-####
-#  #
-####
-.
-####
-#  #
-####
-   #
-####
-.
-####
-#  #
-####
-#  #
-#  #
-.
-###
-#  #
-###
-#  #
-###
-.
-####
-#
-#
-#
-####
-.
-###
-#  #
-#  #
-#  #
-###
-.
-####
-#
-####
-#
-####
-.
-####
-#
-####
-#
-#
-";
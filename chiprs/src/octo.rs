@@ -0,0 +1,407 @@
+//! Octo cartridges: GIF images with a label for humans, and the program and
+//! its settings hidden in the low 4 bits of each pixel's color index.
+//!
+//! Two pixels make a byte (high nibble first), continuing across frames.
+//! The bytes are a 32-bit big-endian length, followed by that many bytes
+//! of JSON: `{"program": "<Octo source>", "options": {...}}`.
+
+use std::collections::BTreeMap;
+
+use crate::KEYS_SIZE;
+use crate::compiler;
+use crate::gif;
+use crate::platform::{self, Platform};
+use crate::quirks::{self, Quirks};
+
+#[derive(Debug, PartialEq)]
+pub struct Cartridge {
+    /// Octo source code.
+    pub program: String,
+    pub options: OctoOptions,
+}
+
+/// Octo's keyboard layout: the key (on a QWERTY keyboard) for each CHIP-8
+/// key, from 0 to F. Cartridges are made to be played with it.
+pub const KEYBOARD: [char; KEYS_SIZE] = [
+    'x', '1', '2', '3', 'q', 'w', 'e', 'a',
+    's', 'd', 'z', 'c', '4', 'r', 'f', 'v',
+];
+
+/// Settings saved in a cartridge, translated to ours. Octo's clipping,
+/// VF order and vblank quirks, and its buzzer colors, have no equivalent
+/// here. The key mapping is not a setting: Octo always uses `KEYBOARD`.
+#[derive(Debug, PartialEq)]
+pub struct OctoOptions {
+    /// Instructions per frame.
+    pub tickrate: Option<u32>,
+    pub quirks: Quirks,
+    /// Largest ROM the program is meant for (which tells apart CHIP-8,
+    /// SUPER-CHIP and XO-CHIP programs).
+    pub max_size: Option<u32>,
+    /// Colors as RGB: background, pixels in plane 1, plane 2 and both.
+    pub background: Option<u32>,
+    pub foreground: [Option<u32>; 3],
+}
+
+pub fn is_cartridge(data: &[u8]) -> bool {
+    gif::is_gif(data)
+}
+
+impl Cartridge {
+    pub fn load(data: &[u8]) -> Result<Cartridge, String> {
+        let image = gif::decode(data)?;
+        let nibbles: Vec<u8> = image.frames.iter().flatten().map(|p| p & 0xF).collect();
+        let bytes: Vec<u8> = nibbles.chunks_exact(2).map(|pair| pair[0] << 4 | pair[1]).collect();
+        if bytes.len() < 4 {
+            return Err("cartridge is too small".to_string());
+        }
+        let size = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+        let payload = bytes.get(4..4 + size)
+            .ok_or_else(|| format!("cartridge payload is truncated ({} bytes)", size))?;
+        let text = std::str::from_utf8(payload)
+            .map_err(|_| "cartridge payload is not valid UTF-8".to_string())?;
+        let json = Parser::new(text).parse()?;
+
+        let program = match json.get("program") {
+            Some(Json::String(s)) => s.clone(),
+            _ => return Err("cartridge has no program".to_string()),
+        };
+        let options = json.get("options");
+        let option = |name: &str| options.and_then(|o| o.get(name));
+        let flag = |name: &str| match option(name) {
+            Some(Json::Bool(b)) => Some(*b),
+            _ => None,
+        };
+        let number = |name: &str| match option(name) {
+            Some(Json::Number(n)) if *n >= 0.0 => Some(*n as u32),
+            _ => None,
+        };
+        let color = |name: &str| match option(name) {
+            Some(Json::String(s)) => parse_color(s),
+            _ => None,
+        };
+
+        // Octo's quirks are the XO-CHIP behaviour by default, and turn on
+        // the CHIP-48 one.
+        let mut quirks = quirks::XOCHIP;
+        if let Some(b) = flag("shiftQuirks") {
+            quirks.shift_vy = !b;
+        }
+        if let Some(b) = flag("loadStoreQuirks") {
+            quirks.load_store_increments_i = !b;
+        }
+        if let Some(b) = flag("jumpQuirks") {
+            quirks.jump_vx = b;
+        }
+        if let Some(b) = flag("logicQuirks") {
+            quirks.logic_resets_vf = b;
+        }
+
+        Ok(Cartridge {
+            program,
+            options: OctoOptions {
+                tickrate: number("tickrate"),
+                quirks,
+                max_size: number("maxSize"),
+                background: color("backgroundColor"),
+                foreground: [color("fillColor"), color("fillColor2"), color("blendColor")],
+            },
+        })
+    }
+
+    pub fn platform(&self) -> &'static Platform {
+        match self.options.max_size {
+            Some(size) if size <= 3232 => &platform::CHIP8,
+            Some(size) if size <= 3584 => &platform::SCHIP,
+            _ => &platform::XOCHIP,
+        }
+    }
+
+    /// Instructions per second (Octo counts them per frame).
+    pub fn speed(&self) -> Option<u32> {
+        self.options.tickrate.map(|tickrate| tickrate * 60)
+    }
+
+    /// Compiles the program (see `compiler` for what's supported).
+    pub fn rom(&self) -> Result<Vec<u8>, String> {
+        compiler::compile(&self.program).map_err(|e| format!("can't compile the program: {}", e))
+    }
+}
+
+fn parse_color(s: &str) -> Option<u32> {
+    let hex = s.strip_prefix('#')?;
+    if hex.len() == 6 {
+        u32::from_str_radix(hex, 16).ok()
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(BTreeMap<String, Json>),
+}
+
+impl Json {
+    fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(map) => map.get(key),
+            _ => None,
+        }
+    }
+}
+
+/// Just enough JSON for the cartridge payload.
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(text: &'a str) -> Parser<'a> {
+        Parser { text, pos: 0 }
+    }
+
+    fn parse(&mut self) -> Result<Json, String> {
+        let value = self.value()?;
+        self.skip_whitespace();
+        if self.pos < self.text.len() {
+            return Err(self.error());
+        }
+        Ok(value)
+    }
+
+    fn error(&self) -> String {
+        format!("bad JSON in cartridge at position {}", self.pos)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.next();
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.next() == Some(c) {
+            Ok(())
+        } else {
+            Err(self.error())
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        let rest = &self.text[self.pos..];
+        for &(word, value) in &[("null", None), ("true", Some(true)), ("false", Some(false))] {
+            if rest.starts_with(word) {
+                self.pos += word.len();
+                return Ok(value.map_or(Json::Null, Json::Bool));
+            }
+        }
+        match self.peek() {
+            Some('"') => Ok(Json::String(self.string()?)),
+            Some('[') => {
+                self.next();
+                let mut items = vec![];
+                self.skip_whitespace();
+                if self.peek() == Some(']') {
+                    self.next();
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    self.skip_whitespace();
+                    match self.next() {
+                        Some(',') => (),
+                        Some(']') => return Ok(Json::Array(items)),
+                        _ => return Err(self.error()),
+                    }
+                }
+            }
+            Some('{') => {
+                self.next();
+                let mut map = BTreeMap::new();
+                self.skip_whitespace();
+                if self.peek() == Some('}') {
+                    self.next();
+                    return Ok(Json::Object(map));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    self.expect(':')?;
+                    map.insert(key, self.value()?);
+                    self.skip_whitespace();
+                    match self.next() {
+                        Some(',') => (),
+                        Some('}') => return Ok(Json::Object(map)),
+                        _ => return Err(self.error()),
+                    }
+                }
+            }
+            _ => {
+                let len = rest.find(|c: char| !"+-.eE0123456789".contains(c)).unwrap_or(rest.len());
+                let n = rest[..len].parse().map_err(|_| self.error())?;
+                self.pos += len;
+                Ok(Json::Number(n))
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if self.next() != Some('"') {
+            return Err(self.error());
+        }
+        let mut s = String::new();
+        loop {
+            match self.next().ok_or_else(|| self.error())? {
+                '"' => return Ok(s),
+                '\\' => {
+                    let c = match self.next().ok_or_else(|| self.error())? {
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'u' => self.unicode_escape()?,
+                        c => c,
+                    };
+                    s.push(c);
+                }
+                c => s.push(c),
+            }
+        }
+    }
+
+    // \uXXXX, possibly a surrogate pair.
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let mut n = self.hex4()?;
+        if (0xD800..0xDC00).contains(&n) && self.text[self.pos..].starts_with("\\u") {
+            self.pos += 2;
+            let low = self.hex4()?;
+            n = 0x10000 + ((n - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF);
+        }
+        Ok(std::char::from_u32(n).unwrap_or('\u{FFFD}'))
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self.text.get(self.pos..self.pos + 4).ok_or_else(|| self.error())?;
+        let n = u32::from_str_radix(digits, 16).map_err(|_| self.error())?;
+        self.pos += 4;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Chip;
+    use crate::gif::tests::encode;
+
+    fn cartridge(json: &str) -> Vec<u8> {
+        let mut bytes = (json.len() as u32).to_be_bytes().to_vec();
+        bytes.extend(json.as_bytes());
+        // The high nibbles make a label.
+        let mut pixels: Vec<u8> = bytes.iter()
+            .flat_map(|&b| vec![b >> 4, b & 0xF])
+            .enumerate()
+            .map(|(i, n)| ((i % 3) << 4) as u8 | n)
+            .collect();
+        // Split into 2 frames.
+        let height = pixels.len().div_ceil(64);
+        let size = 32 * height;
+        pixels.resize(size * 2, 0);
+        encode(32, height, &[pixels[..size].to_vec(), pixels[size..].to_vec()])
+    }
+
+    #[test]
+    fn test_load() {
+        let json = r##"{
+            "program": ": main\n  0x00 0xE0 # clear\n  :byte 18 0b10 -1\n: data \"é\"",
+            "options": {
+                "tickrate": 20, "maxSize": 3584, "shiftQuirks": true,
+                "loadStoreQuirks": true, "jumpQuirks": false, "vBlankQuirks": null,
+                "fillColor": "#FFCC00", "fillColor2": "#FF6600", "blendColor": "#662200",
+                "backgroundColor": "#996600", "screenRotation": 0, "fontStyle": "octo",
+                "touchInputMode": "none", "list": [1.5e2, {}, []]
+            }
+        }"##;
+        let cart = Cartridge::load(&cartridge(json)).unwrap();
+        assert!(cart.program.starts_with(": main\n"));
+        assert!(cart.program.ends_with("\"é\""));
+        assert_eq!(cart.options, OctoOptions {
+            tickrate: Some(20),
+            quirks: Quirks {
+                shift_vy: false,
+                load_store_increments_i: false,
+                jump_vx: false,
+                logic_resets_vf: false,
+            },
+            max_size: Some(3584),
+            background: Some(0x996600),
+            foreground: [Some(0xFFCC00), Some(0xFF6600), Some(0x662200)],
+        });
+        assert_eq!(cart.platform().name, "schip");
+        assert_eq!(cart.speed(), Some(1200));
+        assert_eq!(cart.rom(), Err(
+            "can't compile the program: line 4: undefined label \"\\\"é\\\"\"".to_string()));
+
+        let cart = Cartridge::load(&cartridge(r#"{"program": ": main 0x00 0xE0 :byte 18 0b10 -1"}"#))
+            .unwrap();
+        assert_eq!(cart.options.quirks, quirks::XOCHIP);
+        assert_eq!(cart.platform().name, "xochip");
+        assert_eq!(cart.rom(), Ok(vec![0x00, 0xE0, 18, 2, 0xFF]));
+
+        assert!(Cartridge::load(&cartridge(r#"{"program": 1}"#)).is_err());
+        assert!(Cartridge::load(&cartridge(r#"{"program": ""#)).is_err());
+    }
+
+    #[test]
+    fn test_compile_and_run() {
+        // Cartridges store the source, as written in Octo.
+        let program = "\
+# Draw a smile in the middle of the screen.
+: main
+  i := smile
+  v0 := 28
+  v1 := 14
+  sprite v0 v1 3
+  loop again
+
+: smile
+  0x81 0x00 0x7E
+";
+        let json = format!(r#"{{"program": {:?}, "options": {{"tickrate": 7, "maxSize": 3216}}}}"#,
+                           program);
+        let cart = Cartridge::load(&cartridge(&json)).unwrap();
+        let rom = cart.rom().unwrap();
+        assert_eq!(rom.len(), 13);
+
+        let mut chip = Chip::with_platform(cart.platform());
+        chip.memory.load_rom(&rom, 0x200).unwrap();
+        chip.jump(0x200);
+        for _ in 0..10 {
+            chip.step().unwrap();
+        }
+        let row = |y| (28..36).map(|x| chip.display.at(x, y)).collect::<Vec<_>>();
+        assert_eq!(row(14), vec![true, false, false, false, false, false, false, true]);
+        assert_eq!(row(15), vec![false; 8]);
+        assert_eq!(row(16), vec![false, true, true, true, true, true, true, false]);
+    }
+}
@@ -0,0 +1,220 @@
+use crate::instr::{self, Instr, Opcode};
+use crate::memory::{FONT, BIG_FONT};
+use crate::quirks::{self, Quirks};
+
+/// Description of a CHIP-8 dialect: everything that's fixed for a given
+/// machine, as opposed to `Quirks`, which are left for the user to tweak.
+///
+/// Adding a dialect means adding a `Platform` (and a decoding table, if it
+/// has new opcodes), rather than changing `Chip`.
+pub struct Platform {
+    pub name: &'static str,
+    pub title: &'static str,
+    pub memory_size: usize,
+    /// Where programs are loaded, and start executing.
+    pub start: u16,
+    /// Where execution starts, if not at `start`.
+    pub entry: Option<u16>,
+    /// Available resolutions (width, height). The first one is used at
+    /// startup, LOW and HIGH switch between the first and the second.
+    pub display_modes: &'static [(usize, usize)],
+    /// Number of bit planes (1 for monochrome).
+    pub planes: usize,
+    /// Whether the display has color attributes (CHIP-8X).
+    pub colors: bool,
+    /// Whether there's a second keypad (CHIP-8X).
+    pub second_keypad: bool,
+    /// Decoding tables, searched in order (see `instr::decode`).
+    pub decoder: &'static [&'static [Opcode]],
+    pub font: &'static [u8],
+    /// Big font for LD HF, Vx; empty if there's none.
+    pub big_font: &'static [u8],
+    pub stack_size: usize,
+    /// Whether DRW with height 0 draws a 16x16 sprite (instead of nothing).
+    pub wide_sprites: bool,
+    /// Default speed, in instructions per second.
+    pub speed: u32,
+    /// Default quirks.
+    pub quirks: Quirks,
+}
+
+pub const CHIP8: Platform = Platform {
+    name: "chip8",
+    title: "CHIP-8",
+    memory_size: 0x1000,
+    start: 0x200,
+    entry: None,
+    display_modes: &[(64, 32)],
+    planes: 1,
+    colors: false,
+    second_keypad: false,
+    decoder: &[instr::CHIP8],
+    font: FONT,
+    big_font: &[],
+    stack_size: 16,
+    wide_sprites: false,
+    speed: 5000,
+    quirks: quirks::DEFAULT,
+};
+
+pub const CHIP48: Platform = Platform {
+    name: "chip48",
+    title: "CHIP-48",
+    quirks: quirks::CHIP48,
+    ..CHIP8
+};
+
+pub const SCHIP: Platform = Platform {
+    name: "schip",
+    title: "SUPER-CHIP",
+    display_modes: &[(64, 32), (128, 64)],
+    decoder: &[instr::SCHIP, instr::CHIP8],
+    big_font: BIG_FONT,
+    wide_sprites: true,
+    quirks: quirks::CHIP48,
+    ..CHIP8
+};
+
+pub const XOCHIP: Platform = Platform {
+    name: "xochip",
+    title: "XO-CHIP",
+    memory_size: 0x10000,
+    display_modes: &[(64, 32), (128, 64)],
+    planes: 2,
+    decoder: &[instr::XOCHIP, instr::SCHIP, instr::CHIP8],
+    big_font: BIG_FONT,
+    wide_sprites: true,
+    speed: 60000,
+    quirks: quirks::XOCHIP,
+    ..CHIP8
+};
+
+pub const CHIP8X: Platform = Platform {
+    name: "chip8x",
+    title: "CHIP-8X",
+    start: 0x300,
+    colors: true,
+    second_keypad: true,
+    decoder: &[instr::CHIP8X, instr::CHIP8],
+    stack_size: 12,
+    speed: 1000,
+    quirks: quirks::VIP,
+    ..CHIP8
+};
+
+pub const CHIP8E: Platform = Platform {
+    name: "chip8e",
+    title: "CHIP-8E",
+    decoder: &[instr::CHIP8E, instr::CHIP8],
+    stack_size: 12,
+    speed: 1000,
+    quirks: quirks::VIP,
+    ..CHIP8
+};
+
+pub const CHIP8I: Platform = Platform {
+    name: "chip8i",
+    title: "CHIP-8I",
+    decoder: &[instr::CHIP8I, instr::CHIP8],
+    stack_size: 12,
+    speed: 1000,
+    quirks: quirks::VIP,
+    ..CHIP8
+};
+
+/// Two-page hires CHIP-8 for the VIP. ROMs begin with a jump (1260) into a
+/// patch for the interpreter, the program itself starts at 0x2C0.
+pub const HIRES: Platform = Platform {
+    name: "hires",
+    title: "Hires CHIP-8",
+    entry: Some(0x2C0),
+    display_modes: &[(64, 64)],
+    decoder: &[instr::HIRES, instr::CHIP8],
+    stack_size: 12,
+    speed: 1000,
+    quirks: quirks::VIP,
+    ..CHIP8
+};
+
+/// MegaChip starts out as SUPER-CHIP, and switches to its 256x192 color
+/// mode with MEGAON.
+pub const MEGACHIP: Platform = Platform {
+    name: "megachip",
+    title: "MegaChip",
+    memory_size: 0x100_0000,
+    display_modes: &[(64, 32), (128, 64)],
+    decoder: &[instr::MEGACHIP, instr::SCHIP, instr::CHIP8],
+    big_font: BIG_FONT,
+    wide_sprites: true,
+    speed: 60000,
+    quirks: quirks::CHIP48,
+    ..CHIP8
+};
+
+pub static PLATFORMS: &[&Platform] = &[
+    &CHIP8, &CHIP48, &SCHIP, &XOCHIP, &CHIP8X, &CHIP8E, &CHIP8I, &HIRES, &MEGACHIP,
+];
+
+impl Platform {
+    pub fn from_name(name: &str) -> Option<&'static Platform> {
+        PLATFORMS.iter().find(|platform| platform.name == name).cloned()
+    }
+
+    pub fn names() -> Vec<&'static str> {
+        PLATFORMS.iter().map(|platform| platform.name).collect()
+    }
+
+    /// Recognizes ROMs that need a specific platform, by the way they
+    /// start. Only hires CHIP-8 is detected for now.
+    pub fn detect(rom: &[u8]) -> Option<&'static Platform> {
+        if rom.starts_with(&[0x12, 0x60]) {
+            Some(&HIRES)
+        } else {
+            None
+        }
+    }
+
+    pub fn entry(&self) -> u16 {
+        self.entry.unwrap_or(self.start)
+    }
+
+    pub fn decode(&self, b: u16) -> Option<Instr> {
+        instr::decode(self.decoder, b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instr::Instr::*;
+
+    #[test]
+    fn test_platforms() {
+        assert_eq!(Platform::from_name("schip").unwrap().title, "SUPER-CHIP");
+        assert!(Platform::from_name("chip9").is_none());
+
+        assert_eq!(CHIP8.decode(0x00FF), Some(SYS(0x0FF)));
+        assert_eq!(SCHIP.decode(0x00FF), Some(HIGH));
+        assert_eq!(SCHIP.decode(0xF000), None);
+        assert_eq!(XOCHIP.decode(0xF000), Some(LD_I_LONG));
+        assert_eq!(XOCHIP.decode(0x1234), Some(JP(0x234)));
+        assert_eq!(CHIP8.decode(0xB123), Some(JP_V0(0x123)));
+        assert_eq!(CHIP8X.decode(0xB123), Some(COL(1, 2, 3)));
+        assert_eq!(CHIP8E.decode(0xBB12), Some(JP_BACK(0x12)));
+        assert_eq!(CHIP8I.decode(0xBB12), Some(JP_V0(0xB12)));
+        assert_eq!(HIRES.decode(0x0230), Some(CLS_HIRES));
+        assert_eq!(MEGACHIP.decode(0x0011), Some(MEGA_ON));
+        assert_eq!(MEGACHIP.decode(0x00FB), Some(SCR));
+
+        assert_eq!(Platform::detect(&[0x12, 0x60, 0x01, 0x7A]).unwrap().name, "hires");
+        assert!(Platform::detect(&[0x12, 0x62]).is_none());
+        assert_eq!(HIRES.entry(), 0x2C0);
+        assert_eq!(CHIP8X.entry(), 0x300);
+
+        for platform in PLATFORMS.iter() {
+            assert!(platform.entry() as usize + 2 <= platform.memory_size, "{}", platform.name);
+            assert!(!platform.display_modes.is_empty(), "{}", platform.name);
+            assert_eq!(platform.font.len(), 16 * 5, "{}", platform.name);
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use crate::Chip;
+use crate::hook::Hook;
+use crate::instr::Instr;
+use crate::platform::{self, Platform};
+
+// Number of entries shown in the "hot spots" part of the report.
+const HOT_SPOTS: usize = 20;
+
+/// Collects execution statistics: how many times each instruction was
+/// executed, how much time (in instructions) is spent in each subroutine,
+/// and how many sprites are drawn per frame.
+///
+/// Subroutines are tracked by following CALL and RET, so the profiler
+/// should be attached before the program starts.
+pub struct Profiler {
+    total: u64,
+    // Execution count and opcode for each address.
+    pcs: HashMap<u16, (u64, u16)>,
+    kinds: HashMap<&'static str, u64>,
+    // Current call stack (addresses of subroutines).
+    stack: Vec<u16>,
+    // Number of instructions executed with given call stack.
+    stacks: HashMap<Vec<u16>, u64>,
+    calls: HashMap<u16, u64>,
+    pending: Option<Instr>,
+    frame_draws: u64,
+    draws: Vec<u64>,
+    // Used for decoding the instructions in the report.
+    platform: &'static Platform,
+}
+
+impl Default for Profiler {
+    fn default() -> Profiler {
+        Profiler::new()
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler {
+            total: 0,
+            pcs: HashMap::new(),
+            kinds: HashMap::new(),
+            stack: vec![],
+            stacks: HashMap::new(),
+            calls: HashMap::new(),
+            pending: None,
+            frame_draws: 0,
+            draws: vec![],
+            platform: &platform::CHIP8,
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Number of times the instruction at `addr` was executed.
+    pub fn count_at(&self, addr: u16) -> u64 {
+        self.pcs.get(&addr).map_or(0, |&(count, _)| count)
+    }
+
+    /// Number of times given kind of instruction (e.g. `"DRW"`) was executed.
+    pub fn count_kind(&self, kind: &str) -> u64 {
+        self.kinds.get(kind).cloned().unwrap_or(0)
+    }
+
+    /// Number of sprites drawn in each (completed) frame.
+    pub fn draws_per_frame(&self) -> &[u64] {
+        &self.draws
+    }
+
+    /// Number of instructions executed in each subroutine, including
+    /// subroutines called by it. The main program has address `None`.
+    pub fn subroutine_times(&self) -> HashMap<Option<u16>, (u64, u64)> {
+        let mut times: HashMap<Option<u16>, (u64, u64)> = HashMap::new();
+        for (stack, &count) in self.stacks.iter() {
+            let main = times.entry(None).or_insert((0, 0));
+            main.0 += count;
+            if stack.is_empty() {
+                main.1 += count;
+            }
+
+            let mut seen = vec![];
+            for &addr in stack.iter() {
+                // Don't count recursive calls twice.
+                if !seen.contains(&addr) {
+                    times.entry(Some(addr)).or_insert((0, 0)).0 += count;
+                    seen.push(addr);
+                }
+            }
+            if let Some(&addr) = stack.last() {
+                times.entry(Some(addr)).or_insert((0, 0)).1 += count;
+            }
+        }
+        times
+    }
+
+    /// Writes a human-readable report.
+    pub fn write_report(&self, w: &mut dyn Write) -> io::Result<()> {
+        let percent = |count: u64| 100.0 * count as f64 / self.total.max(1) as f64;
+        let frames = self.draws.len() as u64;
+
+        writeln!(w, "Instructions executed: {}", self.total)?;
+        writeln!(w, "Frames: {}", frames)?;
+
+        writeln!(w)?;
+        writeln!(w, "Hot spots:")?;
+        let mut pcs: Vec<_> = self.pcs.iter().collect();
+        pcs.sort_by_key(|&(&addr, &(count, _))| (!count, addr));
+        for (&addr, &(count, b)) in pcs.iter().take(HOT_SPOTS) {
+            writeln!(w, "  {:04X}  {:10}  {:5.1}%  {}",
+                     addr, count, percent(count), disassemble(self.platform, b))?;
+        }
+
+        writeln!(w)?;
+        writeln!(w, "Instructions by kind:")?;
+        let mut kinds: Vec<_> = self.kinds.iter().collect();
+        kinds.sort_by_key(|&(&kind, &count)| (!count, kind));
+        for (kind, &count) in kinds {
+            writeln!(w, "  {:8}  {:10}  {:5.1}%", kind, count, percent(count))?;
+        }
+
+        writeln!(w)?;
+        writeln!(w, "Subroutines:          calls       total        self")?;
+        let mut times: Vec<_> = self.subroutine_times().into_iter().collect();
+        times.sort_by_key(|&(addr, (total, _))| (!total, addr));
+        for (addr, (total, own)) in times {
+            let (name, calls) = match addr {
+                Some(addr) => (format!("{:04X}", addr), self.calls.get(&addr).cloned().unwrap_or(0)),
+                None => ("main".to_string(), 1),
+            };
+            writeln!(w, "  {:4}  {:10}  {:10} {:5.1}%  {:10} {:5.1}%",
+                     name, calls, total, percent(total), own, percent(own))?;
+        }
+
+        writeln!(w)?;
+        if frames > 0 {
+            let total: u64 = self.draws.iter().sum();
+            writeln!(w, "Sprites drawn per frame: min {}, avg {:.1}, max {}",
+                     self.draws.iter().min().unwrap(),
+                     total as f64 / frames as f64,
+                     self.draws.iter().max().unwrap())?;
+        }
+
+        // Games often wait for the delay timer in a loop that reads it over
+        // and over; these show up here.
+        writeln!(w)?;
+        writeln!(w, "Timer reads (LD Vx, DT):         per frame")?;
+        let mut reads: Vec<_> = pcs.iter()
+            .filter(|&&(_, &(_, b))| matches!(self.platform.decode(b), Some(Instr::LD_R_DT(_))))
+            .collect();
+        reads.sort_by_key(|&&(&addr, &(count, _))| (!count, addr));
+        for &(&addr, &(count, _)) in reads {
+            writeln!(w, "  {:04X}  {:10}  {:10.1}", addr, count,
+                     count as f64 / frames.max(1) as f64)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the call stacks in "folded" format, as used by flamegraph
+    /// tools (e.g. `inferno-flamegraph` or `flamegraph.pl`).
+    pub fn write_folded(&self, w: &mut dyn Write) -> io::Result<()> {
+        let mut stacks: Vec<_> = self.stacks.iter().collect();
+        stacks.sort();
+        for (stack, count) in stacks {
+            write!(w, "main")?;
+            for addr in stack.iter() {
+                write!(w, ";sub_{:04X}", addr)?;
+            }
+            writeln!(w, " {}", count)?;
+        }
+        Ok(())
+    }
+}
+
+fn disassemble(platform: &Platform, b: u16) -> String {
+    match platform.decode(b) {
+        Some(instr) => instr.to_string(),
+        None => "????".to_string(),
+    }
+}
+
+impl Hook for Profiler {
+    fn before_step(&mut self, chip: &Chip, pc: u16, b: u16) {
+        self.total += 1;
+        self.pcs.entry(pc).or_insert((0, b)).0 += 1;
+
+        if let Some(count) = self.stacks.get_mut(&self.stack) {
+            *count += 1;
+        } else {
+            self.stacks.insert(self.stack.clone(), 1);
+        }
+
+        self.platform = chip.platform();
+        self.pending = self.platform.decode(b);
+        if let Some(instr) = self.pending {
+            *self.kinds.entry(instr.name()).or_insert(0) += 1;
+            if let Instr::DRW(..) = instr {
+                self.frame_draws += 1;
+            }
+        }
+    }
+
+    fn after_step(&mut self, _chip: &Chip, result: &Result<(), String>) {
+        if result.is_err() {
+            return;
+        }
+        match self.pending.take() {
+            Some(Instr::CALL(addr)) => {
+                self.stack.push(addr);
+                *self.calls.entry(addr).or_insert(0) += 1;
+            }
+            Some(Instr::RET) => {
+                self.stack.pop();
+            }
+            _ => (),
+        }
+    }
+
+    fn on_tick(&mut self, _chip: &Chip) {
+        self.draws.push(self.frame_draws);
+        self.frame_draws = 0;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instr::Instr::*;
+
+    #[test]
+    fn test_profile() {
+        let mut chip = Chip::new();
+        chip.memory.load_program(0x200, &[
+            CALL(0x206),
+            CALL(0x206),
+            JP(0xFFF),
+            // 0x206
+            DRW(0, 0, 1),
+            CALL(0x20C),
+            RET,
+            // 0x20C
+            RET,
+        ]);
+        chip.jump(0x200);
+
+        let mut profiler = Profiler::new();
+        while chip.pc() != 0xFFF {
+            chip.step_with(&mut profiler).unwrap();
+            if chip.pc() == 0x202 {
+                chip.tick_with(&mut profiler);
+            }
+        }
+
+        assert_eq!(profiler.total(), 11);
+        assert_eq!(profiler.count_at(0x206), 2);
+        assert_eq!(profiler.count_kind("CALL"), 4);
+        assert_eq!(profiler.draws_per_frame(), &[1]);
+
+        let times = profiler.subroutine_times();
+        assert_eq!(times[&None], (11, 3));
+        assert_eq!(times[&Some(0x206)], (8, 6));
+        assert_eq!(times[&Some(0x20C)], (2, 2));
+
+        let mut folded = vec![];
+        profiler.write_folded(&mut folded).unwrap();
+        assert_eq!(String::from_utf8(folded).unwrap(), "\
+main 3
+main;sub_0206 6
+main;sub_0206;sub_020C 2
+");
+    }
+
+    #[test]
+    fn test_report_platform() {
+        let mut chip = Chip::with_platform(&platform::SCHIP);
+        chip.memory.load_program(0x200, &[SCR, SCR, JP(0xFFF)]);
+        chip.jump(0x200);
+
+        let mut profiler = Profiler::new();
+        while chip.pc() != 0xFFF {
+            chip.step_with(&mut profiler).unwrap();
+        }
+        let mut report = vec![];
+        profiler.write_report(&mut report).unwrap();
+        let report = String::from_utf8(report).unwrap();
+        assert!(report.contains(&format!("0200           1   33.3%  {}", SCR)), "{}", report);
+    }
+}
@@ -0,0 +1,64 @@
+/// Behaviours that differ between CHIP-8 interpreters. Games written for one
+/// interpreter often rely on its particular behaviour, so this has to be
+/// configurable.
+///
+/// The default is the set of behaviours most modern interpreters agree on.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Quirks {
+    /// SHR/SHL shift VY and store the result in VX (COSMAC VIP), instead of
+    /// shifting VX in place.
+    pub shift_vy: bool,
+    /// Fx55/Fx65 leave I pointing past the last register stored or loaded.
+    pub load_store_increments_i: bool,
+    /// Bnnn jumps to nnn + VX, where X is the highest digit of nnn, instead
+    /// of nnn + V0.
+    pub jump_vx: bool,
+    /// AND, OR and XOR set VF to 0 (a side effect of how the COSMAC VIP
+    /// interpreter implemented them).
+    pub logic_resets_vf: bool,
+}
+
+pub const PRESETS: &[&str] = &["default", "vip", "chip48", "schip", "xochip"];
+
+pub const DEFAULT: Quirks = Quirks {
+    shift_vy: false,
+    load_store_increments_i: false,
+    jump_vx: false,
+    logic_resets_vf: false,
+};
+
+pub const VIP: Quirks = Quirks {
+    shift_vy: true,
+    load_store_increments_i: true,
+    jump_vx: false,
+    logic_resets_vf: true,
+};
+
+/// CHIP-48 and SUPER-CHIP.
+pub const CHIP48: Quirks = Quirks {
+    shift_vy: false,
+    load_store_increments_i: false,
+    jump_vx: true,
+    logic_resets_vf: false,
+};
+
+/// XO-CHIP (as implemented by Octo) went back to the COSMAC VIP behaviour,
+/// except for the VF reset.
+pub const XOCHIP: Quirks = Quirks {
+    shift_vy: true,
+    load_store_increments_i: true,
+    jump_vx: false,
+    logic_resets_vf: false,
+};
+
+impl Quirks {
+    pub fn preset(name: &str) -> Option<Quirks> {
+        match name {
+            "default" => Some(DEFAULT),
+            "vip" => Some(VIP),
+            "chip48" | "schip" => Some(CHIP48),
+            "xochip" => Some(XOCHIP),
+            _ => None,
+        }
+    }
+}
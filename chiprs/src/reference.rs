@@ -0,0 +1,321 @@
+//! A second, deliberately simple implementation of CHIP-8, used to test
+//! `Chip` against. Instructions are decoded using a table of opcode
+//! patterns, independently of `Instr`.
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::SmallRng;
+
+use crate::{Chip, V_SIZE, STACK_SIZE, KEYS_SIZE};
+use crate::display::{DISPLAY_W, DISPLAY_H};
+use crate::instr::Instr;
+use crate::memory::MEMORY_SIZE;
+use crate::quirks::{Quirks, PRESETS};
+
+#[derive(Debug, Clone, PartialEq)]
+struct State {
+    v: [u8; V_SIZE],
+    i: u16,
+    pc: u16,
+    sp: usize,
+    stack: Vec<u16>,
+    dt: u8,
+    st: u8,
+    memory: Vec<u8>,
+    pixels: Vec<u8>,
+}
+
+struct Reference {
+    state: State,
+    keys: [bool; KEYS_SIZE],
+    quirks: Quirks,
+}
+
+// Opcode fields.
+struct Args {
+    x: usize,
+    y: usize,
+    n: u8,
+    nn: u8,
+    nnn: u16,
+}
+
+type Exec = fn(&mut State, &Args, &Reference) -> Result<(), ()>;
+
+// Opcodes matching `pattern` after applying `mask`. All instructions except
+// RND (random) and LD Vx, K (waits for a key).
+const OPS: &[(u16, u16, Exec)] = &[
+    (0xFFFF, 0x00E0, |s, _, _| { s.pixels = vec![0; DISPLAY_W * DISPLAY_H]; Ok(()) }),
+    (0xFFFF, 0x00EE, |s, _, _| {
+        if s.sp == 0 {
+            return Err(());
+        }
+        s.sp -= 1;
+        s.pc = s.stack[s.sp];
+        Ok(())
+    }),
+    (0xF000, 0x0000, |_, _, _| Ok(())),
+    (0xF000, 0x1000, |s, a, _| { s.pc = a.nnn; Ok(()) }),
+    (0xF000, 0x2000, |s, a, _| {
+        if s.sp == STACK_SIZE {
+            return Err(());
+        }
+        s.stack[s.sp] = s.pc;
+        s.sp += 1;
+        s.pc = a.nnn;
+        Ok(())
+    }),
+    (0xF000, 0x3000, |s, a, _| { if s.v[a.x] == a.nn { s.pc += 2; } Ok(()) }),
+    (0xF000, 0x4000, |s, a, _| { if s.v[a.x] != a.nn { s.pc += 2; } Ok(()) }),
+    (0xF00F, 0x5000, |s, a, _| { if s.v[a.x] == s.v[a.y] { s.pc += 2; } Ok(()) }),
+    (0xF000, 0x6000, |s, a, _| { s.v[a.x] = a.nn; Ok(()) }),
+    (0xF000, 0x7000, |s, a, _| { s.v[a.x] = s.v[a.x].wrapping_add(a.nn); Ok(()) }),
+    (0xF00F, 0x8000, |s, a, _| { s.v[a.x] = s.v[a.y]; Ok(()) }),
+    // For arithmetic and logic, the flag is written last, so it wins when X
+    // is F.
+    (0xF00F, 0x8001, |s, a, r| { s.v[a.x] |= s.v[a.y]; logic_flag(s, r); Ok(()) }),
+    (0xF00F, 0x8002, |s, a, r| { s.v[a.x] &= s.v[a.y]; logic_flag(s, r); Ok(()) }),
+    (0xF00F, 0x8003, |s, a, r| { s.v[a.x] ^= s.v[a.y]; logic_flag(s, r); Ok(()) }),
+    (0xF00F, 0x8004, |s, a, _| {
+        let sum = s.v[a.x] as u16 + s.v[a.y] as u16;
+        s.v[a.x] = sum as u8;
+        s.v[0xF] = (sum > 0xFF) as u8;
+        Ok(())
+    }),
+    (0xF00F, 0x8005, |s, a, _| {
+        let (vx, vy) = (s.v[a.x], s.v[a.y]);
+        s.v[a.x] = vx.wrapping_sub(vy);
+        s.v[0xF] = (vx >= vy) as u8;
+        Ok(())
+    }),
+    (0xF00F, 0x8006, |s, a, r| {
+        let src = s.v[if r.quirks.shift_vy { a.y } else { a.x }];
+        s.v[a.x] = src >> 1;
+        s.v[0xF] = src & 1;
+        Ok(())
+    }),
+    (0xF00F, 0x8007, |s, a, _| {
+        let (vx, vy) = (s.v[a.x], s.v[a.y]);
+        s.v[a.x] = vy.wrapping_sub(vx);
+        s.v[0xF] = (vy >= vx) as u8;
+        Ok(())
+    }),
+    (0xF00F, 0x800E, |s, a, r| {
+        let src = s.v[if r.quirks.shift_vy { a.y } else { a.x }];
+        s.v[a.x] = src << 1;
+        s.v[0xF] = src >> 7;
+        Ok(())
+    }),
+    (0xF00F, 0x9000, |s, a, _| { if s.v[a.x] != s.v[a.y] { s.pc += 2; } Ok(()) }),
+    (0xF000, 0xA000, |s, a, _| { s.i = a.nnn; Ok(()) }),
+    (0xF000, 0xB000, |s, a, r| {
+        let x = if r.quirks.jump_vx { a.x } else { 0 };
+        let target = a.nnn as usize + s.v[x] as usize;
+        if target >= MEMORY_SIZE {
+            return Err(());
+        }
+        s.pc = target as u16;
+        Ok(())
+    }),
+    (0xF000, 0xD000, |s, a, _| {
+        let start = s.i as usize;
+        if start + a.n as usize > MEMORY_SIZE {
+            return Err(());
+        }
+        let mut collision = false;
+        for row in 0..a.n as usize {
+            let byte = s.memory[start + row];
+            for col in 0..8 {
+                if byte & (0x80 >> col) != 0 {
+                    let px = (s.v[a.x] as usize + col) % DISPLAY_W;
+                    let py = (s.v[a.y] as usize + row) % DISPLAY_H;
+                    let pixel = &mut s.pixels[py * DISPLAY_W + px];
+                    collision |= *pixel != 0;
+                    *pixel ^= 1;
+                }
+            }
+        }
+        s.v[0xF] = collision as u8;
+        Ok(())
+    }),
+    (0xF0FF, 0xE09E, |s, a, r| {
+        let key = *r.keys.get(s.v[a.x] as usize).ok_or(())?;
+        if key { s.pc += 2; }
+        Ok(())
+    }),
+    (0xF0FF, 0xE0A1, |s, a, r| {
+        let key = *r.keys.get(s.v[a.x] as usize).ok_or(())?;
+        if !key { s.pc += 2; }
+        Ok(())
+    }),
+    (0xF0FF, 0xF007, |s, a, _| { s.v[a.x] = s.dt; Ok(()) }),
+    (0xF0FF, 0xF015, |s, a, _| { s.dt = s.v[a.x]; Ok(()) }),
+    (0xF0FF, 0xF018, |s, a, _| { s.st = s.v[a.x]; Ok(()) }),
+    (0xF0FF, 0xF01E, |s, a, _| { s.i = s.i.wrapping_add(s.v[a.x] as u16); Ok(()) }),
+    (0xF0FF, 0xF029, |s, a, _| {
+        if s.v[a.x] > 0xF {
+            return Err(());
+        }
+        s.i = s.v[a.x] as u16 * 5;
+        Ok(())
+    }),
+    (0xF0FF, 0xF033, |s, a, _| {
+        let start = s.i as usize;
+        if start + 3 > MEMORY_SIZE {
+            return Err(());
+        }
+        let vx = s.v[a.x];
+        s.memory[start..start + 3].copy_from_slice(&[vx / 100, vx / 10 % 10, vx % 10]);
+        Ok(())
+    }),
+    (0xF0FF, 0xF055, |s, a, r| {
+        let start = s.i as usize;
+        if start + a.x + 1 > MEMORY_SIZE {
+            return Err(());
+        }
+        s.memory[start..=start + a.x].copy_from_slice(&s.v[..=a.x]);
+        if r.quirks.load_store_increments_i {
+            s.i = s.i.wrapping_add(a.x as u16 + 1);
+        }
+        Ok(())
+    }),
+    (0xF0FF, 0xF065, |s, a, r| {
+        let start = s.i as usize;
+        if start + a.x + 1 > MEMORY_SIZE {
+            return Err(());
+        }
+        s.v[..=a.x].copy_from_slice(&s.memory[start..=start + a.x]);
+        if r.quirks.load_store_increments_i {
+            s.i = s.i.wrapping_add(a.x as u16 + 1);
+        }
+        Ok(())
+    }),
+];
+
+fn logic_flag(s: &mut State, r: &Reference) {
+    if r.quirks.logic_resets_vf {
+        s.v[0xF] = 0;
+    }
+}
+
+impl Reference {
+    fn from_chip(chip: &Chip) -> Reference {
+        Reference {
+            state: state_of(chip),
+            keys: *chip.keys(),
+            quirks: chip.quirks,
+        }
+    }
+
+    fn step(&mut self) -> Result<(), ()> {
+        let pc = self.state.pc as usize;
+        if pc + 1 >= MEMORY_SIZE {
+            return Err(());
+        }
+        let b = ((self.state.memory[pc] as u16) << 8) | self.state.memory[pc + 1] as u16;
+        let &(_, _, exec) = OPS.iter().find(|&&(mask, pattern, _)| b & mask == pattern)
+            .ok_or(())?;
+        let args = Args {
+            x: ((b >> 8) & 0xF) as usize,
+            y: ((b >> 4) & 0xF) as usize,
+            n: (b & 0xF) as u8,
+            nn: (b & 0xFF) as u8,
+            nnn: b & 0xFFF,
+        };
+        let mut state = self.state.clone();
+        state.pc += 2;
+        exec(&mut state, &args, self)?;
+        self.state = state;
+        Ok(())
+    }
+}
+
+fn state_of(chip: &Chip) -> State {
+    State {
+        v: *chip.v(),
+        i: chip.i() as u16,
+        pc: chip.pc(),
+        sp: chip.sp() as usize,
+        stack: chip.stack().to_vec(),
+        dt: chip.dt(),
+        st: chip.st(),
+        memory: chip.memory.bytes.to_vec(),
+        pixels: chip.display.pixels.to_vec(),
+    }
+}
+
+// Whether the instruction can be compared. Invalid opcodes are, both
+// implementations should fail on them.
+fn covered(b: u16) -> bool {
+    !matches!(Instr::from(b), Some(Instr::RND(..)) | Some(Instr::LD_R_K(_)))
+}
+
+fn random_instr(rng: &mut SmallRng) -> u16 {
+    loop {
+        let b: u16 = rng.gen();
+        match Instr::from(b) {
+            Some(instr) if covered(b) => {
+                // Keep most jumps inside the program.
+                return match instr {
+                    Instr::JP(_) | Instr::CALL(_) | Instr::JP_V0(_) if rng.gen_range(0, 4) != 0 =>
+                        (b & 0xF000) | rng.gen_range(0x200, 0x280) & !1,
+                    _ => b,
+                };
+            }
+            _ => (),
+        }
+    }
+}
+
+#[test]
+fn test_differential() {
+    let mut rng = SmallRng::seed_from_u64(0);
+    for &preset in PRESETS {
+        for _ in 0..300 {
+            let mut chip = Chip::new();
+            chip.quirks = Quirks::preset(preset).unwrap();
+            chip.memory.load_font();
+            for addr in 0x280..MEMORY_SIZE {
+                chip.memory.bytes[addr] = rng.gen();
+            }
+            for n in 0..0x40 {
+                let b = random_instr(&mut rng);
+                chip.memory.bytes[0x200 + n * 2] = (b >> 8) as u8;
+                chip.memory.bytes[0x200 + n * 2 + 1] = b as u8;
+            }
+            for key in 0..KEYS_SIZE as u8 {
+                if rng.gen() {
+                    chip.key_down(key);
+                }
+            }
+            for x in 0..V_SIZE as u8 {
+                // Small values, so that they're sometimes valid keys and
+                // font characters.
+                chip.memory.bytes[0x280 + x as usize] = rng.gen_range(0, 0x20);
+            }
+            chip.memory.bytes[0x200] = 0xA2;
+            chip.memory.bytes[0x201] = 0x80;
+            chip.memory.bytes[0x202] = 0xFF;
+            chip.memory.bytes[0x203] = 0x65;
+            chip.jump(0x200);
+
+            let mut reference = Reference::from_chip(&chip);
+            for step in 0..200 {
+                let b = chip.memory.u16_at(chip.pc() as usize % (MEMORY_SIZE - 1));
+                if !covered(b) {
+                    break;
+                }
+                let before = state_of(&chip);
+                let result = chip.step();
+                let expected = reference.step();
+                assert_eq!(result.is_ok(), expected.is_ok(),
+                           "{}: step {}, opcode {:04X}: {:?}", preset, step, b, result);
+                if result.is_err() {
+                    break;
+                }
+                assert!(state_of(&chip) == reference.state,
+                        "{}: step {}, opcode {:04X}\nbefore: {:?}\nchip: {:?}\nreference: {:?}",
+                        preset, step, b, before.v, chip.v(), reference.state.v);
+            }
+        }
+    }
+}
@@ -0,0 +1,537 @@
+use crate::sha1::sha1_hex;
+
+/// What we know about a ROM.
+#[derive(Debug, PartialEq)]
+pub struct RomInfo {
+    /// SHA-1 of the ROM contents (lowercase hex).
+    pub sha1: &'static str,
+    pub title: &'static str,
+    pub author: Option<&'static str>,
+    /// Interpreter the ROM was written for: `chip8` (COSMAC VIP and
+    /// compatible) or `chip48` (HP48 calculators).
+    pub platform: &'static str,
+    /// Recommended quirks preset (see `Quirks::preset`).
+    pub quirks: &'static str,
+    /// Recommended speed, in instructions per second.
+    pub speed: Option<u32>,
+    /// Keymap, in `.c8k` format.
+    pub keymap: Option<&'static str>,
+    /// Controls, as lines of text. Key names refer to keys after applying
+    /// the keymap.
+    pub help: &'static [&'static str],
+}
+
+/// Looks up a ROM by its contents.
+pub fn lookup(rom: &[u8]) -> Option<&'static RomInfo> {
+    let sha1 = sha1_hex(rom);
+    ROMS.iter().find(|info| info.sha1 == sha1)
+}
+
+/// Known ROMs: everything in `games/`. Controls are taken from `GAMES.TXT`,
+/// and keymaps from the `.c8k` files.
+pub static ROMS: &[RomInfo] = &[
+    // 15PUZZLE.ch8
+    RomInfo {
+        sha1: "cf3a8c546038c63cd4cc1de8d171b9bf0d57c0ee",
+        title: "15 Puzzle",
+        author: Some("Roger Ivie"),
+        platform: "chip48",
+        quirks: "chip48",
+        speed: None,
+        keymap: None,
+        help: &["Press the key of the tile to move"],
+    },
+    // AIRPLANE.ch8
+    RomInfo {
+        sha1: "fca71182a8838b686573e69b22aff945d79fe1d0",
+        title: "Airplane",
+        author: None,
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: None,
+        help: &["8 = drop packet"],
+    },
+    // BLINKY.ch8
+    RomInfo {
+        sha1: "d40abc54374e4343639f993e897e00904ddf85d9",
+        title: "Blinky",
+        author: Some("Christian Egeberg"),
+        platform: "chip48",
+        quirks: "chip48",
+        speed: None,
+        keymap: Some("0122458469ABCDE5"),
+        help: &["2 4 6 8 = move, 5 = restart"],
+    },
+    // BLITZ.ch8
+    RomInfo {
+        sha1: "6f6509f38220e057a7e32ebb22dd353c1078e3e7",
+        title: "Blitz",
+        author: None,
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: None,
+        help: &["5 = drop bomb"],
+    },
+    // BREAKOUT.ch8
+    RomInfo {
+        sha1: "237756a4014fb3aa82a29246a7cdd534f8dc2dbb",
+        title: "Breakout",
+        author: Some("Paul Vervalin"),
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: None,
+        help: &["4 6 = move paddle"],
+    },
+    // BRIX.ch8
+    RomInfo {
+        sha1: "f13766c14aeb02ad8d4d103cb5eadd282d20cddc",
+        title: "Brix",
+        author: Some("Paul Vervalin"),
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: None,
+        help: &["4 6 = move paddle"],
+    },
+    // CAVE.ch8
+    RomInfo {
+        sha1: "5c82520906073287a3ef781746c67207ca084d93",
+        title: "Cave",
+        author: None,
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: Some("0123456789ABCDE5"),
+        help: &["5 = start, 2 4 6 8 = move"],
+    },
+    // CONNECT4.ch8
+    RomInfo {
+        sha1: "2d10c07b532f4fa7c07a07324ba26ca39fe484fd",
+        title: "Connect 4",
+        author: None,
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: None,
+        help: &["4 6 = select column, 5 = drop coin"],
+    },
+    // FIGURES.ch8
+    RomInfo {
+        sha1: "3b2bf5dc7ffb5f3fbe168e802079f79730535ca8",
+        title: "Figures",
+        author: None,
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: None,
+        help: &["4 6 = move"],
+    },
+    // FILTER.ch8
+    RomInfo {
+        sha1: "ae71a7b081a947f1760cdc147759803aea45e751",
+        title: "Filter",
+        author: None,
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: None,
+        help: &["4 6 = move"],
+    },
+    // GUESS.ch8
+    RomInfo {
+        sha1: "137cb8397456f53fcab216124458238bc18c0965",
+        title: "Guess",
+        author: None,
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: None,
+        help: &["Think of a number between 1 and 63", "5 = number is on the board, other key = it isn't"],
+    },
+    // HIDDEN.ch8
+    RomInfo {
+        sha1: "050f07a54371da79f924dd0227b89d07b4f2aed0",
+        title: "Hidden",
+        author: Some("David Winter"),
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: None,
+        help: &["2 4 6 8 = move, 5 = show card"],
+    },
+    // INVADERS.ch8
+    RomInfo {
+        sha1: "5c28a5f85289c9d859f95fd5eadbdcb1c30bb08b",
+        title: "Space Invaders",
+        author: None,
+        platform: "chip8",
+        quirks: "chip48",
+        speed: None,
+        keymap: None,
+        help: &["5 = start / shoot, 4 6 = move"],
+    },
+    // KALEID.ch8
+    RomInfo {
+        sha1: "d6fa9dc9005dc0496f39ba52fef56f9fd0a5a158",
+        title: "Kaleidoscope",
+        author: None,
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: Some("5123456789ABCDEF"),
+        help: &["2 4 6 8 = draw, 5 = repeat pattern"],
+    },
+    // LANDING.ch8
+    RomInfo {
+        sha1: "72fb3e0a4572bdb81f484df7948a8bc736fe78d0",
+        title: "Landing",
+        author: None,
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: Some("0123456785ABCDEF"),
+        help: &["5 = drop bomb"],
+    },
+    // MAZE.ch8
+    RomInfo {
+        sha1: "8b70080adbac44513ec60005734a816372b845ec",
+        title: "Maze",
+        author: None,
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: None,
+        help: &[],
+    },
+    // MERLIN.ch8
+    RomInfo {
+        sha1: "d979858bb9ffd07b48f52f92a8bcac0199f3623e",
+        title: "Merlin",
+        author: None,
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: Some("0123426869ABCDEF"),
+        help: &["4 2 = upper squares, 8 6 = lower squares"],
+    },
+    // MISSILE.ch8
+    RomInfo {
+        sha1: "0d0cc129dad3c45ba672f85fec71a668232212cc",
+        title: "Missile",
+        author: None,
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: None,
+        help: &["8 = shoot"],
+    },
+    // PADDLES.ch8
+    RomInfo {
+        sha1: "a18f1e3897416180b32e47ddc82cba9aca2c8d52",
+        title: "Paddles",
+        author: None,
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: None,
+        help: &["F = 1 player, E = 2 players", "7 9 = player 1, 4 6 = player 2"],
+    },
+    // PONG(1P).ch8
+    RomInfo {
+        sha1: "607c4f7f4e4dce9f99d96b3182bfe7e88bb090ee",
+        title: "Pong (1 player)",
+        author: None,
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: None,
+        help: &["1 4 = up / down"],
+    },
+    // PONG.ch8
+    RomInfo {
+        sha1: "b232ef880bd6060fb45fa6effed7edf0ae95670e",
+        title: "Pong",
+        author: Some("Paul Vervalin"),
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: None,
+        help: &["1 4 = player 1, C D = player 2"],
+    },
+    // PONG2.ch8
+    RomInfo {
+        sha1: "1830eb401ba8789a477dfcf294873a5479ebcfe8",
+        title: "Pong 2",
+        author: None,
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: None,
+        help: &["1 4 = player 1, C D = player 2"],
+    },
+    // PUZZLE.ch8
+    RomInfo {
+        sha1: "1293db0ccccbe7dd3fc5a09a2abc5d7b175e18e0",
+        title: "Puzzle",
+        author: None,
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: Some("0183456729ABCDEF"),
+        help: &["2 4 6 8 = move tile"],
+    },
+    // ROCKET.ch8
+    RomInfo {
+        sha1: "5e70f91ca08e9b9e9de61670492e3db2d7f7d57a",
+        title: "Rocket",
+        author: None,
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: Some("0123456789A5CDEF"),
+        help: &["5 = start, 4 6 = move"],
+    },
+    // SOCCER.ch8
+    RomInfo {
+        sha1: "6df358d77961a0bf21e98876f9f616791cba31e3",
+        title: "Soccer",
+        author: None,
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: None,
+        help: &["1 4 = player 1, C D = player 2"],
+    },
+    // SPACEF.ch8
+    RomInfo {
+        sha1: "aa4f1a282bd64a2364102abf5737a4205365a2b4",
+        title: "Space Flight",
+        author: None,
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: Some("0123456789ABCD65"),
+        help: &["5 = start, 6 = start level, 1 4 = up / down"],
+    },
+    // SQUASH.ch8
+    RomInfo {
+        sha1: "a58ec7cc63707f9e7274026de27c15ec1d9945bd",
+        title: "Squash",
+        author: None,
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: Some("0223656789ABCDEF"),
+        help: &["2 6 = up / down"],
+    },
+    // SYZYGY.ch8
+    RomInfo {
+        sha1: "1bdb4ddaa7049266fa3226851f28855a365cfd12",
+        title: "Syzygy",
+        author: None,
+        platform: "chip48",
+        quirks: "chip48",
+        speed: None,
+        keymap: Some("0122458469A5CD28"),
+        help: &["2 8 = with / without border", "2 4 6 8 = move, 5 = show score"],
+    },
+    // TANK.ch8
+    RomInfo {
+        sha1: "18b9d15f4c159e1f0ed58c2d8ec1d89325d3a3b6",
+        title: "Tank",
+        author: None,
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: Some("0183456729ABCDEF"),
+        help: &["2 4 6 8 = move"],
+    },
+    // TETRIS.ch8
+    RomInfo {
+        sha1: "5f518084744bf3cb8733f6e5454dfd1634320563",
+        title: "Tetris",
+        author: None,
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: Some("0823546789ABCDEF"),
+        help: &["5 = rotate, 4 6 = move, 8 = drop"],
+    },
+    // TICTAC.ch8
+    RomInfo {
+        sha1: "429d455a4bc53167942bf6fd934d72b0f648dce3",
+        title: "Tic-Tac-Toe",
+        author: None,
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: None,
+        help: &["1 - 9 = choose square"],
+    },
+    // TRON.ch8
+    RomInfo {
+        sha1: "a6a6cb2351c20b8f904da07c0ce91bd8161e9317",
+        title: "Tron",
+        author: None,
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: None,
+        help: &["B = with borders, F = without, 0 = start", "1 4 3 C = player 1, 7 A 9 E = player 2"],
+    },
+    // UFO.ch8
+    RomInfo {
+        sha1: "bdb92475acfe11bc7814a2f5eade13fcd09b756a",
+        title: "UFO",
+        author: None,
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: None,
+        help: &["4 5 6 = shoot left / up / right"],
+    },
+    // VBRIX.ch8
+    RomInfo {
+        sha1: "da710f631f8e35534d0b9170bcf892a60f49c43d",
+        title: "Vertical Brix",
+        author: Some("Paul Robson"),
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: Some("0223656589ABCDEF"),
+        help: &["5 = start, 2 6 = move"],
+    },
+    // VERS.ch8
+    RomInfo {
+        sha1: "ade839585ddeb0e3633177df03c1d91589e629eb",
+        title: "Vers",
+        author: None,
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: None,
+        help: &["Player 1: 7 A 1 2 = up / down / left / right", "Player 2: C D B F = up / down / left / right"],
+    },
+    // WALL.ch8
+    RomInfo {
+        sha1: "09ce01c54ddddda42ca5cd171f1ffcfd47355d12",
+        title: "Wall",
+        author: None,
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: Some("0223656789ABCDEF"),
+        help: &["2 6 = up / down"],
+    },
+    // WIPEOFF.ch8
+    RomInfo {
+        sha1: "d666688a8fce468a7d88b536bc1ef5f35ba12031",
+        title: "Wipe Off",
+        author: None,
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: None,
+        help: &["4 6 = move"],
+    },
+    // C8PIC.ch8
+    RomInfo {
+        sha1: "a82ca5c53e1dcedfab4f65efef02229145771b7d",
+        title: "CHIP-8 Picture",
+        author: None,
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: None,
+        help: &[],
+    },
+    // IBM.ch8
+    RomInfo {
+        sha1: "1ba58656810b67fd131eb9af3e3987863bf26c90",
+        title: "IBM Logo",
+        author: None,
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: None,
+        help: &[],
+    },
+    // Rocket2.ch8
+    RomInfo {
+        sha1: "e2005db6391f589534dd2d63a95b429338bd667c",
+        title: "Rocket 2",
+        author: None,
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: Some("0123456789ABCDE5"),
+        help: &["5 = start"],
+    },
+    // TAPEWORM.ch8
+    RomInfo {
+        sha1: "775e82a36c93f1b41b42eca94b55acbc4a48cebe",
+        title: "Tapeworm",
+        author: None,
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: Some("0123456789ABCDE5"),
+        help: &["5 = start, 2 4 6 8 = turn"],
+    },
+    // TIMEBOMB.ch8
+    RomInfo {
+        sha1: "67996195539c0ddcd98533a01dffeec6a53a6da1",
+        title: "Time Bomb",
+        author: None,
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: None,
+        help: &["2 8 = set time, 5 = start"],
+    },
+    // X-MIRROR.ch8
+    RomInfo {
+        sha1: "bc158d819890f16f105b8a316eeeefe4a0bad875",
+        title: "X-Mirror",
+        author: None,
+        platform: "chip8",
+        quirks: "default",
+        speed: None,
+        keymap: None,
+        help: &["2 4 6 8 = draw"],
+    },
+];
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirks::Quirks;
+
+    #[test]
+    fn test_lookup() {
+        let rom = std::fs::read("../games/TETRIS.ch8").unwrap();
+        let info = lookup(&rom).unwrap();
+        assert_eq!(info.title, "Tetris");
+        assert_eq!(info.keymap, Some("0823546789ABCDEF"));
+
+        assert_eq!(lookup(&rom[1..]), None);
+    }
+
+    #[test]
+    fn test_entries() {
+        for info in ROMS.iter() {
+            assert_eq!(info.sha1.len(), 40, "{}", info.title);
+            assert!(Quirks::preset(info.quirks).is_some(), "{}", info.title);
+            if let Some(keymap) = info.keymap {
+                assert_eq!(keymap.len(), 16, "{}", info.title);
+                assert!(keymap.chars().all(|c| c.is_ascii_hexdigit()), "{}", info.title);
+            }
+            assert_eq!(ROMS.iter().filter(|other| other.sha1 == info.sha1).count(), 1,
+                       "{}", info.title);
+        }
+    }
+}
@@ -0,0 +1,256 @@
+use std::collections::VecDeque;
+use std::io::{self, Write};
+
+use crate::{Chip, V_SIZE};
+use crate::hook::Hook;
+use crate::instr::{self, Instr};
+
+/// Decides which instructions get traced.
+#[derive(Debug, Clone, Default)]
+pub struct TraceFilter {
+    /// Only trace instructions in this address range (inclusive).
+    pub addr_range: Option<(u16, u16)>,
+    /// Only trace these kinds of instructions. A kind matches an instruction
+    /// if it's the instruction's name (`LD_R_B`), or the first part of it
+    /// (`LD` matches all the `LD_*` instructions). Empty means all kinds.
+    pub kinds: Vec<String>,
+}
+
+impl TraceFilter {
+    pub fn matches(&self, pc: u16, instr: Option<Instr>) -> bool {
+        if let Some((start, end)) = self.addr_range {
+            if pc < start || pc > end {
+                return false;
+            }
+        }
+        if self.kinds.is_empty() {
+            return true;
+        }
+        match instr {
+            Some(instr) => self.kinds.iter().any(|kind| kind_matches(kind, instr.name())),
+            None => false,
+        }
+    }
+
+    /// Parses a comma-separated list of kinds. Fails on kinds that don't
+    /// match any instruction.
+    pub fn parse_kinds(list: &str) -> Result<Vec<String>, String> {
+        let names = instr::names();
+        list.split(',')
+            .map(|kind| kind.trim().to_uppercase())
+            .map(|kind| {
+                if names.iter().any(|name| kind_matches(&kind, name)) {
+                    Ok(kind)
+                } else {
+                    Err(format!("unknown instruction kind {:?}, expected one of: {}",
+                                kind, names.join(", ")))
+                }
+            })
+            .collect()
+    }
+}
+
+fn kind_matches(kind: &str, name: &str) -> bool {
+    name == kind || (name.starts_with(kind) && name[kind.len()..].starts_with('_'))
+}
+
+struct Pending {
+    line: String,
+    v: [u8; V_SIZE],
+    i: u32,
+}
+
+/// Logs executed instructions, together with the register changes they
+/// caused, e.g.:
+///
+/// ```text
+/// 0202  A22A  LD I, #22A       I=022A
+/// 0204  600C  LD V0, #0C       V0=0C
+/// ```
+///
+/// In ring buffer mode, only the last instructions are kept in memory, and
+/// written out when an error occurs.
+pub struct Tracer<W: Write> {
+    out: W,
+    filter: TraceFilter,
+    ring: Option<(usize, VecDeque<String>)>,
+    pending: Option<Pending>,
+    // Address of the last instruction, traced or not.
+    pc: Option<u16>,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> Tracer<W> {
+    pub fn new(out: W, filter: TraceFilter) -> Tracer<W> {
+        Tracer {
+            out,
+            filter,
+            ring: None,
+            pending: None,
+            pc: None,
+            error: None,
+        }
+    }
+
+    /// Only keep last `size` lines, and write them out on error.
+    pub fn with_ring_buffer(mut self, size: usize) -> Tracer<W> {
+        self.ring = Some((size, VecDeque::with_capacity(size)));
+        self
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    fn write_line(&mut self, line: String) {
+        match self.ring {
+            Some((size, ref mut lines)) => {
+                if lines.len() == size {
+                    lines.pop_front();
+                }
+                lines.push_back(line);
+            }
+            None => self.write(&line),
+        }
+    }
+
+    fn write(&mut self, line: &str) {
+        if self.error.is_none() {
+            if let Err(e) = writeln!(self.out, "{}", line) {
+                self.error = Some(e);
+            }
+        }
+    }
+
+    fn dump_ring(&mut self) {
+        if let Some((_, ref mut lines)) = self.ring {
+            let lines: Vec<String> = lines.drain(..).collect();
+            for line in lines {
+                self.write(&line);
+            }
+        }
+    }
+}
+
+impl<W: Write> Hook for Tracer<W> {
+    fn before_step(&mut self, chip: &Chip, pc: u16, b: u16) {
+        let instr = chip.platform().decode(b);
+        self.pc = Some(pc);
+        self.pending = if self.filter.matches(pc, instr) {
+            let text = match instr {
+                Some(instr) => instr.to_string(),
+                None => "????".to_string(),
+            };
+            Some(Pending {
+                line: format!("{:04X}  {:04X}  {:<16}", pc, b, text),
+                v: *chip.v(),
+                i: chip.i(),
+            })
+        } else {
+            None
+        };
+    }
+
+    fn after_step(&mut self, chip: &Chip, result: &Result<(), String>) {
+        if let Some(pending) = self.pending.take() {
+            let mut line = pending.line;
+            for (x, (before, after)) in pending.v.iter().zip(chip.v().iter()).enumerate() {
+                if before != after {
+                    line += &format!(" V{:X}={:02X}", x, after);
+                }
+            }
+            if pending.i != chip.i() {
+                line += &format!(" I={:04X}", chip.i());
+            }
+            self.write_line(line.trim_end().to_string());
+        }
+
+        if let Err(e) = result {
+            self.dump_ring();
+            let pc = self.pc.take().unwrap_or_else(|| chip.pc());
+            self.write(&format!("error at {:04X}: {}", pc, e));
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        self.out.flush()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instr::Instr::*;
+
+    fn run(tracer: Tracer<Vec<u8>>, program: &[Instr]) -> String {
+        let mut tracer = tracer;
+        let mut chip = Chip::new();
+        chip.memory.load_program(0x200, program);
+        chip.jump(0x200);
+        while chip.pc() != 0xFFF && chip.step_with(&mut tracer).is_ok() {}
+        String::from_utf8(tracer.into_inner()).unwrap()
+    }
+
+    static PROGRAM: &[Instr] = &[
+        LD_R_B(0, 7),
+        LD_I_A(0x300),
+        ADD_I_R(0),
+        CALL(0x20A),
+        JP(0xFFF),
+        RET,
+        RET,
+    ];
+
+    #[test]
+    fn test_trace() {
+        let trace = run(Tracer::new(vec![], TraceFilter::default()), PROGRAM);
+        assert_eq!(trace, "\
+0200  6007  LD V0, #07       V0=07
+0202  A300  LD I, #300       I=0300
+0204  F01E  ADD I, V0        I=0307
+0206  220A  CALL #20A
+020A  00EE  RET
+0208  1FFF  JP #FFF
+");
+    }
+
+    #[test]
+    fn test_filter() {
+        let filter = TraceFilter {
+            addr_range: Some((0x202, 0x208)),
+            kinds: vec!["LD".to_string(), "CALL".to_string()],
+        };
+        let trace = run(Tracer::new(vec![], filter), PROGRAM);
+        assert_eq!(trace, "\
+0202  A300  LD I, #300       I=0300
+0206  220A  CALL #20A
+");
+
+        assert_eq!(TraceFilter::parse_kinds("ld, Call,LD_I_A"),
+                   Ok(vec!["LD".to_string(), "CALL".to_string(), "LD_I_A".to_string()]));
+        let err = TraceFilter::parse_kinds("DRW,DRAW").unwrap_err();
+        assert!(err.starts_with("unknown instruction kind \"DRAW\", expected one of: CLS, RET,"));
+        assert!(TraceFilter::parse_kinds("LD_I").is_ok());
+        assert!(TraceFilter::parse_kinds("LD_R_").is_err());
+    }
+
+    #[test]
+    fn test_ring_buffer() {
+        let tracer = Tracer::new(vec![], TraceFilter::default()).with_ring_buffer(2);
+        let trace = run(tracer, &[
+            LD_R_B(0, 1),
+            LD_R_B(1, 2),
+            LD_R_B(2, 3),
+            RET,
+        ]);
+        assert_eq!(trace, "\
+0204  6203  LD V2, #03       V2=03
+0206  00EE  RET
+error at 0206: stack underflow
+");
+    }
+}
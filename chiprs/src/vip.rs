@@ -0,0 +1,446 @@
+use crate::cdp1802::{Bus, Cpu};
+use crate::display::Display;
+use crate::host::{Beeper, FrameSink};
+use crate::{KEYS_SIZE, V_SIZE};
+
+/// RAM of a fully expanded VIP.
+pub const RAM_SIZE: usize = 0x1000;
+/// The monitor ROM, at 0x8000.
+pub const ROM_SIZE: usize = 0x200;
+
+// CDP1861 timing: a frame has 262 lines of 14 machine cycles, 128 of them
+// are displayed.
+const LINE_CYCLES: u32 = 14;
+const FRAME_LINES: u32 = 262;
+pub const FRAME_CYCLES: u32 = LINE_CYCLES * FRAME_LINES;
+pub const DISPLAY_W: usize = 64;
+pub const DISPLAY_LINES: usize = 128;
+const DISPLAY_START: u32 = 80;
+const DISPLAY_END: u32 = DISPLAY_START + DISPLAY_LINES as u32;
+// The interrupt is requested 2 lines before the display starts. EF1 is set
+// for 4 lines before the display starts, and 4 lines before it ends.
+const INT_START: u32 = DISPLAY_START - 2;
+const EFX_LINES: u32 = 4;
+// In each displayed line, DMA starts 2 cycles in and takes 8 cycles.
+const DMA_OFFSET: u32 = 2;
+const DMA_BYTES: usize = 8;
+
+// LDA R5: the interpreter fetching a CHIP-8 instruction (see `Vip::step`).
+const LDA_R5: u8 = 0x45;
+// How long `step` waits for the next instruction.
+const STEP_FRAMES: u32 = 10;
+
+/// Memory and I/O of the VIP, as seen by the CPU.
+struct VipBus {
+    ram: Vec<u8>,
+    rom: Vec<u8>,
+    // After reset, the ROM also appears at 0, until the first access with
+    // A15 set.
+    rom_low: bool,
+    keys: [bool; KEYS_SIZE],
+    // Key selected for EF3 (OUT 2).
+    key_latch: u8,
+    display_on: bool,
+    efx: bool,
+}
+
+impl VipBus {
+    fn rom_at(&self, addr: u16) -> u8 {
+        self.rom.get(addr as usize % ROM_SIZE).cloned().unwrap_or(0)
+    }
+}
+
+impl Bus for VipBus {
+    fn read(&mut self, addr: u16) -> u8 {
+        if addr & 0x8000 != 0 {
+            self.rom_low = false;
+            self.rom_at(addr)
+        } else if self.rom_low {
+            self.rom_at(addr)
+        } else {
+            self.ram[addr as usize % self.ram.len()]
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        if addr & 0x8000 != 0 {
+            self.rom_low = false;
+        } else {
+            let len = self.ram.len();
+            self.ram[addr as usize % len] = value;
+        }
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        match port {
+            1 => self.display_on = false,
+            2 => self.key_latch = value & 0xF,
+            _ => (),
+        }
+    }
+
+    fn input(&mut self, port: u8) -> u8 {
+        if port == 1 {
+            self.display_on = true;
+        }
+        0
+    }
+
+    fn ef(&self, line: u8) -> bool {
+        match line {
+            1 => self.efx,
+            3 => self.keys[self.key_latch as usize],
+            _ => false,
+        }
+    }
+}
+
+/// The COSMAC VIP: an RCA 1802 CPU, RAM, the monitor ROM, a CDP1861 video
+/// chip and a hex keypad. Runs the original CHIP-8 interpreter (loaded at
+/// 0 by the user) instead of emulating CHIP-8 instructions directly.
+///
+/// The screen is 64x128, as output by the 1861; CHIP-8 shows each of its
+/// 32 rows 4 times.
+pub struct Vip {
+    cpu: Cpu,
+    bus: VipBus,
+    pub display: Display,
+    // Position in the current frame, in machine cycles.
+    frame_cycle: u32,
+    frames: u64,
+    // Last line fetched by DMA.
+    dma_line: Option<u32>,
+    // Start of the interpreter's main loop, once found.
+    fetch_addr: Option<u16>,
+
+    /// Receive the screen at the end of every frame, and the state of Q.
+    pub frame_sink: Option<Box<dyn FrameSink>>,
+    pub beeper: Option<Box<dyn Beeper>>,
+    // Last state reported to the beeper.
+    beeping: bool,
+}
+
+impl Vip {
+    /// A VIP with the given monitor ROM, and empty RAM. Like the real
+    /// machine, it starts by running the monitor, which passes control to
+    /// the program in RAM unless C is held down.
+    pub fn new(monitor: &[u8]) -> Vip {
+        Vip {
+            cpu: Cpu::new(),
+            bus: VipBus {
+                ram: vec![0; RAM_SIZE],
+                rom: monitor.to_vec(),
+                rom_low: true,
+                keys: [false; KEYS_SIZE],
+                key_latch: 0,
+                display_on: false,
+                efx: false,
+            },
+            display: Display::with_size(DISPLAY_W, DISPLAY_LINES),
+            frame_cycle: 0,
+            frames: 0,
+            dma_line: None,
+            fetch_addr: None,
+            frame_sink: None,
+            beeper: None,
+            beeping: false,
+        }
+    }
+
+    /// Copies `data` to RAM (the interpreter goes at 0, CHIP-8 programs at
+    /// 0x200).
+    pub fn load(&mut self, addr: usize, data: &[u8]) -> Result<(), String> {
+        let end = addr + data.len();
+        if end > RAM_SIZE {
+            return Err(format!("{} bytes at {:04X} don't fit in RAM", data.len(), addr));
+        }
+        self.bus.ram[addr..end].copy_from_slice(data);
+        Ok(())
+    }
+
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    pub fn ram(&self) -> &[u8] {
+        &self.bus.ram
+    }
+
+    /// Number of frames displayed so far.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn key_down(&mut self, key: u8) {
+        self.bus.keys[key as usize] = true;
+    }
+
+    pub fn key_up(&mut self, key: u8) {
+        self.bus.keys[key as usize] = false;
+    }
+
+    /// The buzzer is driven by Q.
+    pub fn sound_on(&self) -> bool {
+        self.cpu.q
+    }
+
+    /// CHIP-8 registers, as kept by the interpreter: PC in R5, I in RA,
+    /// and V0-VF at xxF0 in the page R6 points to. Only meaningful between
+    /// instructions.
+    pub fn chip8_pc(&self) -> u16 {
+        self.cpu.r[5]
+    }
+
+    pub fn chip8_i(&self) -> u16 {
+        self.cpu.r[0xA]
+    }
+
+    pub fn chip8_v(&self) -> [u8; V_SIZE] {
+        let start = ((self.cpu.r[6] & 0xFF00) | 0xF0) as usize % RAM_SIZE;
+        let mut v = [0; V_SIZE];
+        v.copy_from_slice(&self.bus.ram[start..start + V_SIZE]);
+        v
+    }
+
+    /// Runs until the interpreter fetches the next CHIP-8 instruction, or
+    /// for a few frames if it's waiting (e.g. for a key).
+    ///
+    /// The interpreter reads instructions with LDA R5; the first place it
+    /// does that is taken to be the start of its main loop.
+    pub fn step(&mut self) -> Result<(), String> {
+        let mut executed = false;
+        let mut budget = STEP_FRAMES * FRAME_CYCLES;
+        while budget > 0 {
+            // This can start an interrupt, so check PC after it.
+            budget = budget.saturating_sub(self.update_video());
+            if !self.cpu.idle {
+                let pc = self.cpu.pc();
+                if self.fetch_addr.is_none() && self.peek(pc) == LDA_R5 {
+                    self.fetch_addr = Some(pc);
+                }
+                if executed && self.fetch_addr == Some(pc) {
+                    return Ok(());
+                }
+            }
+            let cycles = self.cpu.step(&mut self.bus);
+            self.advance(cycles);
+            budget = budget.saturating_sub(cycles);
+            executed = self.fetch_addr.is_some();
+        }
+        match self.fetch_addr {
+            Some(_) => Ok(()),
+            None => Err("the interpreter never fetched a CHIP-8 instruction".to_string()),
+        }
+    }
+
+    /// Runs until the end of the current frame.
+    pub fn run_frame(&mut self) {
+        let frames = self.frames;
+        while self.frames == frames {
+            self.run_instruction();
+        }
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        if addr & 0x8000 != 0 || self.bus.rom_low {
+            self.bus.rom_at(addr)
+        } else {
+            self.bus.ram[addr as usize % RAM_SIZE]
+        }
+    }
+
+    /// Lets the 1861 do its work, then runs one instruction.
+    fn run_instruction(&mut self) {
+        self.update_video();
+        let cycles = self.cpu.step(&mut self.bus);
+        self.advance(cycles);
+    }
+
+    fn advance(&mut self, cycles: u32) {
+        self.frame_cycle += cycles;
+        if self.frame_cycle >= FRAME_CYCLES {
+            self.frame_cycle -= FRAME_CYCLES;
+            self.frames += 1;
+            if !self.bus.display_on {
+                self.display.clear();
+            }
+            self.send_frame();
+        }
+        if self.cpu.q != self.beeping {
+            self.beeping = self.cpu.q;
+            if let Some(beeper) = self.beeper.as_mut() {
+                beeper.set(self.beeping);
+            }
+        }
+    }
+
+    /// Sends the screen to the frame sink (this also happens at the end of
+    /// every frame).
+    pub fn send_frame(&mut self) {
+        if let Some(sink) = self.frame_sink.as_mut() {
+            sink.frame(&self.display);
+        }
+    }
+
+    /// Interrupt, EF1 and DMA from the 1861, depending on the position in
+    /// the frame. These happen between instructions.
+    fn update_video(&mut self) -> u32 {
+        let line = self.frame_cycle / LINE_CYCLES;
+        let on = self.bus.display_on;
+        self.bus.efx = on &&
+            ((DISPLAY_START - EFX_LINES..DISPLAY_START).contains(&line) ||
+             (DISPLAY_END - EFX_LINES..DISPLAY_END).contains(&line));
+        if !on {
+            return 0;
+        }
+
+        let mut cycles = 0;
+        if (INT_START..DISPLAY_START).contains(&line) {
+            cycles = self.cpu.interrupt();
+            self.advance(cycles);
+        }
+        if (DISPLAY_START..DISPLAY_END).contains(&line) &&
+            self.frame_cycle % LINE_CYCLES >= DMA_OFFSET && self.dma_line != Some(line)
+        {
+            self.dma_line = Some(line);
+            let row = (line - DISPLAY_START) as usize * DISPLAY_W;
+            for i in 0..DMA_BYTES {
+                let byte = self.cpu.dma_out(&mut self.bus);
+                for j in 0..8 {
+                    self.display.pixels[row + i * 8 + j] = (byte >> (7 - j)) & 1;
+                }
+            }
+            self.advance(DMA_BYTES as u32);
+            cycles += DMA_BYTES as u32;
+        }
+        cycles
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+
+    struct Frames(Rc<RefCell<u32>>);
+
+    impl FrameSink for Frames {
+        fn frame(&mut self, _display: &Display) {
+            *self.0.borrow_mut() += 1;
+        }
+    }
+
+    struct Buzzer(Rc<RefCell<Vec<bool>>>);
+
+    impl Beeper for Buzzer {
+        fn set(&mut self, on: bool) {
+            self.0.borrow_mut().push(on);
+        }
+    }
+
+    // A stand-in for the monitor: jumps to the ROM proper, switches from R0
+    // to R3 as the program counter, sets up an interrupt routine that
+    // points R0 at 0x0F00, turns on the display and waits in a loop. The
+    // loop copies the state of key 5 to Q, and the interrupt counts frames
+    // in 0x0E00.
+    const MONITOR: &[u8] = &[
+        0xC0, 0x80, 0x03, // 8000: LBR 8003
+        0xF8, 0x80,       // 8003: LDI 80
+        0xB3,             // 8005: PHI 3
+        0xF8, 0x0A,       // 8006: LDI 0A
+        0xA3,             // 8008: PLO 3
+        0xD3,             // 8009: SEP 3
+        0xF8, 0x80,       // 800A: LDI 80
+        0xB1,             // 800C: PHI 1
+        0xF8, 0x40,       // 800D: LDI 40
+        0xA1,             // 800F: PLO 1
+        0xF8, 0x0E,       // 8010: LDI 0E
+        0xB2,             // 8012: PHI 2
+        0xF8, 0x10,       // 8013: LDI 10
+        0xA2,             // 8015: PLO 2
+        0xE2,             // 8016: SEX 2
+        0x69,             // 8017: INP 1
+        0xF8, 0x05,       // 8018: LDI 05
+        0x52,             // 801A: STR 2
+        0x62,             // 801B: OUT 2
+        0x22,             // 801C: DEC 2
+        0x36, 0x21,       // 801D: B3 21
+        0x7A,             // 801F: REQ
+        0x38,             // 8020: SKP
+        0x7B,             // 8021: SEQ
+        0x30, 0x18,       // 8022: BR 18
+    ];
+
+    const INTERRUPT: &[u8] = &[
+        0x72,             // 803E: LDXA
+        0x70,             // 803F: RET
+        0x22,             // 8040: DEC 2
+        0x78,             // 8041: SAV
+        0x22,             // 8042: DEC 2
+        0x52,             // 8043: STR 2
+        0xF8, 0x0F,       // 8044: LDI 0F
+        0xB0,             // 8046: PHI 0
+        0xF8, 0x00,       // 8047: LDI 00
+        0xA0,             // 8049: PLO 0
+        0xF8, 0x0E,       // 804A: LDI 0E
+        0xB4,             // 804C: PHI 4
+        0xF8, 0x00,       // 804D: LDI 00
+        0xA4,             // 804F: PLO 4
+        0x04,             // 8050: LDN 4
+        0xFC, 0x01,       // 8051: ADI 01
+        0x54,             // 8053: STR 4
+        0x30, 0x3E,       // 8054: BR 3E
+    ];
+
+    fn monitor() -> Vec<u8> {
+        let mut rom = vec![0; ROM_SIZE];
+        rom[..MONITOR.len()].copy_from_slice(MONITOR);
+        rom[0x3E..0x3E + INTERRUPT.len()].copy_from_slice(INTERRUPT);
+        rom
+    }
+
+    #[test]
+    fn test_video() {
+        let frames = Rc::new(RefCell::new(0));
+        let beeps = Rc::new(RefCell::new(vec![]));
+        let mut vip = Vip::new(&monitor());
+        vip.frame_sink = Some(Box::new(Frames(frames.clone())));
+        vip.beeper = Some(Box::new(Buzzer(beeps.clone())));
+        let mut screen = vec![0; 0x100];
+        screen[0] = 0xF0;
+        screen[0x08] = 0x81;
+        screen[0xFF] = 0x01;
+        vip.load(0xF00, &screen).unwrap();
+
+        for _ in 0..3 {
+            vip.run_frame();
+        }
+        assert_eq!(vip.ram()[0xE00], 3);
+        // DMA goes through 0x0F00-0x12FF, the rest wraps around to 0.
+        assert!(vip.display.at(0, 0));
+        assert!(vip.display.at(3, 0));
+        assert!(!vip.display.at(4, 0));
+        assert!(vip.display.at(0, 1));
+        assert!(vip.display.at(7, 1));
+        assert!(vip.display.at(63, 31));
+        assert!(!vip.display.at(63, 32));
+
+        assert!(!vip.sound_on());
+        vip.key_down(5);
+        vip.run_frame();
+        assert!(vip.sound_on());
+        vip.key_up(5);
+        vip.run_frame();
+        assert!(!vip.sound_on());
+        assert_eq!(*frames.borrow(), 5);
+        assert_eq!(*beeps.borrow(), vec![true, false]);
+    }
+
+    #[test]
+    fn test_no_interpreter() {
+        let mut vip = Vip::new(&monitor());
+        assert!(vip.step().is_err());
+    }
+}